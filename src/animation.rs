@@ -0,0 +1,220 @@
+use anyhow::anyhow;
+use image_convert::{
+    magick_rust::{bindings, MagickWand},
+    MagickError,
+};
+
+// a frame lasting this much longer or shorter only differs because of the precision of the delays
+// of a format, such as the hundredths of a second of GIF
+const DURATION_TOLERANCE_MS: u64 = 10;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Frame {
+    delay:            usize,
+    ticks_per_second: isize,
+    dispose:          bindings::DisposeType,
+}
+
+impl Frame {
+    #[inline]
+    fn duration_ms(&self) -> u64 {
+        self.delay as u64 * 1000 / self.ticks_per_second.max(1) as u64
+    }
+}
+
+// the delays and disposal methods of the frames and the loop count of an animation, which are
+// read before its frames are coalesced and set again on the resized frames
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Timing {
+    frames:     Vec<Frame>,
+    iterations: usize,
+}
+
+impl Timing {
+    pub fn of(mw: &mut MagickWand) -> Result<Timing, MagickError> {
+        let count = unsafe { bindings::MagickGetNumberImages(mw.wand) };
+
+        let mut frames = Vec::with_capacity(count);
+        let mut iterations = 0;
+
+        for index in 0..count {
+            mw.set_iterator_index(index as isize)?;
+
+            if index == 0 {
+                iterations = mw.get_image_iterations();
+            }
+
+            frames.push(Frame {
+                delay:            mw.get_image_delay(),
+                ticks_per_second: unsafe { bindings::MagickGetImageTicksPerSecond(mw.wand) },
+                dispose:          mw.get_image_dispose(),
+            });
+        }
+
+        Ok(Timing {
+            frames,
+            iterations,
+        })
+    }
+
+    pub fn apply(&self, index: usize, frame: &mut MagickWand) -> Result<(), MagickError> {
+        let timing = match self.frames.get(index) {
+            Some(timing) => timing,
+            None => return Ok(()),
+        };
+
+        frame.set_image_delay(timing.delay)?;
+        frame.set_image_dispose(timing.dispose)?;
+        frame.set_image_iterations(self.iterations)?;
+
+        match unsafe { bindings::MagickSetImageTicksPerSecond(frame.wand, timing.ticks_per_second) }
+        {
+            bindings::MagickBooleanType_MagickTrue => Ok(()),
+            _ => Err(MagickError("failed to set the ticks per second")),
+        }
+    }
+
+    // the encoded animation is read again, so that what its players will see is compared
+    pub fn verify(&self, data: &[u8]) -> anyhow::Result<()> {
+        let mut mw = MagickWand::new();
+
+        mw.read_image_blob(data)?;
+
+        self.compare(&Timing::of(&mut mw)?)
+    }
+
+    fn compare(&self, output: &Timing) -> anyhow::Result<()> {
+        if output.frames.len() != self.frames.len() {
+            return Err(anyhow!(
+                "The animation has {} frames instead of {}.",
+                output.frames.len(),
+                self.frames.len()
+            ));
+        }
+
+        for (index, (input, output)) in self.frames.iter().zip(output.frames.iter()).enumerate() {
+            let (expected, actual) = (input.duration_ms(), output.duration_ms());
+
+            if expected.abs_diff(actual) > DURATION_TOLERANCE_MS {
+                return Err(anyhow!(
+                    "Frame {index} of the animation lasts {actual} ms instead of {expected} ms."
+                ));
+            }
+        }
+
+        if output.iterations != self.iterations {
+            return Err(anyhow!(
+                "The animation loops {} times instead of {} (0 is forever).",
+                output.iterations,
+                self.iterations
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image_convert::magick_rust::PixelWand;
+
+    use super::*;
+
+    fn in_ms(delays: &[usize], iterations: usize) -> Timing {
+        Timing {
+            frames: delays
+                .iter()
+                .map(|&delay| Frame {
+                    delay,
+                    ticks_per_second: 1000,
+                    dispose: bindings::DisposeType_NoneDispose,
+                })
+                .collect(),
+            iterations,
+        }
+    }
+
+    #[test]
+    fn compare_frame_count() {
+        let error = in_ms(&[100, 100], 0).compare(&in_ms(&[100], 0)).unwrap_err();
+
+        assert_eq!("The animation has 1 frames instead of 2.", error.to_string());
+    }
+
+    #[test]
+    fn compare_iterations() {
+        let error = in_ms(&[100], 0).compare(&in_ms(&[100], 1)).unwrap_err();
+
+        assert_eq!("The animation loops 1 times instead of 0 (0 is forever).", error.to_string());
+    }
+
+    #[test]
+    fn compare_delays_within_tolerance() {
+        let expected = in_ms(&[100, 100], 0);
+
+        assert!(expected.compare(&in_ms(&[110, 90], 0)).is_ok());
+    }
+
+    #[test]
+    fn compare_delays_beyond_tolerance() {
+        let expected = in_ms(&[100, 100], 0);
+
+        let error = expected.compare(&in_ms(&[100, 111], 0)).unwrap_err();
+
+        assert_eq!("Frame 1 of the animation lasts 111 ms instead of 100 ms.", error.to_string());
+
+        assert!(expected.compare(&in_ms(&[89, 100], 0)).is_err());
+    }
+
+    #[test]
+    fn compare_delays_in_other_ticks() {
+        let mut output = in_ms(&[10], 0);
+
+        output.frames[0].ticks_per_second = 100;
+
+        assert!(in_ms(&[100], 0).compare(&output).is_ok());
+    }
+
+    #[test]
+    fn apply_round_trip_gif() {
+        let timing = Timing {
+            frames:     vec![
+                Frame {
+                    delay:            7,
+                    ticks_per_second: 100,
+                    dispose:          bindings::DisposeType_BackgroundDispose,
+                },
+                Frame {
+                    delay:            25,
+                    ticks_per_second: 100,
+                    dispose:          bindings::DisposeType_PreviousDispose,
+                },
+            ],
+            iterations: 3,
+        };
+
+        let mut animation = MagickWand::new();
+
+        for (index, color) in ["red", "blue"].into_iter().enumerate() {
+            let mut pixel = PixelWand::new();
+            pixel.set_color(color).unwrap();
+
+            let mut frame = MagickWand::new();
+
+            frame.new_image(4, 4, &pixel).unwrap();
+            timing.apply(index, &mut frame).unwrap();
+
+            animation.add_image(&frame).unwrap();
+        }
+
+        let data = animation.write_images_blob("GIF").unwrap();
+
+        timing.verify(&data).unwrap();
+
+        let mut output = MagickWand::new();
+
+        output.read_image_blob(&data).unwrap();
+
+        assert_eq!(timing, Timing::of(&mut output).unwrap());
+    }
+}
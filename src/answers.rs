@@ -0,0 +1,81 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{cli::CLIArgs, console};
+
+// the answers to the overwrite prompt given in advance, which are kept behind the same lock as the
+// prompt so that they are taken in the order the outputs are asked about
+#[derive(Debug, Default)]
+pub struct Answers {
+    sequence: VecDeque<bool>,
+    // keyed by the absolute output paths
+    by_path:  HashMap<PathBuf, bool>,
+    // the answer to the rest of the prompts, given by --yes or --skip-existing
+    fallback: Option<bool>,
+}
+
+impl Answers {
+    pub fn new(args: &CLIArgs) -> anyhow::Result<Answers> {
+        let mut answers = Answers {
+            sequence: args.assume_answers.iter().copied().collect(),
+            by_path:  HashMap::new(),
+            fallback: if args.yes {
+                Some(true)
+            } else if args.skip_existing {
+                Some(false)
+            } else {
+                None
+            },
+        };
+
+        if let Some(answers_file) = args.answers_file.as_deref() {
+            answers.read(answers_file).with_context(|| anyhow!("{answers_file:?}"))?;
+        }
+
+        Ok(answers)
+    }
+
+    // one answer and one output path per line, such as `y out/image.jpg`, where the paths are
+    // relative to the current directory and lines starting with `#` are comments
+    fn read(&mut self, path: &Path) -> anyhow::Result<()> {
+        let content = fs::read_to_string(path)?;
+
+        let current_dir = env::current_dir()?;
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (answer, output_path) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("{line:?} is not like `y path/to/output`"))?;
+
+            let answer = console::parse_answer(answer)
+                .ok_or_else(|| anyhow!("{answer:?} is neither yes nor no"))?;
+
+            self.by_path.insert(current_dir.join(output_path.trim()), answer);
+        }
+
+        Ok(())
+    }
+
+    // the answer for an output in the answers file, or else the next one of --assume-answers, or
+    // else the one of --yes or --skip-existing
+    pub fn take(&mut self, output_path: &Path) -> Option<bool> {
+        if !self.by_path.is_empty() {
+            if let Ok(current_dir) = env::current_dir() {
+                if let Some(answer) = self.by_path.get(&current_dir.join(output_path)) {
+                    return Some(*answer);
+                }
+            }
+        }
+
+        self.sequence.pop_front().or(self.fallback)
+    }
+}
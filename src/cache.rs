@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{cli::CLIArgs, processing};
+
+const CACHE_FILE_NAME: &str = "cache.json";
+const CACHE_VERSION: u32 = 1;
+
+// the options which do not change the outputs, so changing them keeps the cache
+const RUN_OPTIONS: [&str; 45] = [
+    "input_path",
+    "single_thread",
+    "verbose",
+    "quiet",
+    "force",
+    "assume_answers",
+    "answers_file",
+    "yes",
+    "skip_existing",
+    "newer_only",
+    "failed_list",
+    "retry_failed",
+    "state",
+    "resume",
+    "notify_url",
+    "notify_command",
+    "progress",
+    "progress_bar",
+    "json_stream",
+    "files_from0",
+    "files_from",
+    "print0",
+    "stdin",
+    "stdout",
+    "coordinator",
+    "worker",
+    "worker_jobs",
+    "memory_limit",
+    "ordered_output",
+    "preflight",
+    "identify_first",
+    "report",
+    "report_file",
+    "placeholders",
+    "quarantine_dir",
+    "skip_list",
+    "max_depth",
+    "sniff",
+    "follow_symlinks",
+    "symlinked_files",
+    "priority_glob",
+    "status_file",
+    "timings",
+    "cache",
+    "chmod_writable",
+];
+
+// kept in memory during a run, and written into the cache directory once the run has finished
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    version:  u32,
+    // keyed by the canonical paths of the inputs
+    files:    BTreeMap<PathBuf, Entry>,
+    #[serde(skip)]
+    settings: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    hash:     String,
+    settings: String,
+    outputs:  Vec<PathBuf>,
+}
+
+// an input which has been hashed, to be looked up before it is resized and recorded after that
+#[derive(Debug)]
+pub struct Key {
+    path: PathBuf,
+    hash: String,
+}
+
+impl Key {
+    pub fn new(input_path: &Path) -> io::Result<Key> {
+        Ok(Key {
+            path: input_path.canonicalize()?,
+            hash: format!("{:016x}", fnv1a(&fs::read(input_path)?)),
+        })
+    }
+}
+
+// FNV-1a, whose values stay the same between builds, unlike the ones of `DefaultHasher`
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0xCBF29CE484222325, |hash, b| (hash ^ u64::from(*b)).wrapping_mul(0x100000001B3))
+}
+
+fn settings(args: &CLIArgs) -> anyhow::Result<String> {
+    let mut options = match serde_json::to_value(args)? {
+        Value::Object(options) => options,
+        _ => unreachable!(),
+    };
+
+    for key in RUN_OPTIONS {
+        options.remove(key);
+    }
+
+    Ok(format!("{:016x}", fnv1a(serde_json::to_string(&options)?.as_bytes())))
+}
+
+pub fn open(args: &CLIArgs) -> anyhow::Result<()> {
+    let cache_dir = match args.cache.as_deref() {
+        Some(cache_dir) => cache_dir,
+        None => return Ok(()),
+    };
+
+    let cache_path = cache_dir.join(CACHE_FILE_NAME);
+
+    let mut cache = match fs::read(&cache_path) {
+        Ok(data) => {
+            let cache: Cache =
+                serde_json::from_slice(&data).with_context(|| anyhow!("{cache_path:?}"))?;
+
+            // a cache of another version is started over instead of failing the run
+            if cache.version == CACHE_VERSION {
+                cache
+            } else {
+                Cache::default()
+            }
+        },
+        Err(error) if error.kind() == ErrorKind::NotFound => Cache::default(),
+        Err(error) => return Err(error).with_context(|| anyhow!("{cache_path:?}")),
+    };
+
+    cache.version = CACHE_VERSION;
+    cache.settings = settings(args)?;
+
+    *CACHE.lock().unwrap() = Some(cache);
+
+    Ok(())
+}
+
+// whether the input has been resized with the same content and options, and its outputs are still
+// there
+pub fn contains(key: &Key) -> bool {
+    let cache = CACHE.lock().unwrap();
+
+    match cache.as_ref().and_then(|cache| cache.files.get(&key.path).map(|entry| (cache, entry))) {
+        Some((cache, entry)) => {
+            entry.hash == key.hash
+                && entry.settings == cache.settings
+                && entry.outputs.iter().all(|output| output.exists())
+        },
+        None => false,
+    }
+}
+
+pub fn insert(key: Key, outputs: Vec<PathBuf>) -> io::Result<()> {
+    // an image resized in place is cached with the content it has been resized into
+    let key = if outputs.iter().any(|output| output.canonicalize().ok().as_ref() == Some(&key.path))
+    {
+        Key::new(&key.path)?
+    } else {
+        key
+    };
+
+    let mut cache = CACHE.lock().unwrap();
+
+    if let Some(cache) = cache.as_mut() {
+        let settings = cache.settings.clone();
+
+        cache.files.insert(key.path, Entry {
+            hash: key.hash,
+            settings,
+            outputs,
+        });
+    }
+
+    Ok(())
+}
+
+pub fn save(args: &CLIArgs) -> anyhow::Result<()> {
+    let cache_dir = match args.cache.as_deref() {
+        Some(cache_dir) => cache_dir,
+        None => return Ok(()),
+    };
+
+    let cache = CACHE.lock().unwrap();
+
+    if let Some(cache) = cache.as_ref() {
+        processing::create_dir_all(cache_dir, args.output_dir_mode)
+            .with_context(|| anyhow!("{cache_dir:?}"))?;
+
+        let cache_path = cache_dir.join(CACHE_FILE_NAME);
+
+        let mut data = serde_json::to_vec_pretty(cache)?;
+
+        data.push(b'\n');
+
+        processing::write_atomically(&cache_path, &data, None)
+            .with_context(|| anyhow!("{cache_path:?}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn run_options_are_options() {
+        let args = CLIArgs::try_parse_from(["image-resizer", "-", "-m", "1920"]).unwrap();
+
+        let options = match serde_json::to_value(&args).unwrap() {
+            Value::Object(options) => options,
+            _ => unreachable!(),
+        };
+
+        // a renamed or removed option would otherwise be hashed into the settings silently
+        for key in RUN_OPTIONS {
+            assert!(options.contains_key(key), "{key} is not an option");
+        }
+    }
+
+    #[test]
+    fn insert_in_place() {
+        let dir = env::temp_dir().join(format!("image-resizer-{}-cache", process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let input_path = dir.join("input.jpg");
+        let other_path = dir.join("other.jpg");
+        let output_path = dir.join("output.jpg");
+
+        fs::write(&input_path, b"input").unwrap();
+        fs::write(&other_path, b"other").unwrap();
+        fs::write(&output_path, b"output").unwrap();
+
+        *CACHE.lock().unwrap() = Some(Cache {
+            version:  CACHE_VERSION,
+            files:    BTreeMap::new(),
+            settings: String::from("settings"),
+        });
+
+        // resized in place, so the input is hashed again after it has been overwritten
+        let key = Key::new(&input_path).unwrap();
+
+        fs::write(&input_path, b"resized").unwrap();
+
+        insert(key, vec![input_path.clone()]).unwrap();
+
+        assert!(contains(&Key::new(&input_path).unwrap()));
+
+        // resized into another file, so the input keeps its hash
+        let key = Key::new(&other_path).unwrap();
+
+        insert(key, vec![output_path.clone()]).unwrap();
+
+        assert!(contains(&Key::new(&other_path).unwrap()));
+
+        fs::write(&other_path, b"changed").unwrap();
+
+        assert!(!contains(&Key::new(&other_path).unwrap()));
+
+        // an output which has been removed makes the input resized again
+        fs::write(&other_path, b"other").unwrap();
+        fs::remove_file(&output_path).unwrap();
+
+        assert!(!contains(&Key::new(&other_path).unwrap()));
+
+        // other settings
+        CACHE.lock().unwrap().as_mut().unwrap().settings = String::from("others");
+
+        assert!(!contains(&Key::new(&input_path).unwrap()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,33 +1,92 @@
-use std::path::PathBuf;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use clap::{CommandFactory, FromArgMatches, Parser};
+use anyhow::{anyhow, Context};
+use clap::{
+    builder::ArgPredicate, parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser,
+    ValueEnum,
+};
 use concat_with::concat_line;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use terminal_size::terminal_size;
 
+use crate::rules::Rule;
+
 const APP_NAME: &str = "Image Resizer";
 const CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 const CARGO_PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 
+const OPTIONS_SCHEMA_VERSION: u32 = 1;
+
+// the options which --side-maximum is required without
+const SIZE_OPTIONS: [&str; 11] = [
+    "side_maximum",
+    "max_width",
+    "max_height",
+    "crop",
+    "pad",
+    "scale",
+    "max_megapixels",
+    "side_minimum",
+    "sizes",
+    "preset",
+    "destinations",
+];
+
 const AFTER_HELP: &str = "Enjoy it! https://magiclen.org";
 
 const APP_ABOUT: &str = concat!(
     "It helps you interlace an image or multiple images for web-page usage.\n\nEXAMPLES:\n",
     concat_line!(prefix "image-resizer ",
         "/path/to/image -m 1920                           # Make /path/to/image resized",
+        "/path/to/image --max-width 1920 --max-height 600 # Make /path/to/image resized to fit inside 1920x600",
+        "/path/to/folder --crop 400x300 --gravity top     # Make images inside /path/to/folder resized and cropped to exactly 400x300, keeping their tops",
+        "/path/to/folder --pad 1000x1000                  # Make images inside /path/to/folder resized to fit inside 1000x1000 and padded with white to exactly that size",
+        "/path/to/folder --scale 50%                      # Make images inside /path/to/folder resized to half of their widths and heights",
+        "/path/to/folder --max-megapixels 12              # Make images inside /path/to/folder having more than 12 megapixels shrunk to 12 megapixels",
         "/path/to/folder -m 1920                          # Make images inside /path/to/folder and make resized",
+        "/path/to/folder -m 1920 --priority-glob 'cover*' # Make images inside /path/to/folder resized, starting with the ones whose names start with cover",
         "/path/to/image -o /path/to/image2 -m 1920        # Make /path/to/image resized, and save it to /path/to/image2",
         "/path/to/folder -o /path/to/folder2 -m 1920      # Make images inside /path/to/folder resized, and save them to /path/to/folder2",
         "/path/to/folder -o /path/to/folder2 -f -m 1920   # Make images inside /path/to/folder resized, and save them to /path/to/folder2 without overwriting checks",
         "/path/to/folder --allow-gif -r -m 1920           # Make images inside /path/to/folder including GIF resized and also remain their profiles",
         "/path/to/image -m 1920 --shrink                  # Make /path/to/image shrunk if it needs to be",
+        "/path/to/folder --side-minimum 1024              # Make images inside /path/to/folder having long sides shorter than 1024 pixels enlarged to 1024 pixels",
+        "/path/to/folder --preset thumbnail               # Make images inside /path/to/folder resized to at most 320 pixels with a quality of 75, stripping their profiles",
+        "/path/to/folder -m 1920 --keep-metadata icc      # Make images inside /path/to/folder resized, keeping their ICC profiles but stripping the other metadata",
         "/path/to/image -m 1920 -q 75                     # Make /path/to/image resized with a quality of 75 if it uses lossy compression",
+        "/path/to/image.jpg -m 1920 --target-size 500KB   # Make /path/to/image.jpg resized with the highest quality keeping it within 500 KB",
         "/path/to/image -m 1920 --4:2:0                   # Make /path/to/image resized and output using 4:2:0 (chroma quartered) subsampling to reduce the file size",
-        "/path/to/image -m 1920 --no-sharpen              # Make /path/to/image resized without auto sharpening",
+        "/path/to/image -m 1920 --sharpen 0               # Make /path/to/image resized without auto sharpening",
         "/path/to/image -m 1920 --ppi 150                 # Make /path/to/image resized, and set their PPI to 150",
+        "/path/to/folder -m 512 --density 300             # Make images inside /path/to/folder resized, rasterizing SVG images at 300 DPI into PNG images",
+        "/path/to/card -m 1920                            # Make JPEG previews of the camera RAW (CR2, NEF, ARW and DNG) images inside /path/to/card next to them",
+        "/path/to/folder --allow-gif --dither none -m 64  # Make images inside /path/to/folder including GIF resized without dithering their palettes",
+        "/path/to/folder -m 1920 --formats jpeg,png,webp  # Make only the JPEG, PNG and WebP images inside /path/to/folder resized, by their contents rather than their extensions",
+        "/path/to/folder -m 1920 --brightness 10          # Make images inside /path/to/folder resized and 10% brighter",
+        "/path/to/folder -m 1920 --auto-level             # Make images inside /path/to/folder resized after stretching their histograms",
+        "/path/to/image.png -m 1024 --icns                # Build /path/to/image.icns with the standard macOS icon sizes from /path/to/image.png",
+        "/path/to/image.png -m 1920 --trim-margins 5%     # Crop 5% from each edge of /path/to/image.png before resizing it",
+        "/path/to/image.webp -m 480 --convert gif         # Resize /path/to/image.webp and save it as /path/to/image.gif if it is animated",
+        "/path/to/folder -m 480 --suffix=-small           # Make each image inside /path/to/folder resized into a file next to it, such as /path/to/folder/image-small.jpg",
+        "/path/to/image.jpg --sizes 480,960,1920          # Make /path/to/image-480.jpg, /path/to/image-960.jpg and /path/to/image-1920.jpg from /path/to/image.jpg, decoding it once",
+        "/path/to/folder -m 1920 --identify-first         # Identify images inside /path/to/folder in parallel first, and then resize them, the largest first, with an ETA measured in pixels",
+        "/path/to/folder -o /path/to/out --preflight=json # List the outputs inside /path/to/out which exist or collide, and the images which would be skipped, without resizing anything",
+        "/path/to/icon.png -m 32 --dpr 1,2,3              # Make /path/to/icon.png 32 pixels at most, and /path/to/icon@2x.png and /path/to/icon@3x.png 64 and 96 pixels at most",
+        "pack /path/to/folder -o /path/to/atlas -m 128    # Resize images inside /path/to/folder to at most 128 pixels and pack them into /path/to/atlas-0.png, ..., with their coordinates in /path/to/atlas.json",
+        "install-shell-integration -m 1920                # Add \"Resize with Image Resizer (1920px)\" to the context menu of Windows Explorer",
+        "install-quick-action -m 1920                     # Add a \"Resize with Image Resizer (1920px)\" Quick Action to Finder on macOS",
     )
 );
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser, Serialize, Deserialize)]
 #[command(name = APP_NAME)]
 #[command(term_width = terminal_size().map(|(width, _)| width.0 as usize).unwrap_or(0))]
 #[command(version = CARGO_PKG_VERSION)]
@@ -35,50 +94,1172 @@ const APP_ABOUT: &str = concat!(
 #[command(after_help = AFTER_HELP)]
 pub struct CLIArgs {
     #[arg(value_hint = clap::ValueHint::AnyPath)]
+    #[arg(required = false, required_unless_present_any = ["files_from", "files_from0", "stdin"])]
+    #[arg(default_value_if("stdin", ArgPredicate::IsPresent, "-"))]
+    #[arg(default_value_if("files_from", ArgPredicate::IsPresent, "."))]
+    #[arg(default_value_if("files_from0", ArgPredicate::IsPresent, "."))]
     #[arg(help = "Assign an image or a directory for image resizing. It should be a path of a \
-                  file or a directory")]
-    pub input_path:       PathBuf,
+                  file or a directory. With --files-from or --files-from0, it is the current \
+                  directory by default")]
+    pub input_path:        PathBuf,
     #[arg(short, long, visible_alias = "output")]
     #[arg(value_hint = clap::ValueHint::AnyPath)]
     #[arg(help = "Assign a destination of your generated files. It should be a path of a \
-                  directory or a file depending on your input path")]
-    pub output_path:      Option<PathBuf>,
+                  directory or a file depending on your input path. A file is saved inside an \
+                  existing directory, or a path ending with a separator, with its own name")]
+    pub output_path:       Option<PathBuf>,
+    #[arg(long = "out", value_name = "NAME=DIR[:preset=PRESET]")]
+    #[arg(value_parser = parse_destination, value_hint = clap::ValueHint::DirPath)]
+    #[arg(conflicts_with_all = ["output_path", "coordinator", "worker", "preflight"])]
+    #[arg(help = "Write the outputs into a directory with the settings of a preset, such as \
+                  web=/srv/www/img:preset=web. Give it more than once to write into several \
+                  directories in one run, decoding each image only once")]
+    pub destinations:      Vec<Destination>,
+    #[arg(skip)]
+    #[serde(skip)]
+    pub destination_args:  Vec<CLIArgs>,
     #[arg(short, long)]
     #[arg(help = "Use only one thread")]
-    pub single_thread:    bool,
+    pub single_thread:     bool,
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    #[arg(help = "Print the options chosen for each image, such as its maximum size and \
+                  quality. Give it twice to print all of the options for each image as JSON")]
+    pub verbose:           u8,
+    #[arg(long)]
+    #[arg(help = "Print neither the messages of the resized images nor the notices of the \
+                  skipped images, but only the warnings, the errors and the summary")]
+    pub quiet:             bool,
     #[arg(short, long)]
     #[arg(help = "Force to overwrite files")]
-    pub force:            bool,
+    pub force:             bool,
+    #[arg(long, value_name = "ANSWERS", value_delimiter = ',', value_parser = parse_yes_no)]
+    #[arg(conflicts_with = "force")]
+    #[arg(help = "Answer the prompts about overwriting existing outputs in order with a \
+                  comma-separated list, such as y,n,y, and ask the rest on the terminal")]
+    pub assume_answers:    Vec<bool>,
+    #[arg(long, value_name = "FILE", conflicts_with = "force")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Answer the prompts about overwriting the outputs listed in a file, one per \
+                  line such as `y path/to/output.jpg`, relative to the current directory. They \
+                  take precedence over --assume-answers")]
+    pub answers_file:      Option<PathBuf>,
+    #[arg(short, long, conflicts_with_all = ["force", "skip_existing"])]
+    #[arg(help = "Answer yes to the prompts about overwriting existing outputs which are not \
+                  answered by --answers-file or --assume-answers, instead of asking them")]
+    pub yes:               bool,
+    #[arg(long, conflicts_with = "force")]
+    #[arg(help = "Answer no to the prompts about overwriting existing outputs which are not \
+                  answered by --answers-file or --assume-answers, instead of asking them, so \
+                  that the existing outputs are skipped")]
+    pub skip_existing:     bool,
+    #[arg(long)]
+    #[arg(help = "Skip the images whose outputs exist and have been modified after them, \
+                  without printing anything but the count in the summary, so that running again \
+                  over a growing directory only resizes the new and the changed images. Give -f \
+                  or --yes as well to overwrite the outputs of the changed images without asking")]
+    pub newer_only:        bool,
+    #[arg(long, value_name = "DIR")]
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    #[arg(help = "Keep the hashes of the contents of the resized images along with the options \
+                  in a cache directory, and skip the images whose contents and options have not \
+                  changed and whose outputs still exist, even if they have been modified")]
+    pub cache:             Option<PathBuf>,
     #[arg(long)]
     #[arg(help = "Allow to do GIF interlacing")]
-    pub allow_gif:        bool,
+    pub allow_gif:         bool,
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',')]
+    #[arg(help = "Only resize the images with these extensions, such as jpg,png, when resizing \
+                  a directory. Listing gif allows GIF images as --allow-gif does")]
+    pub extensions:        Vec<String>,
+    #[arg(long, visible_alias = "all-files")]
+    #[arg(help = "Also walk the files without an image extension when resizing a directory, \
+                  such as the ones exported from phones and messengers or named .jpe or .jfif, \
+                  and identify them by their contents. The ones which are not images are \
+                  skipped with a notice")]
+    pub sniff:             bool,
+    #[arg(long, value_name = "FORMATS", value_delimiter = ',')]
+    #[arg(help = "Only resize the images whose identified formats, not their extensions, are \
+                  these, such as jpeg,png,webp. An extension of a format, such as jpg, also \
+                  stands for it")]
+    pub formats:           Vec<String>,
+    #[arg(long, value_name = "FORMATS", value_delimiter = ',')]
+    #[arg(help = "Never resize the images whose identified formats, not their extensions, are \
+                  these, such as tiff")]
+    pub skip_formats:      Vec<String>,
     #[arg(short, long)]
     #[arg(help = "Remain the profiles of all images")]
-    pub remain_profile:   bool,
+    pub remain_profile:    bool,
+    #[arg(long, value_enum, value_name = "METADATA", value_delimiter = ',')]
+    #[arg(conflicts_with = "remain_profile")]
+    #[arg(help = "Keep some metadata while the other profiles are stripped, even when an image \
+                  is saved in another format, such as icc for the embedded ICC profile")]
+    pub keep_metadata:     Vec<Metadata>,
+    #[arg(long, conflicts_with_all = ["remain_profile", "keep_metadata"])]
+    #[arg(help = "Remove all metadata, including the ICC profiles, from the outputs whatever \
+                  their formats are, even if the config, a preset or the options of a format \
+                  keep it")]
+    pub strip:             bool,
+    #[arg(long)]
+    #[arg(help = "Remove the EXIF profiles, which may hold the GPS locations, from the outputs, \
+                  even if --remain-profile keeps the other profiles")]
+    pub strip_exif:        bool,
+    #[arg(long)]
+    #[arg(help = "Remove the XMP profiles from the outputs, even if --remain-profile keeps the \
+                  other profiles")]
+    pub strip_xmp:         bool,
+    #[arg(long, value_enum)]
+    #[arg(help = "Use a bundle of the maximum side, the quality, the subsampling and whether to \
+                  strip the profiles: web (1920, 82, 4:2:0, stripped), thumbnail (320, 75, \
+                  4:2:0, stripped), print (4096, 95, kept) or archive (8192, 98, kept). A \
+                  [presets.web] table in the config changes a bundle. The options given on the \
+                  command line take precedence")]
+    pub preset:            Option<Preset>,
     #[arg(short = 'm', long, visible_alias = "max")]
+    #[arg(required_unless_present_any = [
+        "max_width",
+        "max_height",
+        "crop",
+        "pad",
+        "scale",
+        "max_megapixels",
+        "side_minimum",
+        "sizes",
+        "preset",
+        "destinations",
+    ])]
     #[arg(
         help = "Set the maximum pixels of each side of an image (Aspect ratio will be preserved)"
     )]
-    pub side_maximum:     u16,
+    pub side_maximum:      Option<u16>,
+    #[arg(long, value_name = "WIDTH")]
+    #[arg(help = "Set the maximum width of an image instead of --side-maximum, so that images \
+                  fit inside a box such as 1920x600 along with --max-height")]
+    pub max_width:         Option<u16>,
+    #[arg(long, value_name = "HEIGHT")]
+    #[arg(help = "Set the maximum height of an image instead of --side-maximum")]
+    pub max_height:        Option<u16>,
+    #[arg(long, value_name = "RULE", value_parser = parse_rule)]
+    #[arg(help = "Change the options for the images matching a rule, such as 'if exif.camera == \
+                  \"Scanner\" then max=3000'. Conditions on format, width, height and \
+                  exif.<TAG> are joined with and, and max, width, height and quality can be \
+                  set. Can be given multiple times, and a later rule overrides an earlier one")]
+    pub rule:              Vec<Rule>,
+    #[arg(long, value_name = "WxH", value_parser = parse_crop)]
+    #[arg(conflicts_with_all = ["side_maximum", "max_width", "max_height"])]
+    #[arg(help = "Resize images to cover a box, such as 400x300, and crop what is outside of \
+                  it, so that every output has exactly that size")]
+    pub crop:              Option<CropBox>,
+    #[arg(long, value_name = "WxH", value_parser = parse_crop)]
+    #[arg(conflicts_with_all = [
+        "side_maximum",
+        "max_width",
+        "max_height",
+        "crop",
+        "scale",
+        "max_megapixels",
+        "side_minimum",
+    ])]
+    #[arg(help = "Resize images to fit inside a box, such as 1000x1000, and pad the rest of it \
+                  with --background, so that every output has exactly that size")]
+    pub pad:               Option<CropBox>,
+    #[arg(long, default_value = "#ffffff", value_name = "COLOR", value_parser = parse_color)]
+    #[arg(help = "Set the color of the padding added by --pad, such as #ffffff, #00000000 or \
+                  transparent. Transparent images saved as JPEG or PGM, such as HEIC images \
+                  transcoded to JPEG, are also flattened onto it, ignoring its transparency")]
+    pub background:        Color,
+    #[arg(long, value_name = "PERCENT", value_parser = parse_scale)]
+    #[arg(conflicts_with_all = ["side_maximum", "max_width", "max_height", "crop"])]
+    #[arg(help = "Scale each image by a percentage of its own size, such as 50%, instead of \
+                  fitting it inside a fixed size")]
+    pub scale:             Option<f64>,
+    #[arg(long, value_name = "MEGAPIXELS", value_parser = parse_megapixels)]
+    #[arg(conflicts_with = "crop")]
+    #[arg(help = "Shrink the images which have more pixels than this many millions, such as 12, \
+                  preserving their aspect ratios. It can be used along with the other sizes")]
+    pub max_megapixels:    Option<f64>,
+    #[arg(long, value_enum, default_value = "center", requires = "crop")]
+    #[arg(help = "Choose which part of an image is kept by --crop. The attention gravity keeps \
+                  the part with the most details")]
+    pub gravity:           Gravity,
     #[arg(long, visible_alias = "shrink")]
     #[arg(help = "Only shrink images, not enlarge them")]
-    pub only_shrink:      bool,
-    #[arg(long)]
-    #[arg(help = "Disable automatically sharpening")]
-    pub no_sharpen:       bool,
+    pub only_shrink:       bool,
+    #[arg(long, value_name = "PIXELS", conflicts_with_all = ["crop", "scale"])]
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(help = "Set the minimum pixels of the long side of an image. Smaller images are \
+                  enlarged to it, even with --only-shrink, unless the maximum size is smaller")]
+    pub side_minimum:      Option<u16>,
+    #[arg(long, value_enum, default_value = "enlarge", value_name = "ACTION")]
+    #[arg(requires = "side_minimum")]
+    #[arg(help = "Choose whether the images smaller than --side-minimum are enlarged or skipped \
+                  and reported")]
+    pub below_minimum:     BelowMinimum,
+    #[arg(long, value_enum, default_value = "lanczos")]
+    #[arg(help = "Choose the filter used to resize the images. Use nearest for pixel art, so \
+                  that its pixels stay crisp instead of being blurred")]
+    pub filter:            ResizeFilter,
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_sharpen)]
+    #[arg(help = "Set the strength (sigma) of the unsharp mask applied after resizing, such as \
+                  1.5 for text screenshots or 0.3 for portraits. 0 disables it. By default, it \
+                  grows with the output size and how much the image is shrunk")]
+    pub sharpen:           Option<f64>,
+    #[arg(long, hide = true, conflicts_with = "sharpen")]
+    #[arg(help = "Disable automatically sharpening, as --sharpen 0 does")]
+    pub no_sharpen:        bool,
+    #[arg(long, value_name = "TEXT")]
+    #[arg(
+        help = "Draw a text, such as a copyright notice, onto the resized images, except ICO icons"
+    )]
+    pub annotate:          Option<String>,
+    #[arg(long, value_enum, default_value = "south-east", value_name = "GRAVITY")]
+    #[arg(requires = "annotate")]
+    #[arg(help = "Choose where the text of --annotate is drawn")]
+    pub annotate_gravity:  AnnotateGravity,
+    #[arg(long, default_value = "4%", value_name = "SIZE", value_parser = parse_text_size)]
+    #[arg(requires = "annotate")]
+    #[arg(help = "Set the size of the text of --annotate in pixels, such as 24, or in a \
+                  percentage of the shorter side of the resized image, such as 5%")]
+    pub annotate_size:     Margin,
+    #[arg(long, value_name = "FONT", requires = "annotate")]
+    #[arg(help = "Set the font of the text of --annotate, by a name known to ImageMagick or by \
+                  the path of a font file")]
+    pub annotate_font:     Option<String>,
+    #[arg(long, default_value = "#ffffff", value_name = "COLOR", value_parser = parse_color)]
+    #[arg(requires = "annotate")]
+    #[arg(help = "Set the color of the text of --annotate, such as #ffffff or #00000080")]
+    pub annotate_color:    Color,
     #[arg(short, long)]
     #[arg(default_value = "92")]
     #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
     #[arg(help = "Set the quality for lossy compression")]
-    pub quality:          u8,
+    pub quality:           u8,
+    #[arg(long)]
+    #[arg(help = "Keep the input instead of its output if the output is larger, such as a JPEG \
+                  image which has been optimized already. The input is copied to the output \
+                  path unless it is the output itself")]
+    pub keep_smaller:      bool,
     #[arg(long)]
     #[arg(value_parser = parse_ppi)]
-    #[arg(help = "Set pixels per inch (ppi)")]
-    pub ppi:              Option<f64>,
+    #[arg(help = "Set pixels per inch (ppi), or per centimeter with --density-unit ppcm")]
+    pub ppi:               Option<f64>,
+    #[arg(long, value_enum, default_value = "ppi", value_name = "UNIT")]
+    #[arg(help = "Set the unit of the density written by --ppi into JPEG, PNG, TIFF, WebP and \
+                  BMP outputs")]
+    pub density_unit:      DensityUnit,
     #[arg(long, visible_alias = "4:2:0")]
     #[arg(help = "Use 4:2:0 (chroma quartered) subsampling to reduce the file size if it is \
                   supported")]
-    pub chroma_quartered: bool,
+    pub chroma_quartered:  bool,
+    #[arg(long = "jpeg-restart-interval", value_name = "MCUS")]
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(help = "Insert a restart marker into JPEG outputs every given number of MCUs")]
+    pub restart_interval:  Option<u16>,
+    #[arg(long)]
+    #[arg(help = "Use arithmetic coding instead of Huffman coding for JPEG outputs if the JPEG \
+                  library of ImageMagick supports it")]
+    pub jpeg_arithmetic:   bool,
+    #[arg(long, conflicts_with = "interlace")]
+    #[arg(help = "Interlace animated GIF outputs as well, so that browsers can show them while \
+                  they are loading. The still JPEG, PNG and GIF outputs are progressive or \
+                  interlaced by default. It is the same as --interlace line, and cannot be used \
+                  with the image backend")]
+    pub progressive:       bool,
+    #[arg(long, value_enum, value_name = "SCHEME")]
+    #[arg(help = "Set the interlace scheme of JPEG, PNG and GIF outputs, including animated GIF \
+                  ones. none writes baseline JPEG and non-interlaced PNG and GIF images. By \
+                  default, the still outputs are interlaced by line and the animated ones are \
+                  not. Only none can be used with the image backend")]
+    pub interlace:         Option<Interlace>,
+    #[arg(long, value_enum)]
+    #[arg(help = "Set the dithering method used when reducing colors for palette-based outputs \
+                  such as GIF")]
+    pub dither:            Option<Dither>,
+    #[arg(long, value_name = "N")]
+    #[arg(help = "Seed the pseudo-random number generator of ImageMagick, which some dithering \
+                  methods and effects use, so that the outputs of different runs and machines \
+                  are the same")]
+    pub seed:              Option<u32>,
+    #[arg(long, value_enum, default_value = "reuse", value_name = "MODE")]
+    #[arg(help = "Choose whether resized GIF images keep the colors chosen when they are \
+                  written, or get a new palette for each frame built from the resized pixels in \
+                  the Lab colorspace, which reduces banding on gradients")]
+    pub gif_palette:       GifPalette,
+    #[arg(long)]
+    #[arg(help = "Check that each resized animation keeps the number of frames, the duration of \
+                  each frame and the loop count of its original, and fail the images whose \
+                  animations do not")]
+    pub verify_animation:  bool,
+    #[arg(long, allow_negative_numbers = true)]
+    #[arg(value_parser = clap::value_parser!(i8).range(-100..=100))]
+    #[arg(help = "Adjust the brightness before encoding, from -100 to 100")]
+    pub brightness:        Option<i8>,
+    #[arg(long, allow_negative_numbers = true)]
+    #[arg(value_parser = clap::value_parser!(i8).range(-100..=100))]
+    #[arg(help = "Adjust the contrast before encoding, from -100 to 100")]
+    pub contrast:          Option<i8>,
+    #[arg(long, allow_negative_numbers = true)]
+    #[arg(value_parser = clap::value_parser!(i8).range(-100..=100))]
+    #[arg(help = "Adjust the saturation before encoding, from -100 (grayscale) to 100")]
+    pub saturation:        Option<i8>,
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "channels")]
+    #[arg(help = "Stretch the histogram of every channel or only the luminance before resizing")]
+    pub auto_level:        Option<AutoLevel>,
+    #[arg(long)]
+    #[arg(value_parser = parse_gamma)]
+    #[arg(help = "Apply a gamma correction, such as 1.1, after the other adjustments and before \
+                  resizing. It is applied to the sRGB values of the images, in which they are \
+                  resized as well")]
+    pub gamma:             Option<f64>,
+    #[arg(long)]
+    #[arg(help = "Rotate and flip the images as their EXIF orientations say before the other \
+                  adjustments and resizing, and reset the orientations of the outputs, so that \
+                  photos taken by phones do not come out sideways")]
+    pub auto_orient:       bool,
+    #[arg(long, value_enum, value_name = "DEGREES")]
+    #[arg(help = "Rotate the images clockwise before the other adjustments and resizing, such \
+                  as to fix sideways scans")]
+    pub rotate:            Option<Rotation>,
+    #[arg(long, value_enum, value_name = "DIRECTION")]
+    #[arg(help = "Mirror the images horizontally (h) or vertically (v) after --rotate, before \
+                  the other adjustments and resizing")]
+    pub flip:              Option<Flip>,
+    #[arg(long, default_value = "failed.txt")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Assign a file to list the images which could not be resized, along with their \
+                  errors. If the disk becomes full, the images which have not been processed \
+                  yet are listed as well, and the program exits with code 3")]
+    pub failed_list:       PathBuf,
+    #[arg(long)]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Only resize the images listed in a failed list of a previous run")]
+    pub retry_failed:      Option<PathBuf>,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["coordinator", "worker"])]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Keep the images which have been resized or skipped in a JSON file when \
+                  resizing a directory, so that an interrupted run can be resumed with \
+                  --resume. The file is removed once the run finishes without failures")]
+    pub state:             Option<PathBuf>,
+    #[arg(long, requires = "state")]
+    #[arg(help = "Resume the run kept in the file of --state, without resizing or asking about \
+                  the images which it has already completed again")]
+    pub resume:            bool,
+    #[arg(long)]
+    #[arg(value_hint = clap::ValueHint::Url)]
+    #[arg(help = "Post the summary of the run as JSON to a URL when the run completes or fails. \
+                  It is sent once per run, since there is no watch mode")]
+    pub notify_url:        Option<String>,
+    #[arg(long)]
+    #[arg(value_hint = clap::ValueHint::CommandString)]
+    #[arg(help = "Execute a shell command with the summary of the run as JSON on its stdin when \
+                  the run completes or fails. It is executed once per run, since there is no \
+                  watch mode")]
+    pub notify_command:    Option<String>,
+    #[arg(long)]
+    #[arg(help = "Show the progress, the throughput and the estimated time remaining after each \
+                  image when resizing a directory")]
+    pub progress:          bool,
+    #[arg(long, conflicts_with_all = ["print0", "coordinator", "worker"])]
+    #[arg(help = "Show a progress bar with the images done and the images being resized in \
+                  place of the messages of the resized images when resizing a directory. It is \
+                  not shown when stdout is not a terminal")]
+    pub progress_bar:      bool,
+    #[arg(long, conflicts_with_all = ["print0", "progress_bar", "coordinator", "worker"])]
+    #[arg(help = "Print a line of JSON to stdout with the path, the status, the counts, the \
+                  throughput and the estimated time remaining after each image when resizing a \
+                  directory, in place of the messages of the resized images")]
+    pub json_stream:       bool,
+    #[arg(long, value_name = "INDEX")]
+    #[arg(help = "Choose which frame of an ICO image to resize, counting from 0. By default, \
+                  the largest frame is chosen")]
+    pub ico_frame:         Option<usize>,
+    #[arg(long)]
+    #[arg(help = "Build an ICNS icon set with the standard macOS sizes up to the maximum size \
+                  from each image, saved with the .icns extension. ICNS images are always \
+                  rebuilt this way")]
+    pub icns:              bool,
+    #[cfg(feature = "dds")]
+    #[arg(long, value_enum)]
+    #[arg(help = "Set the block compression of DDS and KTX2 textures, which are encoded with \
+                  mipmaps. By default, BC3 is used for textures with alpha and BC1 for the \
+                  others")]
+    pub dds_compression:   Option<DdsCompression>,
+    #[arg(long)]
+    #[arg(help = "Encode JPEG XL images losslessly, ignoring --quality")]
+    pub jxl_lossless:      bool,
+    #[arg(long)]
+    #[arg(help = "Save resized BMP images as PNG images next to their outputs")]
+    pub bmp_to_png:        bool,
+    #[arg(long, value_name = "DPI")]
+    #[arg(value_parser = parse_density)]
+    #[arg(help = "Rasterize SVG images at this density instead of at the size of their outputs. \
+                  SVG images are always saved as PNG images next to their outputs")]
+    pub density:           Option<f64>,
+    #[arg(long, default_value = "4096", value_name = "SIDE")]
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(help = "Set the maximum width and height of each sprite sheet in the pack mode")]
+    pub sheet_size:        u16,
+    #[arg(skip)]
+    #[serde(skip)]
+    pub pack:              bool,
+    #[arg(long, value_name = "FILE", conflicts_with = "retry_failed")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Only resize the images listed in a file (or - for stdin) whose paths are \
+                  separated by NUL characters, such as the output of `find -print0`. The input \
+                  path should be the directory the listed images are relative to")]
+    pub files_from0:       Option<PathBuf>,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["retry_failed", "files_from0"])]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Only resize the images listed in a file (or - for stdin) with one path per \
+                  line, such as the output of `find` or an export of a database. The input path \
+                  should be the directory the listed images are relative to")]
+    pub files_from:        Option<PathBuf>,
+    #[arg(long)]
+    #[arg(help = "Print the paths of the resized images separated by NUL characters, instead of \
+                  the messages")]
+    pub print0:            bool,
+    #[arg(long)]
+    #[arg(conflicts_with_all = [
+        "destinations", "sizes", "dpr", "tile", "quality_ladder", "output_template", "preflight"
+    ])]
+    #[arg(help = "Read a single image from stdin instead of the input path, and resize it in \
+                  memory into --output-path or stdout. The output is in the format of the \
+                  input, and is never asked about, so it is overwritten only with --force or \
+                  --yes")]
+    pub stdin:             bool,
+    #[arg(long)]
+    #[arg(conflicts_with_all = [
+        "output_path", "destinations", "sizes", "dpr", "tile", "quality_ladder",
+        "output_template", "preflight"
+    ])]
+    #[arg(help = "Write the resized image to stdout instead of a file, in the format of the \
+                  input, without any temporary file")]
+    pub stdout:            bool,
+    #[arg(long, value_name = "SIDE")]
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(help = "Split the resized images whose width or height exceeds the given size into a \
+                  grid of tiles named with _<ROW>_<COLUMN> suffixes")]
+    pub tile:              Option<u16>,
+    #[arg(long, default_value = "0", value_name = "PIXELS", requires = "tile")]
+    #[arg(help = "Set how many pixels adjacent tiles overlap")]
+    pub tile_overlap:      u16,
+    #[arg(long, value_name = "QUALITIES", value_delimiter = ',', conflicts_with = "tile")]
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+    #[arg(help = "Encode each resized image with lossy compression once per quality in a \
+                  comma-separated list, such as 85,70,50, instead of using --quality")]
+    pub quality_ladder:    Vec<u8>,
+    #[arg(long, default_value = "{stem}_q{quality}.{ext}", value_name = "TEMPLATE")]
+    #[arg(help = "Set the file name of each encode of the quality ladder. {stem}, {quality} and \
+                  {ext} are replaced")]
+    pub ladder_name:       String,
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    #[arg(conflicts_with_all = ["tile", "quality_ladder", "dpr"])]
+    #[arg(help = "Encode JPEG and WebP outputs with the highest quality, up to --quality, whose \
+                  file sizes fit in a byte budget, such as 500KB (1 KB is 1000 bytes and 1 KiB \
+                  is 1024 bytes)")]
+    pub target_size:       Option<u64>,
+    #[arg(long, value_name = "RATIOS", value_delimiter = ',')]
+    #[arg(conflicts_with_all = ["tile", "quality_ladder"])]
+    #[arg(value_parser = clap::value_parser!(u8).range(1..=9))]
+    #[arg(help = "Treat --side-maximum as a logical size and resize each image once per device \
+                  pixel ratio in a comma-separated list, such as 1,2,3, saving the one of ratio \
+                  N as {stem}@Nx.{ext} (the one of ratio 1 keeps its name)")]
+    pub dpr:               Vec<u8>,
+    #[arg(long, value_name = "SIDES", value_delimiter = ',')]
+    #[arg(conflicts_with_all = [
+        "side_maximum",
+        "max_width",
+        "max_height",
+        "crop",
+        "pad",
+        "scale",
+        "max_megapixels",
+        "side_minimum",
+        "tile",
+        "quality_ladder",
+        "target_size",
+        "dpr",
+    ])]
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(help = "Resize each image once per maximum side in a comma-separated list, such as \
+                  480,960,1920, decoding it only once, and save each size with --size-name")]
+    pub sizes:             Vec<u16>,
+    #[arg(long, default_value = "{stem}-{size}.{ext}", value_name = "TEMPLATE")]
+    #[arg(
+        help = "Set the file name of each size of --sizes. {stem}, {size} and {ext} are replaced"
+    )]
+    pub size_name:         String,
+    #[arg(long, value_name = "TEMPLATE")]
+    #[arg(conflicts_with_all = ["icns", "tile", "quality_ladder", "target_size", "dpr", "sizes"])]
+    #[arg(help = "Set the file name of each output after it has been resized. {stem}, {width}, \
+                  {height} and {ext} are replaced, {width} and {height} with its final size, \
+                  such as {stem}_{width}x{height}.{ext}. Animated images keep their names")]
+    pub output_template:   Option<String>,
+    #[arg(long, value_name = "PREFIX")]
+    #[arg(help = "Prepend a prefix, such as thumb_, to the file names of the outputs, so that \
+                  they can be saved next to their images instead of overwriting them. Images \
+                  whose names have the prefix are not walked when there is no output path")]
+    pub prefix:            Option<String>,
+    #[arg(long, value_name = "SUFFIX", allow_hyphen_values = true)]
+    #[arg(help = "Append a suffix, such as -small, to the file stems of the outputs, so that \
+                  they can be saved next to their images instead of overwriting them. Images \
+                  whose stems have the suffix are not walked when there is no output path")]
+    pub suffix:            Option<String>,
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "worker")]
+    #[arg(help = "Listen on an address such as 0.0.0.0:7878 and hand the images in the input \
+                  directory out to workers instead of resizing them. Only the workers which \
+                  give the same --worker-token are served, but the connections are not \
+                  encrypted, so the address should only be reachable from a trusted network")]
+    pub coordinator:       Option<String>,
+    #[arg(long, value_name = "ADDRESS")]
+    #[arg(help = "Resize the images handed out by the coordinator at an address. The input path \
+                  should be the same directory as the coordinator's, on shared storage, and the \
+                  options of the worker are used")]
+    pub worker:            Option<String>,
+    #[arg(long, value_name = "TOKEN")]
+    #[arg(help = "Share a secret between the coordinator and its workers, so that the \
+                  coordinator only serves the workers which give the same one. \
+                  IMAGE_RESIZER_WORKER_TOKEN is used when it is not given")]
+    #[serde(skip)]
+    pub worker_token:      Option<String>,
+    #[arg(long = "max-jobs-per-worker", value_name = "COUNT", conflicts_with = "coordinator")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(help = "Restart ImageMagick once each thread has resized this many images in a \
+                  directory, waiting for the images being resized, so that the memory which it \
+                  keeps between images is released in a long run. A thread of a worker is \
+                  replaced with a new one, along with its connection, instead. There is no \
+                  watch or server mode, so it only applies to directory runs and workers, and \
+                  the resident memory is printed after each restart and kept in --status-file \
+                  instead of a metrics endpoint")]
+    pub worker_jobs:       Option<u32>,
+    #[arg(long = "magick-memory-limit", value_name = "MIB")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(help = "Limit the memory which ImageMagick takes for the pixels of the images, so \
+                  that the larger images are cached on the disk instead. It is only supported \
+                  on Linux and macOS")]
+    pub memory_limit:      Option<u32>,
+    #[arg(long)]
+    #[arg(help = "Hard-link the outputs of an image which are byte-identical to another of its \
+                  outputs, such as tiles or quality ladder encodes, instead of storing copies")]
+    pub link_duplicates:   bool,
+    #[arg(long, value_enum, value_name = "COLOR_TYPE")]
+    #[arg(help = "Set the color type of PNG outputs. auto reduces each image to the smallest \
+                  sufficient one, such as 8-bit gray for grayscale screenshots or a palette for \
+                  flat graphics, and palette quantizes images to 256 colors with --dither")]
+    pub png_color_type:    Option<PngColorType>,
+    #[cfg(feature = "image-backend")]
+    #[cfg_attr(feature = "magick", arg(long, value_enum, default_value = "magick"))]
+    #[cfg_attr(not(feature = "magick"), arg(long, value_enum, default_value = "image"))]
+    #[arg(help = "Set the library used to decode, resize, sharpen and encode images. The image \
+                  backend only handles JPEG, PNG and WebP images, applies only the size, crop, \
+                  padding, sharpening and quality options and rejects the others, and always \
+                  encodes WebP images losslessly, so --quality does not apply to them")]
+    pub backend:           Backend,
+    #[arg(long)]
+    #[arg(help = "Make read-only outputs and output directories writable while they are \
+                  written, and restore their permissions afterwards, instead of skipping their \
+                  images")]
+    pub chmod_writable:    bool,
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    #[arg(help = "Set the permissions of the written outputs, such as 0644, instead of the ones \
+                  given by the umask")]
+    pub output_mode:       Option<u32>,
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    #[arg(help = "Set the permissions of the created output directories, such as 0755, instead \
+                  of the ones given by the umask")]
+    pub output_dir_mode:   Option<u32>,
+    #[arg(long, conflicts_with_all = ["coordinator", "worker"])]
+    #[arg(help = "Walk the input directory in the order of file names and print the results of \
+                  images in that order, even when they are resized in parallel")]
+    pub ordered_output:    bool,
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["coordinator", "worker"])]
+    #[arg(num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+    #[arg(help = "List the outputs which exist already, the outputs which more than one image \
+                  would be written to, and the images which would be skipped and why, as text, \
+                  JSON or CSV, without resizing anything")]
+    pub preflight:         Option<ReportFormat>,
+    #[arg(long)]
+    #[arg(help = "Identify all of the images in parallel before resizing any of them when \
+                  resizing a directory, reporting the ones which would be skipped and the \
+                  outputs which collide, measuring the ETA in pixels, and resizing the largest \
+                  images first unless --priority-glob is given")]
+    pub identify_first:    bool,
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "report_file")]
+    #[arg(help = "Write a record of each image, with its output paths, the dimensions and the \
+                  byte sizes before and after, the time taken and the error if any, to \
+                  --report-file. The JSON report includes the summary of the run, and the CSV \
+                  report has one row per output")]
+    pub report:            Option<ReportFormat>,
+    #[arg(long, value_name = "FILE", requires = "report")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Write the report of --report to this file once the run has finished")]
+    pub report_file:       Option<PathBuf>,
+    #[arg(long, value_enum, default_value = "skip", value_name = "ACTION")]
+    #[arg(help = "Choose whether the empty files and the placeholders, such as the files of \
+                  only NUL bytes, Git LFS pointers and HTML pages named like images, are \
+                  skipped, deleted or moved into --quarantine-dir. They are found without \
+                  ImageMagick and reported apart from the images which cannot be resized")]
+    pub placeholders:      PlaceholderAction,
+    #[arg(long, value_name = "DIR", required_if_eq("placeholders", "quarantine"))]
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    #[arg(help = "Move the empty files and the placeholders into this directory, keeping their \
+                  paths relative to the input directory")]
+    pub quarantine_dir:    Option<PathBuf>,
+    #[arg(long, value_name = "FILE")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Never resize the images matched by a file of paths or globs, one per line and \
+                  relative to the input directory, when resizing a directory")]
+    pub skip_list:         Option<PathBuf>,
+    #[arg(long, value_name = "SUBPATH")]
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    #[arg(help = "Only resize the images inside this file or directory, relative to the input \
+                  directory, when resizing a directory. Can be given multiple times to keep the \
+                  other options, such as the ones loaded by --options-json, for a few subtrees")]
+    #[serde(skip)]
+    pub only:              Vec<PathBuf>,
+    #[arg(long, value_name = "DEPTH")]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(help = "Only walk this many levels of the input directory when resizing a directory. \
+                  1 only resizes the images directly inside it, not the ones in its \
+                  subdirectories")]
+    pub max_depth:         Option<u32>,
+    #[arg(long)]
+    #[arg(help = "Walk into the symbolic links to directories inside the input directory when \
+                  resizing a directory. Loops of links are not walked again")]
+    pub follow_symlinks:   bool,
+    #[arg(long, value_enum, default_value = "resolve", value_name = "ACTION")]
+    #[arg(help = "Choose whether the symbolic links to images are resized like the images they \
+                  point to, with their outputs at the paths of the links, or skipped when \
+                  resizing a directory. Broken links are always skipped")]
+    pub symlinked_files:   SymlinkedFiles,
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+    #[arg(help = "Resize the images matched by this glob, relative to the input directory or \
+                  matching the file name, before the other images when resizing a directory. \
+                  Can be given multiple times to make tiers, which are resized in the order of \
+                  the globs")]
+    pub priority_glob:     Vec<String>,
+    #[arg(long, value_name = "FILE")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Keep a JSON file updated with the counts, the images being resized, the \
+                  throughput and the estimated time remaining when resizing a directory")]
+    pub status_file:       Option<PathBuf>,
+    #[arg(long, value_name = "FILE", conflicts_with = "no_config")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Load default options from a TOML file, such as quality = 85 and side-maximum \
+                  = 1920, with [format.png] tables for images of a format. Without it, \
+                  ~/.config/image-resizer/config.toml is loaded if it exists. --options-json \
+                  and the options given on the command line take precedence")]
+    #[serde(skip)]
+    pub config:            Option<PathBuf>,
+    #[arg(long)]
+    #[arg(help = "Do not load ~/.config/image-resizer/config.toml")]
+    #[serde(skip)]
+    pub no_config:         bool,
+    #[arg(long, value_name = "FILE")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Load the options from a JSON document saved by --save-options-json. Its \
+                  \"formats\" object can override them for images of a format, such as \
+                  {\"png\": {\"quality\": 100}}. The options given on the command line take \
+                  precedence")]
+    #[serde(skip)]
+    pub options_json:      Option<PathBuf>,
+    #[arg(long, value_name = "FILE")]
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    #[arg(help = "Save the options of this run, along with a schema version, to a JSON document")]
+    #[serde(skip)]
+    pub save_options_json: Option<PathBuf>,
+    #[arg(skip)]
+    #[serde(skip)]
+    pub format_options:    BTreeMap<String, Map<String, Value>>,
+    #[arg(skip)]
+    #[serde(skip)]
+    pub format_args:       BTreeMap<String, CLIArgs>,
+    #[arg(long, value_name = "MARGINS", value_parser = parse_margins)]
+    #[arg(help = "Crop a margin from each edge before resizing, such as 5% or 10. Give two \
+                  values, such as 5%,20, for the top/bottom and left/right edges, or four \
+                  values for the top, right, bottom and left edges")]
+    pub trim_margins:      Option<Margins>,
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    #[arg(help = "Convert animated WebP images to another format, saved with its extension, \
+                  instead of keeping them as WebP")]
+    pub convert:           Option<ConvertFormat>,
+    #[arg(long)]
+    #[arg(help = "Measure how long decoding, resizing and encoding take for each image, print \
+                  them, and add their totals to the summary")]
+    pub timings:           bool,
+}
+
+impl CLIArgs {
+    #[inline]
+    pub fn allows_gif(&self) -> bool {
+        self.allow_gif || self.extensions.iter().any(|e| e.eq_ignore_ascii_case("gif"))
+    }
+
+    // --strip wins over the options of the config, the presets and the formats
+    #[inline]
+    pub fn remains_profile(&self) -> bool {
+        self.remain_profile && !self.strip
+    }
+
+    // --no-sharpen is kept for the configs and the option documents written before --sharpen
+    #[inline]
+    pub fn sharpening(&self) -> Option<f64> {
+        if self.no_sharpen {
+            Some(0f64)
+        } else {
+            self.sharpen
+        }
+    }
+
+    // None keeps the interlace scheme chosen by image-convert
+    #[inline]
+    pub fn interlace_scheme(&self) -> Option<Interlace> {
+        if self.progressive {
+            Some(Interlace::Line)
+        } else {
+            self.interlace
+        }
+    }
+
+    #[inline]
+    pub fn keeps_icc(&self) -> bool {
+        self.keep_metadata.contains(&Metadata::Icc) && !self.strip
+    }
+
+    #[inline]
+    pub fn allows_extension(&self, extension: &str) -> bool {
+        self.extensions.is_empty()
+            || self.extensions.iter().any(|e| e.eq_ignore_ascii_case(extension))
+    }
+
+    // the format identified by ImageMagick, such as JPEG, which is listed by its name or by one of
+    // its extensions
+    pub fn allows_format(&self, format: &str) -> bool {
+        let is_listed = |list: &[String]| {
+            list.iter().any(|name| {
+                name.eq_ignore_ascii_case(format)
+                    || crate::formats::by_name(format).is_some_and(|handler| {
+                        handler.extensions.iter().any(|e| e.eq_ignore_ascii_case(name))
+                    })
+            })
+        };
+
+        (self.formats.is_empty() || is_listed(&self.formats)) && !is_listed(&self.skip_formats)
+    }
+
+    #[inline]
+    pub fn has_affixes(&self) -> bool {
+        self.prefix.is_some() || self.suffix.is_some()
+    }
+
+    #[inline]
+    pub fn is_below_minimum(&self, width: u32, height: u32) -> bool {
+        self.side_minimum.is_some_and(|side_minimum| width.max(height) < u32::from(side_minimum))
+    }
+
+    // the options for an image of the size, whose box is derived from the size with --scale,
+    // narrowed with --max-megapixels, and shrunk to --side-minimum for a small image
+    pub fn for_size(&self, width: u32, height: u32) -> Cow<'_, CLIArgs> {
+        let enlarged =
+            self.below_minimum == BelowMinimum::Enlarge && self.is_below_minimum(width, height);
+
+        if self.scale.is_none() && self.max_megapixels.is_none() && !enlarged {
+            return Cow::Borrowed(self);
+        }
+
+        let mut args = self.clone();
+
+        if let Some(scale) = self.scale {
+            let scaled = |side: u32| {
+                (f64::from(side) * scale / 100f64).round().clamp(1f64, f64::from(u16::MAX)) as u16
+            };
+
+            args.max_width = Some(scaled(width));
+            args.max_height = Some(scaled(height));
+        }
+
+        if let Some(max_megapixels) = self.max_megapixels {
+            let factor =
+                (max_megapixels * 1_000_000f64 / (f64::from(width) * f64::from(height))).sqrt();
+
+            if factor < 1f64 {
+                // rounded down, so that the output never has more pixels than allowed
+                let scaled = |side: u32| {
+                    (f64::from(side) * factor).floor().clamp(1f64, f64::from(u16::MAX)) as u16
+                };
+
+                let (max_width, max_height) = args.max_size();
+
+                let narrow = |max: u16, side: u16| if max == 0 { side } else { max.min(side) };
+
+                args.max_width = Some(narrow(max_width, scaled(width)));
+                args.max_height = Some(narrow(max_height, scaled(height)));
+            }
+        }
+
+        let (max_width, max_height) = args.max_size();
+
+        // without --only-shrink, an image is already enlarged to fit the box
+        if enlarged && (args.only_shrink || (max_width, max_height) == (0, 0)) {
+            let side_minimum = self.side_minimum.unwrap();
+
+            let narrow = |max: u16| if max == 0 { side_minimum } else { max.min(side_minimum) };
+
+            args.max_width = Some(narrow(max_width));
+            args.max_height = Some(narrow(max_height));
+            args.only_shrink = false;
+        }
+
+        Cow::Owned(args)
+    }
+
+    // the box which images are fit inside, where 0 leaves a side unconstrained
+    #[inline]
+    pub fn max_size(&self) -> (u16, u16) {
+        // the images have already been cropped or padded to the aspect ratio of the box
+        if let Some(crop_box) = self.crop.or(self.pad) {
+            return (crop_box.width, crop_box.height);
+        }
+
+        // the largest of --sizes, which the other sizes are resized to separately
+        let side_maximum = self.side_maximum.or_else(|| self.sizes.iter().copied().max());
+
+        (
+            self.max_width.or(side_maximum).unwrap_or(0),
+            self.max_height.or(side_maximum).unwrap_or(0),
+        )
+    }
+
+    // the options of the document's section for the identified format of an image, such as JPEG
+    #[inline]
+    pub fn for_format(&self, format: &str) -> &CLIArgs {
+        self.format_args.get(format).unwrap_or(self)
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct ShellIntegrationArgs {
+    #[arg(short = 'm', long, visible_alias = "max", default_value = "1920")]
+    #[arg(help = "Set the maximum pixels of each side which the entries resize images to")]
+    pub side_maximum: u16,
+    #[arg(long)]
+    #[arg(help = "Remove the entries for the maximum pixels instead")]
+    pub uninstall:    bool,
+    #[arg(last = true)]
+    #[arg(help = "Other options which the entries pass to image-resizer")]
+    pub options:      Vec<String>,
+    #[arg(skip)]
+    pub quick_action: bool,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Ordered,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interlace {
+    None,
+    Line,
+    Plane,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DensityUnit {
+    Ppi,
+    Ppcm,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GifPalette {
+    Reuse,
+    Regenerate,
+}
+
+#[cfg(feature = "dds")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DdsCompression {
+    Uncompressed,
+    Bc1,
+    Bc3,
+    Bc7,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PngColorType {
+    Auto,
+    Rgb,
+    Rgba,
+    Gray,
+    Palette,
+}
+
+#[cfg(feature = "image-backend")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    #[cfg(feature = "magick")]
+    Magick,
+    Image,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metadata {
+    Icc,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    Web,
+    Thumbnail,
+    Print,
+    Archive,
+}
+
+impl Preset {
+    #[inline]
+    fn name(self) -> &'static str {
+        match self {
+            Preset::Web => "web",
+            Preset::Thumbnail => "thumbnail",
+            Preset::Print => "print",
+            Preset::Archive => "archive",
+        }
+    }
+
+    fn options(self) -> Map<String, Value> {
+        let (side_maximum, quality, chroma_quartered, remain_profile) = match self {
+            Preset::Web => (1920, 82, true, false),
+            Preset::Thumbnail => (320, 75, true, false),
+            Preset::Print => (4096, 95, false, true),
+            Preset::Archive => (8192, 98, false, true),
+        };
+
+        let mut options = Map::new();
+
+        options.insert("side_maximum".into(), side_maximum.into());
+        options.insert("quality".into(), quality.into());
+        options.insert("chroma_quartered".into(), chroma_quartered.into());
+        options.insert("remain_profile".into(), remain_profile.into());
+
+        options
+    }
+}
+
+// a directory of --out along with its preset, which is kept as it was written so that it can be
+// saved to an options document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Destination {
+    pub name:   String,
+    pub path:   PathBuf,
+    pub preset: Option<Preset>,
+    source:     String,
+}
+
+impl TryFrom<String> for Destination {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let (name, rest) = source
+            .split_once('=')
+            .ok_or_else(|| "A destination must be like web=/srv/www/img:preset=web".to_string())?;
+
+        let (path, preset) = match rest.rsplit_once(":preset=") {
+            Some((path, preset)) => (
+                path,
+                Some(
+                    Preset::from_str(preset.trim(), true)
+                        .map_err(|_| format!("{preset:?} is not a preset"))?,
+                ),
+            ),
+            None => (rest, None),
+        };
+
+        if name.trim().is_empty() || path.is_empty() {
+            return Err("A destination needs a name and a directory".into());
+        }
+
+        Ok(Destination {
+            name: name.trim().to_string(),
+            path: PathBuf::from(path),
+            preset,
+            source,
+        })
+    }
+}
+
+impl From<Destination> for String {
+    #[inline]
+    fn from(destination: Destination) -> Self {
+        destination.source
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConvertFormat {
+    Gif,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoLevel {
+    Channels,
+    Luminance,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Margin {
+    Pixels(u32),
+    Percent(f64),
+}
+
+impl Margin {
+    #[inline]
+    pub fn to_pixels(self, length: usize) -> usize {
+        match self {
+            Margin::Pixels(pixels) => pixels as usize,
+            Margin::Percent(percent) => (length as f64 * percent / 100f64).round() as usize,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CropBox {
+    pub width:  u16,
+    pub height: u16,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Gravity {
+    Center,
+    Top,
+    Attention,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    Lanczos,
+    Mitchell,
+    Catrom,
+    Nearest,
+    Box,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+pub enum Rotation {
+    #[value(name = "90")]
+    #[serde(rename = "90")]
+    Quarter,
+    #[value(name = "180")]
+    #[serde(rename = "180")]
+    Half,
+    #[value(name = "270")]
+    #[serde(rename = "270")]
+    ThreeQuarters,
+}
+
+impl Rotation {
+    #[inline]
+    pub fn degrees(self) -> f64 {
+        match self {
+            Rotation::Quarter => 90f64,
+            Rotation::Half => 180f64,
+            Rotation::ThreeQuarters => 270f64,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+pub enum Flip {
+    #[value(name = "h", alias = "horizontal")]
+    #[serde(rename = "h")]
+    Horizontal,
+    #[value(name = "v", alias = "vertical")]
+    #[serde(rename = "v")]
+    Vertical,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnnotateGravity {
+    NorthWest,
+    North,
+    NorthEast,
+    West,
+    Center,
+    East,
+    SouthWest,
+    South,
+    SouthEast,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Color {
+    pub red:   u8,
+    pub green: u8,
+    pub blue:  u8,
+    pub alpha: u8,
+}
+
+impl Color {
+    // the notation of ImageMagick
+    #[inline]
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}{:02x}", self.red, self.green, self.blue, self.alpha)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BelowMinimum {
+    Enlarge,
+    Skip,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlaceholderAction {
+    Skip,
+    Delete,
+    Quarantine,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkedFiles {
+    Resolve,
+    Skip,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Margins {
+    pub top:    Margin,
+    pub right:  Margin,
+    pub bottom: Margin,
+    pub left:   Margin,
 }
 
 fn parse_ppi(arg: &str) -> Result<f64, String> {
@@ -91,19 +1272,626 @@ fn parse_ppi(arg: &str) -> Result<f64, String> {
     Ok(ppi)
 }
 
-pub fn get_args() -> CLIArgs {
+fn parse_glob(arg: &str) -> Result<String, String> {
+    glob::Pattern::new(arg).map_err(|err| err.to_string())?;
+
+    Ok(arg.into())
+}
+
+fn parse_density(arg: &str) -> Result<f64, String> {
+    let density = arg.parse::<f64>().map_err(|err| err.to_string())?;
+
+    if density <= 0f64 {
+        return Err("Density must be bigger than 0".into());
+    }
+
+    Ok(density)
+}
+
+fn parse_gamma(arg: &str) -> Result<f64, String> {
+    let gamma = arg.parse::<f64>().map_err(|err| err.to_string())?;
+
+    if gamma <= 0f64 {
+        return Err("Gamma must be bigger than 0".into());
+    }
+
+    Ok(gamma)
+}
+
+fn parse_scale(arg: &str) -> Result<f64, String> {
+    let scale = arg.strip_suffix('%').unwrap_or(arg).trim();
+
+    let scale = scale.parse::<f64>().map_err(|err| err.to_string())?;
+
+    if scale <= 0f64 {
+        return Err("The percentage must be bigger than 0".into());
+    }
+
+    Ok(scale)
+}
+
+fn parse_sharpen(arg: &str) -> Result<f64, String> {
+    let sharpen = arg.trim().parse::<f64>().map_err(|err| err.to_string())?;
+
+    if !sharpen.is_finite() || sharpen < 0f64 {
+        return Err("The amount must be at least 0".into());
+    }
+
+    Ok(sharpen)
+}
+
+fn parse_byte_size(arg: &str) -> Result<u64, String> {
+    let arg = arg.trim();
+
+    let index = arg.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(arg.len());
+
+    let (size, unit) = arg.split_at(index);
+
+    let size = size.trim().parse::<f64>().map_err(|err| err.to_string())?;
+
+    let unit = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1f64,
+        "K" | "KB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "KIB" => 1024f64,
+        "MIB" => 1024f64 * 1024f64,
+        "GIB" => 1024f64 * 1024f64 * 1024f64,
+        _ => return Err(format!("{unit:?} is not a unit of bytes")),
+    };
+
+    let size = (size * unit).floor();
+
+    if size < 1f64 {
+        return Err("The size must be at least 1 byte".into());
+    }
+
+    Ok(size as u64)
+}
+
+fn parse_megapixels(arg: &str) -> Result<f64, String> {
+    let megapixels = arg.parse::<f64>().map_err(|err| err.to_string())?;
+
+    if megapixels <= 0f64 {
+        return Err("Megapixels must be bigger than 0".into());
+    }
+
+    Ok(megapixels)
+}
+
+#[inline]
+fn parse_yes_no(arg: &str) -> Result<bool, String> {
+    crate::console::parse_answer(arg).ok_or_else(|| format!("{arg:?} is neither yes nor no"))
+}
+
+#[inline]
+fn parse_rule(arg: &str) -> Result<Rule, String> {
+    Rule::parse(arg)
+}
+
+#[inline]
+fn parse_destination(arg: &str) -> Result<Destination, String> {
+    Destination::try_from(arg.to_string())
+}
+
+fn parse_crop(arg: &str) -> Result<CropBox, String> {
+    let (width, height) = arg
+        .split_once(['x', 'X'])
+        .ok_or_else(|| "The size of the box must be like 400x300".to_string())?;
+
+    let width = width.trim().parse::<u16>().map_err(|err| err.to_string())?;
+    let height = height.trim().parse::<u16>().map_err(|err| err.to_string())?;
+
+    if width == 0 || height == 0 {
+        return Err("The width and the height must be bigger than 0".into());
+    }
+
+    Ok(CropBox {
+        width,
+        height,
+    })
+}
+
+fn parse_mode(arg: &str) -> Result<u32, String> {
+    let digits = arg.trim();
+    let digits = digits.strip_prefix("0o").unwrap_or(digits);
+
+    let mode = u32::from_str_radix(digits, 8).map_err(|err| err.to_string())?;
+
+    if mode > 0o7777 {
+        return Err("The mode must be an octal number such as 0644".into());
+    }
+
+    Ok(mode)
+}
+
+fn parse_color(arg: &str) -> Result<Color, String> {
+    let color = arg.trim();
+
+    let (red, green, blue, alpha) = match color.to_ascii_lowercase().as_str() {
+        "white" => (255, 255, 255, 255),
+        "black" => (0, 0, 0, 255),
+        "transparent" | "none" => (0, 0, 0, 0),
+        _ => {
+            let hex = color.strip_prefix('#').unwrap_or(color);
+
+            let digits = hex
+                .chars()
+                .map(|c| c.to_digit(16).map(|digit| digit as u8))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| format!("{arg:?} is not a color such as #ffffff"))?;
+
+            let channels: Vec<u8> = match digits.len() {
+                3 | 4 => digits.iter().map(|digit| digit * 17).collect(),
+                6 | 8 => digits.chunks(2).map(|digits| digits[0] * 16 + digits[1]).collect(),
+                _ => return Err(format!("{arg:?} is not a color such as #ffffff")),
+            };
+
+            (channels[0], channels[1], channels[2], channels.get(3).copied().unwrap_or(255))
+        },
+    };
+
+    Ok(Color {
+        red,
+        green,
+        blue,
+        alpha,
+    })
+}
+
+fn parse_text_size(arg: &str) -> Result<Margin, String> {
+    let size = arg.trim();
+
+    match size.strip_suffix('%') {
+        Some(percent) => {
+            let percent = percent.trim().parse::<f64>().map_err(|err| err.to_string())?;
+
+            if percent <= 0f64 || percent > 100f64 {
+                return Err("A percentage size must be bigger than 0% and at most 100%".into());
+            }
+
+            Ok(Margin::Percent(percent))
+        },
+        None => match size.parse::<u32>().map_err(|err| err.to_string())? {
+            0 => Err("The size must be bigger than 0".into()),
+            pixels => Ok(Margin::Pixels(pixels)),
+        },
+    }
+}
+
+fn parse_margins(arg: &str) -> Result<Margins, String> {
+    let margins = arg
+        .split(',')
+        .map(|margin| {
+            let margin = margin.trim();
+
+            match margin.strip_suffix('%') {
+                Some(percent) => {
+                    let percent = percent.trim().parse::<f64>().map_err(|err| err.to_string())?;
+
+                    if !(0f64..50f64).contains(&percent) {
+                        return Err(
+                            "A percentage margin must be at least 0% and less than 50%".to_string()
+                        );
+                    }
+
+                    Ok(Margin::Percent(percent))
+                },
+                None => margin.parse::<u32>().map(Margin::Pixels).map_err(|err| err.to_string()),
+            }
+        })
+        .collect::<Result<Vec<Margin>, String>>()?;
+
+    match margins[..] {
+        [margin] => Ok(Margins {
+            top: margin, right: margin, bottom: margin, left: margin
+        }),
+        [vertical, horizontal] => Ok(Margins {
+            top:    vertical,
+            right:  horizontal,
+            bottom: vertical,
+            left:   horizontal,
+        }),
+        [top, right, bottom, left] => Ok(Margins {
+            top,
+            right,
+            bottom,
+            left,
+        }),
+        _ => Err("Give one, two or four margins".into()),
+    }
+}
+
+pub fn get_shell_integration_args() -> Option<ShellIntegrationArgs> {
+    let mut arg_os: Vec<OsString> = env::args_os().collect();
+
+    let (name, about, quick_action) = match arg_os.get(1).and_then(|arg| arg.to_str()) {
+        Some("install-shell-integration") => (
+            "image-resizer install-shell-integration",
+            "Add \"Resize with Image Resizer\" entries to the context menu of images and \
+             directories in Windows Explorer",
+            false,
+        ),
+        Some("install-quick-action") => (
+            "image-resizer install-quick-action",
+            "Add a \"Resize with Image Resizer\" Quick Action for images and directories to \
+             Finder on macOS",
+            true,
+        ),
+        _ => return None,
+    };
+
+    arg_os.remove(1);
+
+    let matches = ShellIntegrationArgs::command().name(name).about(about).get_matches_from(arg_os);
+
+    match ShellIntegrationArgs::from_arg_matches(&matches) {
+        Ok(mut args) => {
+            args.quick_action = quick_action;
+
+            Some(args)
+        },
+        Err(err) => {
+            err.exit();
+        },
+    }
+}
+
+pub fn get_args() -> anyhow::Result<CLIArgs> {
+    let mut arg_os: Vec<OsString> = env::args_os().collect();
+
+    // `image-resizer pack ...` takes the same options, but packs the resized images into sprite
+    // sheets
+    let pack = arg_os.get(1).map(|arg| arg == "pack").unwrap_or(false);
+
+    if pack {
+        arg_os.remove(1);
+    }
+
+    let config = match config_path(&arg_os) {
+        Some(config) => {
+            Some((read_config(&config).with_context(|| anyhow!("{config:?}"))?, config))
+        },
+        None => None,
+    };
+
     let args = CLIArgs::command();
 
     let about = format!("{APP_NAME} {CARGO_PKG_VERSION}\n{CARGO_PKG_AUTHORS}\n{APP_ABOUT}");
 
     let args = args.about(about);
 
-    let matches = args.get_matches();
+    let args = if pack { args.bin_name("image-resizer pack") } else { args };
+
+    // the size can be given by the config instead of the command line, and the input path is
+    // always present
+    let args = match config.as_ref() {
+        Some((document, _))
+            if SIZE_OPTIONS.iter().any(|key| document.options.contains_key(*key)) =>
+        {
+            args.mut_arg("side_maximum", |arg| arg.required_unless_present("input_path"))
+        },
+        _ => args,
+    };
 
-    match CLIArgs::from_arg_matches(&matches) {
+    let matches = args.get_matches_from(arg_os);
+
+    let mut args = match CLIArgs::from_arg_matches(&matches) {
         Ok(args) => args,
         Err(err) => {
             err.exit();
         },
+    };
+
+    let mut presets = BTreeMap::new();
+
+    if let Some((mut document, config)) = config {
+        presets = std::mem::take(&mut document.presets);
+
+        args = load_options(args, &matches, document).with_context(|| anyhow!("{config:?}"))?;
+    }
+
+    if let Some(options_json) = args.options_json.clone() {
+        args = read_options_json(&options_json)
+            .and_then(|document| load_options(args, &matches, document))
+            .with_context(|| anyhow!("{options_json:?}"))?;
+    }
+
+    if let Some(preset) = args.preset {
+        args = apply_preset(args, &matches, preset, &presets)?;
+    }
+
+    // each directory of --out gets the options of the run with its own preset on top of them
+    let mut destination_args = Vec::with_capacity(args.destinations.len());
+
+    for destination in args.destinations.iter() {
+        if args.destinations.iter().filter(|other| other.name == destination.name).count() > 1 {
+            return Err(anyhow!("The destination {:?} is given more than once.", destination.name));
+        }
+
+        let mut options = match destination.preset {
+            Some(preset) => apply_preset(args.clone(), &matches, preset, &presets)
+                .with_context(|| anyhow!("--out {}", destination.name))?,
+            None => args.clone(),
+        };
+
+        options.output_path = Some(destination.path.clone());
+        options.destinations = Vec::new();
+
+        destination_args.push(options);
+    }
+
+    args.destination_args = destination_args;
+
+    args.pack = pack;
+
+    if let Some(save_options_json) = args.save_options_json.as_deref() {
+        let data = serde_json::to_vec_pretty(&OptionsDocument {
+            schema_version: OPTIONS_SCHEMA_VERSION,
+            options:        &args,
+            formats:        args.format_options.clone(),
+            presets:        BTreeMap::new(),
+        })?;
+
+        fs::write(save_options_json, data).with_context(|| anyhow!("{save_options_json:?}"))?;
+    }
+
+    Ok(args)
+}
+
+// the preset replaces the options of the config and the options document, but not the ones given
+// on the command line
+pub fn apply_preset(
+    args: CLIArgs,
+    matches: &ArgMatches,
+    preset: Preset,
+    presets: &BTreeMap<String, Map<String, Value>>,
+) -> anyhow::Result<CLIArgs> {
+    let mut options = preset.options();
+
+    options.extend(presets.get(preset.name()).cloned().unwrap_or_default());
+
+    load_options(args, matches, OptionsDocument {
+        schema_version: OPTIONS_SCHEMA_VERSION,
+        options,
+        formats: BTreeMap::new(),
+        presets: BTreeMap::new(),
+    })
+    .with_context(|| anyhow!("--preset {}", preset.name()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OptionsDocument<T> {
+    schema_version: u32,
+    options:        T,
+    // the options for images of a format, such as "png" or "jpg", on top of the others
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    formats:        BTreeMap<String, Map<String, Value>>,
+    // the changes to the bundles of --preset, which are only read from the config
+    #[serde(skip)]
+    presets:        BTreeMap<String, Map<String, Value>>,
+}
+
+fn read_options_json(path: &Path) -> anyhow::Result<OptionsDocument<Map<String, Value>>> {
+    let document: OptionsDocument<Map<String, Value>> = serde_json::from_slice(&fs::read(path)?)?;
+
+    if document.schema_version != OPTIONS_SCHEMA_VERSION {
+        return Err(anyhow!("The schema version {} is not supported.", document.schema_version));
+    }
+
+    Ok(document)
+}
+
+// --config and --no-config are looked for before the arguments are parsed, since the config may
+// give the size which is otherwise required
+fn config_path(arg_os: &[OsString]) -> Option<PathBuf> {
+    let mut config = None;
+
+    let mut arg_os = arg_os.iter().skip(1);
+
+    while let Some(arg) = arg_os.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--no-config" {
+            return None;
+        } else if arg == "--config" {
+            config = arg_os.next().map(PathBuf::from);
+        } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            config = Some(PathBuf::from(path));
+        }
+    }
+
+    config.or_else(|| default_config_path().filter(|config| config.is_file()))
+}
+
+// $XDG_CONFIG_HOME/image-resizer/config.toml, or ~/.config/image-resizer/config.toml
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(config_dir) => PathBuf::from(config_dir),
+        None => PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?)
+            .join(".config"),
+    };
+
+    Some(config_dir.join("image-resizer").join("config.toml"))
+}
+
+// the options are written like the long flags, such as side-maximum = 1920, and the options for
+// images of a format are in a table such as [format.png], since `formats` is the --formats option,
+// and the changes to a preset are in a table such as [presets.web]
+fn read_config(path: &Path) -> anyhow::Result<OptionsDocument<Map<String, Value>>> {
+    let mut options: Map<String, Value> = toml::from_str(&fs::read_to_string(path)?)?;
+
+    let formats = match options.remove("format") {
+        Some(Value::Object(formats)) => formats
+            .into_iter()
+            .map(|(format, format_options)| match format_options {
+                Value::Object(format_options) => Ok((format, snake_case_keys(format_options))),
+                _ => Err(anyhow!("format.{format} is not a table.")),
+            })
+            .collect::<anyhow::Result<_>>()?,
+        Some(_) => return Err(anyhow!("format is not a table.")),
+        None => BTreeMap::new(),
+    };
+
+    let presets = match options.remove("presets") {
+        Some(Value::Object(presets)) => presets
+            .into_iter()
+            .map(|(preset, preset_options)| {
+                if Preset::from_str(&preset, true).is_err() {
+                    return Err(anyhow!("{preset:?} is not a preset."));
+                }
+
+                match preset_options {
+                    Value::Object(preset_options) => {
+                        Ok((preset.to_ascii_lowercase(), snake_case_keys(preset_options)))
+                    },
+                    _ => Err(anyhow!("presets.{preset} is not a table.")),
+                }
+            })
+            .collect::<anyhow::Result<_>>()?,
+        Some(_) => return Err(anyhow!("presets is not a table.")),
+        None => BTreeMap::new(),
+    };
+
+    Ok(OptionsDocument {
+        schema_version: OPTIONS_SCHEMA_VERSION,
+        options: snake_case_keys(options),
+        formats,
+        presets,
+    })
+}
+
+#[inline]
+fn snake_case_keys(options: Map<String, Value>) -> Map<String, Value> {
+    options.into_iter().map(|(key, value)| (key.replace('-', "_"), value)).collect()
+}
+
+// the options in the document replace the ones which are not given on the command line, and its
+// formats are added to the ones loaded before
+fn load_options(
+    args: CLIArgs,
+    matches: &ArgMatches,
+    document: OptionsDocument<Map<String, Value>>,
+) -> anyhow::Result<CLIArgs> {
+    let mut options = match serde_json::to_value(&args)? {
+        Value::Object(options) => options,
+        _ => unreachable!(),
+    };
+
+    merge_options(&mut options, matches, document.options)?;
+
+    let mut format_args = BTreeMap::new();
+
+    let mut formats = args.format_options.clone();
+
+    formats.extend(document.formats);
+
+    for (format, format_options) in formats.iter() {
+        let format_name = match format_name(format) {
+            Some(format_name) => format_name,
+            None => return Err(anyhow!("{format:?} is not a supported format.")),
+        };
+
+        let mut options = options.clone();
+
+        merge_options(&mut options, matches, format_options.clone())
+            .with_context(|| anyhow!("{format:?}"))?;
+
+        format_args
+            .insert(format_name.to_string(), serde_json::from_value(Value::Object(options))?);
+    }
+
+    let mut loaded: CLIArgs = serde_json::from_value(Value::Object(options))?;
+
+    loaded.config = args.config;
+    loaded.no_config = args.no_config;
+    loaded.options_json = args.options_json;
+    loaded.save_options_json = args.save_options_json;
+    loaded.only = args.only;
+    loaded.worker_token = args.worker_token;
+    loaded.format_options = formats;
+    loaded.format_args = format_args;
+
+    Ok(loaded)
+}
+
+fn merge_options(
+    options: &mut Map<String, Value>,
+    matches: &ArgMatches,
+    document_options: Map<String, Value>,
+) -> anyhow::Result<()> {
+    let command = CLIArgs::command();
+
+    for (key, value) in document_options {
+        // clap panics when it is asked about a field which is not one of its arguments, such as
+        // the ones filled from the other options
+        if !options.contains_key(&key)
+            || !command.get_arguments().any(|arg| arg.get_id().as_str() == key)
+        {
+            return Err(anyhow!("{key:?} is not an option."));
+        }
+
+        if matches.value_source(&key) != Some(ValueSource::CommandLine) {
+            options.insert(key, value);
+        }
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn format_name(format: &str) -> Option<&'static str> {
+    crate::formats::by_extension(format).map(|handler| handler.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &CLIArgs) -> Map<String, Value> {
+        match serde_json::to_value(args).unwrap() {
+            Value::Object(options) => options,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn merge_options_from_document() {
+        let matches = CLIArgs::command()
+            .try_get_matches_from(["image-resizer", "-", "-m", "1920", "-q", "80"])
+            .unwrap();
+        let args = CLIArgs::from_arg_matches(&matches).unwrap();
+
+        let mut options = options(&args);
+
+        let document = serde_json::json!({ "quality": 50, "max_width": 1920 });
+
+        merge_options(&mut options, &matches, document.as_object().unwrap().clone()).unwrap();
+
+        // the options given on the command line are kept
+        assert_eq!(Some(&Value::from(80)), options.get("quality"));
+        assert_eq!(Some(&Value::from(1920)), options.get("max_width"));
+    }
+
+    #[test]
+    fn merge_options_rejects_fields() {
+        let matches =
+            CLIArgs::command().try_get_matches_from(["image-resizer", "-", "-m", "1920"]).unwrap();
+        let args = CLIArgs::from_arg_matches(&matches).unwrap();
+
+        for key in ["destination_args", "format_args", "pack", "not_an_option"] {
+            let mut options = options(&args);
+
+            // even if the field has been serialized
+            options.insert(String::from(key), Value::Null);
+
+            let mut document = Map::new();
+
+            document.insert(String::from(key), Value::Bool(true));
+
+            let error = merge_options(&mut options, &matches, document).unwrap_err();
+
+            assert_eq!(format!("{key:?} is not an option."), error.to_string());
+        }
     }
 }
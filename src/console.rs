@@ -0,0 +1,134 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Debug)]
+pub enum Line {
+    Stdout(Vec<u8>),
+    Stderr(String),
+}
+
+impl Line {
+    #[inline]
+    pub fn print(&self) -> io::Result<()> {
+        let mut sink = SINK.lock().unwrap();
+
+        let sink = sink.get_or_insert_with(|| Box::new(Terminal));
+
+        match self {
+            Line::Stdout(data) => sink.stdout(data),
+            Line::Stderr(text) => sink.stderr(text.as_bytes()),
+        }
+    }
+}
+
+// where the lines go, which can be replaced by a mode that prints something else than the
+// messages for people
+pub trait Sink: Send {
+    fn stdout(&mut self, data: &[u8]) -> io::Result<()>;
+
+    fn stderr(&mut self, data: &[u8]) -> io::Result<()>;
+
+    #[inline]
+    fn prompt(&mut self, question: &[u8]) -> io::Result<()> {
+        self.stdout(question)
+    }
+
+    // how many images have been done, and the images being resized
+    #[inline]
+    fn progress(&mut self, _done: usize, _current: &[PathBuf]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Terminal;
+
+impl Sink for Terminal {
+    fn stdout(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+
+        stdout.write_all(data)?;
+        stdout.flush()
+    }
+
+    fn stderr(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut stderr = io::stderr().lock();
+
+        stderr.write_all(data)?;
+        stderr.flush()
+    }
+}
+
+// one lock for both streams, since a line written to stderr can still be mixed with one written
+// to stdout when both of them go to the same terminal
+static SINK: Mutex<Option<Box<dyn Sink>>> = Mutex::new(None);
+
+// the prompt is printed and answered while holding the lock, so that no line is printed after
+// the question before it has been answered
+pub fn prompt<T, F: FnOnce() -> T>(question: &str, answer: F) -> io::Result<T> {
+    let mut sink = SINK.lock().unwrap();
+
+    sink.get_or_insert_with(|| Box::new(Terminal)).prompt(question.as_bytes())?;
+
+    Ok(answer())
+}
+
+#[inline]
+pub fn progress(done: usize, current: &[PathBuf]) -> io::Result<()> {
+    let mut sink = SINK.lock().unwrap();
+
+    sink.get_or_insert_with(|| Box::new(Terminal)).progress(done, current)
+}
+
+// the previous sink is dropped, which leaves what it has drawn on the terminal
+#[inline]
+pub fn set_sink(sink: Box<dyn Sink>) {
+    *SINK.lock().unwrap() = Some(sink);
+}
+
+// the Debug format of a path escapes every non-ASCII character, which turns a Japanese filename
+// into a row of \u{...} in the messages
+#[derive(Debug, Copy, Clone)]
+pub struct Quoted<'a>(&'a Path);
+
+impl Display for Quoted<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self.0.display())
+    }
+}
+
+#[inline]
+pub fn quoted<P: AsRef<Path> + ?Sized>(path: &P) -> Quoted<'_> {
+    Quoted(path.as_ref())
+}
+
+const YES: [&str; 11] = ["y", "yes", "はい", "是", "예", "네", "ja", "oui", "sí", "si", "да"];
+const NO: [&str; 8] = ["n", "no", "いいえ", "否", "아니요", "nein", "non", "нет"];
+
+// the answer of a yes/no prompt, which may be typed in another language or with an IME still
+// producing full-width characters
+pub fn parse_answer(answer: &str) -> Option<bool> {
+    let answer = answer
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            _ => c,
+        })
+        .collect::<String>();
+    let answer = answer.trim().to_lowercase();
+
+    if YES.contains(&answer.as_str()) {
+        Some(true)
+    } else if NO.contains(&answer.as_str()) {
+        Some(false)
+    } else {
+        None
+    }
+}
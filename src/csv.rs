@@ -0,0 +1,23 @@
+use std::fmt::{self, Write};
+
+// a row of RFC 4180, whose fields are quoted only when they have to be
+pub fn write_row<W: Write>(writer: &mut W, fields: &[&str]) -> fmt::Result {
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            writer.write_char(',')?;
+        }
+
+        if field.contains(['"', ',', '\r', '\n']) {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            writer.write_str(field)?;
+        }
+    }
+
+    writer.write_str("\r\n")
+}
+
+#[inline]
+pub fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
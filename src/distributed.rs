@@ -0,0 +1,518 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    env,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use scanner_rust::{generic_array::typenum::U8, Scanner};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    answers::Answers,
+    cli::CLIArgs,
+    console::{self, Line},
+    directory_output_path, progress, record_image, resizing,
+    summary::{ImageReport, Outcome, SkipReason, Summary},
+};
+
+// every message is a line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    // the first message of a worker
+    Hello { token: String },
+    // the token of the worker is not the one of the coordinator
+    Denied,
+    Next,
+    Job { path: String },
+    // the queue is empty, but the images being resized by other workers may be given back
+    Wait,
+    Done,
+    Resized { path: String, width: u32, height: u32 },
+    Skipped { path: String, reason: SkipReason },
+    PermissionDenied { path: String },
+    Failed { path: String, error: String },
+}
+
+const WORKER_TOKEN_VAR: &str = "IMAGE_RESIZER_WORKER_TOKEN";
+
+const WAIT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Jobs {
+    queue:      Mutex<VecDeque<PathBuf>>,
+    // the images which have not been finished, including the ones being resized by workers
+    unfinished: Mutex<BTreeSet<PathBuf>>,
+    finished:   AtomicUsize,
+}
+
+pub fn coordinate(
+    args: &CLIArgs,
+    summary: &Mutex<Summary>,
+    image_paths: Vec<PathBuf>,
+    address: &str,
+) -> anyhow::Result<()> {
+    let token = worker_token(args)?;
+
+    // a path which cannot be handed out would otherwise stop the run halfway
+    for image_path in image_paths.iter() {
+        relative_path(args, image_path)?;
+    }
+
+    let listener = TcpListener::bind(address).with_context(|| anyhow!("{address}"))?;
+
+    listener.set_nonblocking(true)?;
+
+    Line::Stdout(format!("Waiting for workers on {}.\n", listener.local_addr()?).into_bytes())
+        .print()?;
+
+    let total = image_paths.len();
+
+    let jobs = Jobs {
+        unfinished: Mutex::new(image_paths.iter().cloned().collect()),
+        queue:      Mutex::new(image_paths.into()),
+        finished:   AtomicUsize::new(0),
+    };
+
+    thread::scope(|scope| {
+        let mut handles = Vec::new();
+
+        while jobs.finished.load(Ordering::SeqCst) < total {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    stream.set_nonblocking(false)?;
+
+                    let jobs = &jobs;
+                    let token = token.as_str();
+
+                    handles.push(scope.spawn(move || {
+                        if let Err(error) = serve(args, summary, jobs, token, stream) {
+                            Line::Stderr(format!("{peer}: {error:?}\n")).print()?;
+                        }
+
+                        io::Result::Ok(())
+                    }));
+                },
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(100));
+                },
+                Err(error) => return Err(error).with_context(|| anyhow!("{address}")),
+            }
+        }
+
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        Ok(())
+    })
+}
+
+fn serve(
+    args: &CLIArgs,
+    summary: &Mutex<Summary>,
+    jobs: &Jobs,
+    token: &str,
+    stream: TcpStream,
+) -> anyhow::Result<()> {
+    let peer = stream.peer_addr()?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    match receive(&mut reader)? {
+        Some(Message::Hello {
+            token: worker_token,
+        }) if tokens_match(&worker_token, token) => (),
+        Some(Message::Hello {
+            ..
+        }) => {
+            send(&mut writer, &Message::Denied)?;
+
+            return Err(anyhow!("The worker token is wrong."));
+        },
+        Some(_) => return Err(anyhow!("unexpected message")),
+        None => return Ok(()),
+    }
+
+    let mut in_flight: Option<PathBuf> = None;
+
+    let mut serve_messages = || -> anyhow::Result<()> {
+        loop {
+            let message = match receive(&mut reader)? {
+                Some(message) => message,
+                None => return Ok(()),
+            };
+
+            let (path, result) = match message {
+                Message::Next => {
+                    let job = jobs.queue.lock().unwrap().pop_front();
+
+                    match job {
+                        Some(image_path) => {
+                            send(&mut writer, &Message::Job {
+                                path: relative_path(args, &image_path)?,
+                            })?;
+
+                            in_flight = Some(image_path);
+                        },
+                        None => {
+                            // the workers stay until every image is finished, so that the one of
+                            // a worker which goes away is taken over
+                            if jobs.unfinished.lock().unwrap().is_empty() {
+                                send(&mut writer, &Message::Done)?;
+
+                                return Ok(());
+                            }
+
+                            send(&mut writer, &Message::Wait)?;
+                        },
+                    }
+
+                    continue;
+                },
+                Message::Resized {
+                    path,
+                    width,
+                    height,
+                } => (
+                    path,
+                    Ok(Outcome::Resized {
+                        width,
+                        height,
+                    }),
+                ),
+                Message::Skipped {
+                    path,
+                    reason,
+                } => (path, Ok(Outcome::Skipped(reason))),
+                Message::PermissionDenied {
+                    path,
+                } => (path, Ok(Outcome::PermissionDenied)),
+                Message::Failed {
+                    path,
+                    error,
+                } => (path, Err(anyhow!(error))),
+                _ => return Err(anyhow!("unexpected message")),
+            };
+
+            let image_path = match in_flight.take() {
+                Some(image_path) => image_path,
+                None => return Err(anyhow!("unexpected result of {path:?}")),
+            };
+
+            match result.as_ref() {
+                Ok(Outcome::Resized {
+                    ..
+                }) => Line::Stdout(
+                    format!("{} has been resized by {peer}.\n", console::quoted(&image_path))
+                        .into_bytes(),
+                )
+                .print()?,
+                Ok(Outcome::Skipped(_)) => (),
+                Ok(Outcome::PermissionDenied) => Line::Stderr(format!(
+                    "{} ({peer}): the output is not writable\n",
+                    console::quoted(&image_path)
+                ))
+                .print()?,
+                Err(error) => {
+                    Line::Stderr(format!("{} ({peer}): {error}\n", console::quoted(&image_path)))
+                        .print()?
+                },
+            }
+
+            jobs.unfinished.lock().unwrap().remove(&image_path);
+
+            summary.lock().unwrap().record(image_path, result);
+
+            jobs.finished.fetch_add(1, Ordering::SeqCst);
+        }
+    };
+
+    let result = serve_messages();
+
+    // give the job of a worker which has gone back to the others
+    if let Some(image_path) = in_flight {
+        jobs.queue.lock().unwrap().push_back(image_path);
+    }
+
+    result
+}
+
+pub fn work(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    summary: &Arc<Mutex<Summary>>,
+    address: &str,
+) -> anyhow::Result<()> {
+    let token = worker_token(args)?;
+
+    let threads = if args.single_thread { 1 } else { num_cpus::get() * 2 };
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| loop {
+                    // a new thread for every --max-jobs-per-worker images
+                    let done = thread::scope(|scope| {
+                        scope
+                            .spawn(|| work_on_jobs(args, sc, overwriting, summary, &token, address))
+                            .join()
+                            .unwrap()
+                    })?;
+
+                    if done {
+                        return anyhow::Ok(());
+                    }
+
+                    let memory = match progress::resident_memory() {
+                        Some(bytes) => format!(" The resident memory is {} MiB.", bytes >> 20),
+                        None => String::new(),
+                    };
+
+                    Line::Stderr(format!(
+                        "A thread has been replaced after resizing {} images.{memory}\n",
+                        args.worker_jobs.unwrap()
+                    ))
+                    .print()?;
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        Ok(())
+    })
+}
+
+// returns whether the coordinator has run out of images, or false once the thread has resized
+// --max-jobs-per-worker images
+fn work_on_jobs(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    summary: &Arc<Mutex<Summary>>,
+    token: &str,
+    address: &str,
+) -> anyhow::Result<bool> {
+    let stream = TcpStream::connect(address).with_context(|| anyhow!("{address}"))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    send(&mut writer, &Message::Hello {
+        token: String::from(token)
+    })?;
+
+    let mut jobs = 0;
+
+    loop {
+        if args.worker_jobs.is_some_and(|worker_jobs| jobs >= worker_jobs) {
+            return Ok(false);
+        }
+
+        send(&mut writer, &Message::Next)?;
+
+        match receive(&mut reader)? {
+            Some(Message::Job {
+                path,
+            }) => {
+                let image_path = job_path(&args.input_path, &path)?;
+
+                let output_path = directory_output_path(args, &image_path);
+
+                let mut report = ImageReport::default();
+
+                let start = Instant::now();
+
+                let result = resizing(
+                    args,
+                    sc,
+                    overwriting,
+                    &image_path,
+                    output_path.as_deref(),
+                    &mut report,
+                );
+
+                report.elapsed = start.elapsed();
+
+                let message = match result.as_ref() {
+                    Ok(Outcome::Resized {
+                        width,
+                        height,
+                    }) => Message::Resized {
+                        path,
+                        width: *width,
+                        height: *height,
+                    },
+                    Ok(Outcome::Skipped(reason)) => Message::Skipped {
+                        path,
+                        reason: *reason,
+                    },
+                    Ok(Outcome::PermissionDenied) => Message::PermissionDenied {
+                        path,
+                    },
+                    Err(error) => {
+                        Line::Stderr(format!("{error:?}\n")).print()?;
+
+                        Message::Failed {
+                            path,
+                            error: format!("{error:#}"),
+                        }
+                    },
+                };
+
+                record_image(args, summary, image_path, result, report)?;
+
+                send(&mut writer, &message)?;
+
+                jobs += 1;
+            },
+            Some(Message::Wait) => thread::sleep(WAIT_INTERVAL),
+            Some(Message::Done) => return Ok(true),
+            Some(Message::Denied) => {
+                return Err(anyhow!("The coordinator has rejected the worker token."))
+            },
+            Some(_) => return Err(anyhow!("unexpected message")),
+            None => return Err(anyhow!("The coordinator has closed the connection.")),
+        }
+    }
+}
+
+// the workers share the token with the coordinator, given by --worker-token or the environment
+fn worker_token(args: &CLIArgs) -> anyhow::Result<String> {
+    let token = match args.worker_token.clone() {
+        Some(token) => token,
+        None => env::var(WORKER_TOKEN_VAR).map_err(|_| {
+            anyhow!("A worker token is needed. Give it by --worker-token or {WORKER_TOKEN_VAR}.")
+        })?,
+    };
+
+    if token.is_empty() {
+        return Err(anyhow!("The worker token is empty."));
+    }
+
+    Ok(token)
+}
+
+// compare every byte, so that the time taken does not tell how much of a token is right
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |d, (a, b)| d | (a ^ b)) == 0
+}
+
+// the paths are handed out in UTF-8 with `/` separators, so that workers on other platforms can
+// join them to their input directories
+fn relative_path(args: &CLIArgs, image_path: &Path) -> anyhow::Result<String> {
+    let outside = || {
+        anyhow!(
+            "{} is not inside the input directory, so it cannot be handed out to the workers.",
+            console::quoted(image_path)
+        )
+    };
+
+    let p = pathdiff::diff_paths(image_path, args.input_path.as_path()).ok_or_else(outside)?;
+
+    let mut names = Vec::new();
+
+    for c in p.components() {
+        let name = match c {
+            Component::Normal(name) => name,
+            _ => return Err(outside()),
+        };
+
+        match name.to_str() {
+            Some(name) => names.push(name),
+            None => {
+                return Err(anyhow!(
+                    "{} is not a UTF-8 path, so it cannot be handed out to the workers.",
+                    console::quoted(image_path)
+                ))
+            },
+        }
+    }
+
+    Ok(names.join("/"))
+}
+
+// a path given by the coordinator must not reach outside the input directory of the worker
+fn job_path(input_path: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    let mut image_path = input_path.to_path_buf();
+
+    for name in path.split('/') {
+        let mut components = Path::new(name).components();
+
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(c)), None) if c == name => image_path.push(name),
+            _ => return Err(anyhow!("{path:?} is not a path inside the input directory.")),
+        }
+    }
+
+    if !image_path.starts_with(input_path) {
+        return Err(anyhow!("{path:?} is not a path inside the input directory."));
+    }
+
+    Ok(image_path)
+}
+
+fn send(writer: &mut TcpStream, message: &Message) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+
+    line.push(b'\n');
+
+    writer.write_all(&line)?;
+
+    Ok(())
+}
+
+fn receive(reader: &mut BufReader<TcpStream>) -> anyhow::Result<Option<Message>> {
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_paths_stay_inside() {
+        let input_path = Path::new("images");
+
+        assert_eq!(
+            Path::new("images").join("a").join("b.jpg"),
+            job_path(input_path, "a/b.jpg").unwrap()
+        );
+
+        for path in ["", "a//b.jpg", "./a.jpg", "../a.jpg", "a/../../b.jpg", "/etc/passwd", "a/"] {
+            assert!(job_path(input_path, path).is_err(), "{path:?}");
+        }
+
+        #[cfg(windows)]
+        for path in ["C:/a.jpg", "C:a.jpg", "\\\\server\\share\\a.jpg", "a\\..\\..\\b.jpg"] {
+            assert!(job_path(input_path, path).is_err(), "{path:?}");
+        }
+    }
+
+    #[test]
+    fn tokens_are_compared() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secret", "secreT"));
+        assert!(!tokens_match("secret", "secret2"));
+    }
+}
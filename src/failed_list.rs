@@ -0,0 +1,40 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+pub fn read_failed_list<P: AsRef<Path>>(path: P) -> io::Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(path)?;
+
+    let paths = content
+        .lines()
+        .filter_map(|line| {
+            let path = match line.split_once('\t') {
+                Some((path, _)) => path,
+                None => line,
+            };
+
+            if path.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(path))
+            }
+        })
+        .collect();
+
+    Ok(paths)
+}
+
+pub fn write_failed_list<P: AsRef<Path>>(
+    path: P,
+    failures: &[(PathBuf, String)],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for (path, error) in failures {
+        writeln!(writer, "{}\t{}", path.to_string_lossy(), error.replace(['\r', '\n'], " "))?;
+    }
+
+    writer.flush()
+}
@@ -0,0 +1,549 @@
+#[cfg(feature = "magick")]
+use image_convert::{magick_rust::MagickWand, ImageResource, MagickError};
+use str_utils::EqIgnoreAsciiCaseMultiple;
+
+use crate::cli::CLIArgs;
+#[cfg(feature = "magick")]
+use crate::processing;
+#[cfg(feature = "dds")]
+use crate::texture::{self, Container};
+
+// how an image is passed to image-convert, whose maximum size and sharpening are all 0 for an
+// image which has already been resized
+#[cfg(feature = "magick")]
+#[derive(Debug, Copy, Clone)]
+pub struct Encoding {
+    pub width:   u16,
+    pub height:  u16,
+    pub sharpen: f64,
+    pub quality: u8,
+}
+
+#[cfg(feature = "magick")]
+impl Encoding {
+    // resize and sharpen the image while it is encoded
+    #[inline]
+    pub fn resizing(args: &CLIArgs, quality: u8) -> Encoding {
+        let (width, height) = args.max_size();
+
+        Encoding {
+            width,
+            height,
+            // a negative sigma is chosen by image-convert from the scale
+            sharpen: args.sharpening().unwrap_or(-1f64),
+            quality,
+        }
+    }
+
+    // the image resized with a filter other than Lanczos, along with the encoding which only
+    // sharpens it, or None if image-convert resizes it
+    pub fn filter(
+        self,
+        args: &CLIArgs,
+        input: &ImageResource,
+    ) -> Result<Option<(ImageResource, Encoding)>, MagickError> {
+        let filtered =
+            processing::resize_with_filter(args, input, (self.width, self.height), self.sharpen)?;
+
+        Ok(filtered.map(|(mw, sharpen)| {
+            let encoding = Encoding {
+                width: processing::saturate(mw.get_image_width()),
+                height: processing::saturate(mw.get_image_height()),
+                sharpen,
+                ..self
+            };
+
+            (ImageResource::MagickWand(mw), encoding)
+        }))
+    }
+
+    // only encode an image which has already been resized
+    #[inline]
+    pub fn encoding_only(quality: u8) -> Encoding {
+        Encoding {
+            width: 0,
+            height: 0,
+            sharpen: 0f64,
+            quality,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FormatHandler {
+    // the format identified by ImageMagick
+    pub name:       &'static str,
+    // the extensions walked in a directory and accepted for outputs
+    pub extensions: &'static [&'static str],
+    // whether --quality applies, so that the format can take part in --quality-ladder
+    pub lossy:      bool,
+    // takes the image either as a MagickWand or as the data of a file, since both the binary and
+    // resize_data encode with it
+    #[cfg(feature = "magick")]
+    pub encode:     fn(&CLIArgs, &ImageResource, Encoding) -> anyhow::Result<Vec<u8>>,
+}
+
+impl FormatHandler {
+    #[inline]
+    pub fn is_allowed(&self, args: &CLIArgs) -> bool {
+        self.name != "GIF" || args.allows_gif()
+    }
+
+    // binary search for the highest quality, up to the one of the encoding, whose output fits in
+    // the target size, returning the one of quality 1 if none fits
+    #[cfg(feature = "magick")]
+    pub fn encode_within(
+        &self,
+        args: &CLIArgs,
+        input: &ImageResource,
+        encoding: Encoding,
+        target_size: u64,
+    ) -> anyhow::Result<(Vec<u8>, u8)> {
+        let encode = |quality: u8| {
+            (self.encode)(args, input, Encoding {
+                quality,
+                ..encoding
+            })
+        };
+
+        let mut low = 1;
+        let mut high = encoding.quality.max(1);
+        let mut best = None;
+        let mut smallest = None;
+
+        while low <= high {
+            let quality = low + (high - low) / 2;
+
+            let data = encode(quality)?;
+
+            if data.len() as u64 <= target_size {
+                best = Some((data, quality));
+                low = quality + 1;
+            } else {
+                smallest = Some((data, quality));
+                high = quality - 1;
+            }
+        }
+
+        // the search has ended at quality 1 if no output fits
+        Ok(best.or(smallest).unwrap())
+    }
+}
+
+// a new format only needs to be registered here
+pub const FORMAT_HANDLERS: &[FormatHandler] = &[
+    FormatHandler {
+        name:                              "JPEG",
+        extensions:                        &["jpg", "jpeg"],
+        lossy:                             true,
+        #[cfg(feature = "magick")]
+        encode:                            encode_jpeg,
+    },
+    FormatHandler {
+        name:                              "PNG",
+        extensions:                        &["png"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_png,
+    },
+    FormatHandler {
+        name:                              "TIFF",
+        extensions:                        &["tif", "tiff"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_tiff,
+    },
+    FormatHandler {
+        name:                              "WEBP",
+        extensions:                        &["webp"],
+        lossy:                             true,
+        #[cfg(feature = "magick")]
+        encode:                            encode_webp,
+    },
+    FormatHandler {
+        name:                              "PGM",
+        extensions:                        &["pgm"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_pgm,
+    },
+    FormatHandler {
+        name:                              "ICO",
+        extensions:                        &["ico"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_ico,
+    },
+    #[cfg(feature = "dds")]
+    FormatHandler {
+        name:                              "DDS",
+        extensions:                        &["dds"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_dds,
+    },
+    #[cfg(feature = "dds")]
+    FormatHandler {
+        name:                              "KTX2",
+        extensions:                        &["ktx2"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_ktx2,
+    },
+    FormatHandler {
+        name:                              "HEIC",
+        extensions:                        &["heic", "heif"],
+        lossy:                             true,
+        #[cfg(feature = "magick")]
+        encode:                            encode_heic,
+    },
+    FormatHandler {
+        name:                              "JXL",
+        extensions:                        &["jxl"],
+        lossy:                             true,
+        #[cfg(feature = "magick")]
+        encode:                            encode_jxl,
+    },
+    FormatHandler {
+        name:                              "BMP",
+        extensions:                        &["bmp"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_bmp,
+    },
+    FormatHandler {
+        name:                              "GIF",
+        extensions:                        &["gif"],
+        lossy:                             false,
+        #[cfg(feature = "magick")]
+        encode:                            encode_gif,
+    },
+];
+
+// camera RAW images are decoded by the dcraw or libraw delegate of ImageMagick, and saved as JPEG
+// previews, whose extensions are the same as the formats identified by ImageMagick
+pub const RAW_FORMATS: &[&str] = &["CR2", "NEF", "ARW", "DNG"];
+
+#[inline]
+pub fn is_raw_extension(extension: &str) -> bool {
+    RAW_FORMATS.iter().any(|format| extension.eq_ignore_ascii_case(format))
+}
+
+#[inline]
+pub fn by_name(name: &str) -> Option<&'static FormatHandler> {
+    FORMAT_HANDLERS.iter().find(|handler| handler.name == name)
+}
+
+#[inline]
+pub fn by_extension(extension: &str) -> Option<&'static FormatHandler> {
+    FORMAT_HANDLERS.iter().find(|handler| {
+        extension.eq_ignore_ascii_case_with_lowercase_multiple(handler.extensions).is_some()
+    })
+}
+
+#[cfg(feature = "magick")]
+fn encode_jpeg(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut config = image_convert::JPGConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.width = encoding.width;
+    config.height = encoding.height;
+    config.shrink_only = args.only_shrink;
+    config.sharpen = encoding.sharpen;
+    config.quality = encoding.quality;
+    config.force_to_chroma_quartered = args.chroma_quartered;
+
+    let mut mw = into_wand(|output| image_convert::to_jpg(output, input, &config))?;
+
+    processing::set_interlace(&mut mw, args.interlace_scheme())?;
+
+    processing::flatten(&mw, args.background)?;
+
+    processing::finish(args, input, &mw)?;
+
+    set_density(args, &mut mw, "JPEG")?;
+
+    Ok(processing::encode_jpeg(&mut mw, args.restart_interval, args.jpeg_arithmetic)?)
+}
+
+#[cfg(feature = "magick")]
+fn encode_png(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut mw =
+        into_wand(|output| image_convert::to_png(output, input, &png_config(args, encoding)))?;
+
+    processing::set_interlace(&mut mw, args.interlace_scheme())?;
+
+    processing::finish(args, input, &mw)?;
+
+    set_density(args, &mut mw, "PNG")?;
+
+    match args.png_color_type {
+        Some(color_type) => Ok(processing::encode_png(&mut mw, color_type, args.dither)?),
+        None => Ok(mw.write_image_blob("PNG")?),
+    }
+}
+
+#[cfg(feature = "magick")]
+fn encode_tiff(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut config = image_convert::TIFFConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.width = encoding.width;
+    config.height = encoding.height;
+    config.shrink_only = args.only_shrink;
+    config.sharpen = encoding.sharpen;
+
+    let mut mw = into_wand(|output| image_convert::to_tiff(output, input, &config))?;
+
+    processing::finish(args, input, &mw)?;
+
+    set_density(args, &mut mw, "TIFF")?;
+
+    Ok(mw.write_image_blob("TIFF")?)
+}
+
+#[cfg(feature = "magick")]
+fn encode_webp(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut config = image_convert::WEBPConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.width = encoding.width;
+    config.height = encoding.height;
+    config.shrink_only = args.only_shrink;
+    config.sharpen = encoding.sharpen;
+    config.quality = encoding.quality;
+
+    let mut mw = into_wand(|output| image_convert::to_webp(output, input, &config))?;
+
+    processing::finish(args, input, &mw)?;
+
+    set_density(args, &mut mw, "WEBP")?;
+
+    Ok(mw.write_image_blob("WEBP")?)
+}
+
+#[cfg(feature = "magick")]
+fn encode_pgm(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut config = image_convert::PGMConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.width = encoding.width;
+    config.height = encoding.height;
+    config.shrink_only = args.only_shrink;
+    config.sharpen = encoding.sharpen;
+
+    let mw = into_wand(|output| image_convert::to_pgm(output, input, &config))?;
+
+    processing::flatten(&mw, args.background)?;
+
+    processing::finish(args, input, &mw)?;
+
+    Ok(mw.write_image_blob("PGM")?)
+}
+
+#[cfg(feature = "magick")]
+fn encode_ico(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    // the size of the output is computed from the decoded image, which the callers of the table
+    // may not have decoded
+    let decoded;
+
+    let mw = match input.as_magick_wand() {
+        Some(mw) => mw,
+        None => {
+            let mut mw = None;
+
+            image_convert::identify_read(&mut mw, input)?;
+
+            decoded = mw.unwrap();
+
+            &decoded
+        },
+    };
+
+    let (width, height) = (mw.get_image_width() as u32, mw.get_image_height() as u32);
+
+    let mut config = image_convert::ICOConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.sharpen = encoding.sharpen;
+
+    let (output_width, output_height) = processing::compute_output_size(
+        args.only_shrink,
+        width,
+        height,
+        encoding.width,
+        encoding.height,
+    )
+    .unwrap_or((width, height));
+
+    config.size.push((processing::saturate(output_width), processing::saturate(output_height)));
+
+    let mut output = ImageResource::with_capacity(0);
+
+    image_convert::to_ico(&mut output, input, &config)?;
+
+    Ok(output.into_vec().unwrap())
+}
+
+#[cfg(feature = "dds")]
+fn encode_dds(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mw = resize_to_wand(args, input, encoding)?;
+
+    texture::encode(&mw, args.dds_compression, Container::Dds)
+}
+
+#[cfg(feature = "dds")]
+fn encode_ktx2(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mw = resize_to_wand(args, input, encoding)?;
+
+    texture::encode(&mw, args.dds_compression, Container::Ktx2)
+}
+
+#[cfg(feature = "magick")]
+fn encode_heic(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut mw = resize_to_wand(args, input, encoding)?;
+
+    Ok(processing::encode_heic(&mut mw, encoding.quality)?)
+}
+
+#[cfg(feature = "magick")]
+fn encode_jxl(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut mw = resize_to_wand(args, input, encoding)?;
+
+    Ok(processing::encode_jxl(&mut mw, encoding.quality, args.jxl_lossless)?)
+}
+
+#[cfg(feature = "magick")]
+fn encode_bmp(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut mw = resize_to_wand(args, input, encoding)?;
+
+    set_density(args, &mut mw, "BMP")?;
+
+    Ok(mw.write_image_blob("BMP")?)
+}
+
+#[cfg(feature = "magick")]
+fn encode_gif(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let mut config = image_convert::GIFConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.width = encoding.width;
+    config.height = encoding.height;
+    config.shrink_only = args.only_shrink;
+    config.sharpen = encoding.sharpen;
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    image_convert::to_gif(&mut output, input, &config)?;
+
+    let mut mw = output.into_magick_wand().unwrap();
+
+    processing::set_interlace(&mut mw, args.interlace_scheme())?;
+
+    processing::finish(args, input, &mw)?;
+
+    processing::quantize_gif(args, &mw)?;
+
+    Ok(mw.write_image_blob("GIF")?)
+}
+
+#[cfg(feature = "magick")]
+#[inline]
+fn png_config(args: &CLIArgs, encoding: Encoding) -> image_convert::PNGConfig {
+    let mut config = image_convert::PNGConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.width = encoding.width;
+    config.height = encoding.height;
+    config.shrink_only = args.only_shrink;
+    config.sharpen = encoding.sharpen;
+
+    config
+}
+
+// image-convert leaves its output in a MagickWand, which is written afterwards, so that the
+// density and the options of the coders can be set first
+#[cfg(feature = "magick")]
+#[inline]
+fn into_wand(
+    convert: impl FnOnce(&mut ImageResource) -> Result<(), MagickError>,
+) -> anyhow::Result<MagickWand> {
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    convert(&mut output)?;
+
+    Ok(output.into_magick_wand().unwrap())
+}
+
+// the formats which image-convert cannot write are resized as PNG, and then written by the coders
+// of ImageMagick
+#[cfg(feature = "magick")]
+#[inline]
+fn resize_to_wand(
+    args: &CLIArgs,
+    input: &ImageResource,
+    encoding: Encoding,
+) -> anyhow::Result<MagickWand> {
+    let mw = into_wand(|output| image_convert::to_png(output, input, &png_config(args, encoding)))?;
+
+    processing::finish(args, input, &mw)?;
+
+    Ok(mw)
+}
+
+#[cfg(feature = "magick")]
+#[inline]
+fn set_density(args: &CLIArgs, mw: &mut MagickWand, format: &str) -> Result<(), MagickError> {
+    match args.ppi {
+        Some(density) => processing::set_density(mw, density, args.density_unit, format),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,55 @@
+// the tag which describes a profile, such as "sRGB IEC61966-2.1" or "Display P3"
+const DESCRIPTION_TAG: &[u8; 4] = b"desc";
+
+pub fn description(profile: &[u8]) -> Option<String> {
+    let count = read_u32(profile, 128)? as usize;
+
+    for index in 0..count {
+        let entry = 132 + index * 12;
+
+        if profile.get(entry..entry + 4)? == DESCRIPTION_TAG {
+            let offset = read_u32(profile, entry + 4)? as usize;
+            let length = read_u32(profile, entry + 8)? as usize;
+
+            return read_text(profile.get(offset..offset.checked_add(length)?)?);
+        }
+    }
+
+    None
+}
+
+#[inline]
+pub fn is_srgb(profile: &[u8]) -> bool {
+    description(profile).map(|d| d.to_ascii_lowercase().contains("srgb")).unwrap_or(false)
+}
+
+fn read_text(tag: &[u8]) -> Option<String> {
+    match tag.get(..4)? {
+        // ICC v2, an ASCII string whose length includes the NUL terminator
+        b"desc" => {
+            let length = read_u32(tag, 8)? as usize;
+
+            let text = tag.get(12..12usize.checked_add(length)?)?;
+
+            Some(String::from_utf8_lossy(text).trim_end_matches('\0').to_string())
+        },
+        // ICC v4, UTF-16BE strings in several languages, of which the first one is taken
+        b"mluc" => {
+            let length = read_u32(tag, 20)? as usize;
+            let offset = read_u32(tag, 24)? as usize;
+
+            let text = tag.get(offset..offset.checked_add(length)?)?;
+
+            let text: Vec<u16> =
+                text.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+
+            Some(String::from_utf16_lossy(&text))
+        },
+        _ => None,
+    }
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+}
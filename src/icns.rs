@@ -0,0 +1,79 @@
+use anyhow::anyhow;
+
+// the icon types whose data is a PNG (or JPEG 2000) image, along with their sizes in pixels
+const ICON_TYPES: [(&[u8; 4], u16); 11] = [
+    (b"icp4", 16),
+    (b"icp5", 32),
+    (b"ic11", 32),
+    (b"icp6", 64),
+    (b"ic12", 64),
+    (b"ic07", 128),
+    (b"ic08", 256),
+    (b"ic13", 256),
+    (b"ic09", 512),
+    (b"ic14", 512),
+    (b"ic10", 1024),
+];
+
+pub fn largest_image(data: &[u8]) -> anyhow::Result<&[u8]> {
+    if data.len() < 8 || &data[..4] != b"icns" {
+        return Err(anyhow!("This is not an ICNS file."));
+    }
+
+    let mut largest: Option<(u16, &[u8])> = None;
+
+    let mut offset = 8;
+
+    while offset + 8 <= data.len() {
+        let icon_type = &data[offset..offset + 4];
+        let length = u32::from_be_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+
+        if length < 8 || offset + length > data.len() {
+            return Err(anyhow!("The ICNS file is broken."));
+        }
+
+        if let Some((_, size)) = ICON_TYPES.iter().find(|(t, _)| t[..] == *icon_type) {
+            if largest.map(|(largest_size, _)| *size > largest_size).unwrap_or(true) {
+                largest = Some((*size, &data[offset + 8..offset + length]));
+            }
+        }
+
+        offset += length;
+    }
+
+    match largest {
+        Some((_, image)) => Ok(image),
+        None => Err(anyhow!("The ICNS file does not contain any PNG or JPEG 2000 icon.")),
+    }
+}
+
+pub fn build<F: FnMut(u16) -> anyhow::Result<Vec<u8>>>(
+    max_size: u16,
+    mut encode: F,
+) -> anyhow::Result<Vec<u8>> {
+    let mut data = b"icns\0\0\0\0".to_vec();
+
+    let mut sizes: Vec<u16> = ICON_TYPES.iter().map(|(_, size)| *size).collect();
+
+    sizes.dedup();
+
+    for size in sizes.into_iter().filter(|size| *size <= max_size) {
+        let image = encode(size)?;
+
+        for (icon_type, _) in ICON_TYPES.iter().filter(|(_, s)| *s == size) {
+            data.extend_from_slice(&icon_type[..]);
+            data.extend_from_slice(&(image.len() as u32 + 8).to_be_bytes());
+            data.extend_from_slice(&image);
+        }
+    }
+
+    if data.len() == 8 {
+        return Err(anyhow!("The maximum size is smaller than the smallest icon (16 pixels)."));
+    }
+
+    let length = data.len() as u32;
+
+    data[4..8].copy_from_slice(&length.to_be_bytes());
+
+    Ok(data)
+}
@@ -0,0 +1,441 @@
+use std::{
+    convert::Infallible,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context};
+use fast_image_resize::Resizer;
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops, DynamicImage, GenericImageView, ImageFormat, ImageReader, Rgba, RgbaImage,
+};
+use scanner_rust::{generic_array::typenum::U8, Scanner};
+
+use crate::{
+    answers::Answers,
+    cli::{BelowMinimum, CLIArgs, Color, CropBox, Interlace, ResizeFilter},
+    console, formats, get_output_path, is_sniffed,
+    preflight::{self, Plan},
+    print_chosen_options, print_resized_message, print_skipped_message, processing, rules,
+    summary::{ImageReport, Outcome, SkipReason, Timings},
+    write_rendition,
+};
+
+// the outputs which `resizing` would write for an image, or why it would be skipped, reading only
+// the header of the image
+pub fn plan(
+    args: &CLIArgs,
+    input_path: &Path,
+    output_path: Option<&Path>,
+) -> anyhow::Result<Result<Plan, SkipReason>> {
+    let input_bytes = fs::metadata(input_path).with_context(|| anyhow!("{input_path:?}"))?.len();
+
+    let reader = ImageReader::open(input_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .with_context(|| anyhow!("{input_path:?}"))?;
+
+    let name = match reader.format() {
+        Some(ImageFormat::Jpeg) => "JPEG",
+        Some(ImageFormat::Png) => "PNG",
+        Some(ImageFormat::WebP) => "WEBP",
+        _ => return Ok(Err(SkipReason::UnsupportedFormat)),
+    };
+
+    if !args.allows_format(name) {
+        return Ok(Err(SkipReason::FormatExcluded));
+    }
+
+    let (width, height) = reader.into_dimensions().with_context(|| anyhow!("{input_path:?}"))?;
+
+    let args = &*rules::apply(args.for_format(name), |attribute| {
+        image_attribute(name, width, height, attribute)
+    });
+
+    check_options(args)?;
+
+    if args.below_minimum == BelowMinimum::Skip && args.is_below_minimum(width, height) {
+        return Ok(Err(SkipReason::BelowMinimum));
+    }
+
+    let output_path = output_path.unwrap_or(input_path);
+
+    let sniffed_path;
+
+    let output_path = if is_sniffed(args, input_path) {
+        sniffed_path = output_path.with_extension(formats::by_name(name).unwrap().extensions[0]);
+
+        sniffed_path.as_path()
+    } else {
+        output_path
+    };
+
+    let estimate = |args: &CLIArgs| {
+        let size = preflight::predicted_size(&args.for_size(width, height), width, height);
+
+        (size, preflight::estimate_bytes(input_bytes, (width, height), size))
+    };
+
+    let outputs = if let Some(template) = args.output_template.as_deref() {
+        let ((output_width, output_height), estimated_bytes) = estimate(args);
+
+        vec![(
+            processing::template_path(template, output_path, output_width, output_height),
+            estimated_bytes,
+        )]
+    } else if args.sizes.is_empty() {
+        vec![(output_path.to_path_buf(), estimate(args).1)]
+    } else {
+        args.sizes
+            .iter()
+            .map(|size| {
+                let sized = CLIArgs {
+                    side_maximum: Some(*size),
+                    ..args.clone()
+                };
+
+                (processing::size_path(&args.size_name, output_path, *size), estimate(&sized).1)
+            })
+            .collect()
+    };
+
+    Ok(Ok(Plan {
+        outputs,
+        pixels: u64::from(width) * u64::from(height),
+    }))
+}
+
+// only JPEG, PNG and WebP images are handled, and only the size, crop, padding, sharpening and
+// quality options are applied
+pub fn resizing(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    input_path: &Path,
+    output_path: Option<&Path>,
+    report: &mut ImageReport,
+) -> anyhow::Result<Outcome> {
+    let reader = ImageReader::open(input_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .with_context(|| anyhow!("{input_path:?}"))?;
+
+    let (format, name, extensions): (ImageFormat, &str, &[&str]) = match reader.format() {
+        Some(ImageFormat::Jpeg) => (ImageFormat::Jpeg, "JPEG", &["jpg", "jpeg"]),
+        Some(ImageFormat::Png) => (ImageFormat::Png, "PNG", &["png"]),
+        Some(ImageFormat::WebP) => (ImageFormat::WebP, "WEBP", &["webp"]),
+        _ => {
+            print_skipped_message(
+                args,
+                report,
+                format!(
+                    "{} has been skipped: it is not a JPEG, PNG or WebP image, which the image \
+                     backend can resize.\n",
+                    console::quoted(input_path)
+                ),
+            )?;
+
+            return Ok(Outcome::Skipped(SkipReason::UnsupportedFormat));
+        },
+    };
+
+    if !args.allows_format(name) {
+        print_skipped_message(
+            args,
+            report,
+            format!(
+                "{} has been skipped: its format {name} is excluded by --formats or \
+                 --skip-formats.\n",
+                console::quoted(input_path)
+            ),
+        )?;
+
+        return Ok(Outcome::Skipped(SkipReason::FormatExcluded));
+    }
+
+    // named after the format which it has been identified as, instead of its own extension
+    let sniffed_path;
+
+    let output_path = if is_sniffed(args, input_path) {
+        sniffed_path = output_path.unwrap_or(input_path).with_extension(extensions[0]);
+
+        Some(sniffed_path.as_path())
+    } else {
+        output_path
+    };
+
+    // the outputs along with their maximum sides, which are given by --sizes
+    let mut renditions: Vec<(PathBuf, Option<u16>)> = Vec::new();
+
+    if args.output_template.is_some() {
+        // named and checked once it has been resized
+        renditions.push((output_path.unwrap_or(input_path).to_path_buf(), None));
+    } else if args.sizes.is_empty() {
+        if let Some(output_path) = get_output_path(args, sc, overwriting, input_path, output_path)?
+        {
+            renditions.push((output_path.to_path_buf(), None));
+        }
+    } else {
+        let output_path = output_path.unwrap_or(input_path);
+
+        for size in args.sizes.iter().copied() {
+            let size_path = processing::size_path(&args.size_name, output_path, size);
+
+            if get_output_path(args, sc, overwriting, &size_path, Some(&size_path))?.is_some() {
+                renditions.push((size_path, Some(size)));
+            }
+        }
+    }
+
+    if renditions.is_empty() {
+        return Ok(Outcome::Skipped(SkipReason::OverwriteDeclined));
+    }
+
+    let image = Timings::measure(&mut report.timings.decode, || reader.decode())
+        .with_context(|| anyhow!("{input_path:?}"))?;
+
+    let (width, height) = image.dimensions();
+
+    let args = &*rules::apply(args.for_format(name), |attribute| {
+        image_attribute(name, width, height, attribute)
+    });
+
+    check_options(args)?;
+
+    if args.below_minimum == BelowMinimum::Skip && args.is_below_minimum(width, height) {
+        print_skipped_message(
+            args,
+            report,
+            format!(
+                "{} has been skipped: its long side is shorter than --side-minimum \
+                 ({width}x{height}).\n",
+                console::quoted(input_path)
+            ),
+        )?;
+
+        return Ok(Outcome::Skipped(SkipReason::BelowMinimum));
+    }
+
+    print_chosen_options(args, report, input_path, name)?;
+
+    let count = renditions.len();
+
+    let mut image = Some(image);
+
+    for (index, (output_path, size)) in renditions.into_iter().enumerate() {
+        // only the last rendition takes the decoded image instead of a copy of it
+        let image = if index + 1 == count { image.take().unwrap() } else { image.clone().unwrap() };
+
+        let sized;
+
+        let args = match size {
+            Some(size) => {
+                sized = CLIArgs {
+                    side_maximum: Some(size),
+                    ..args.clone()
+                };
+
+                &sized
+            },
+            None => args,
+        };
+
+        let args = &*args.for_size(width, height);
+
+        let image = Timings::measure(&mut report.timings.resize, || {
+            let image = resize(args, image)?;
+
+            Ok::<_, anyhow::Error>(match args.pad {
+                Some(pad_box) => pad(image, pad_box, args.background),
+                None => image,
+            })
+        })
+        .with_context(|| anyhow!("resize {input_path:?}"))?;
+
+        let output_path = match args.output_template.as_deref() {
+            Some(template) => {
+                let template_path = processing::template_path(
+                    template,
+                    &output_path,
+                    image.width() as usize,
+                    image.height() as usize,
+                );
+
+                if get_output_path(args, sc, overwriting, &template_path, Some(&template_path))?
+                    .is_none()
+                {
+                    return Ok(Outcome::Skipped(SkipReason::OverwriteDeclined));
+                }
+
+                template_path
+            },
+            None => output_path,
+        };
+
+        Timings::measure(&mut report.timings.encode, || {
+            let data = encode(args, format, &image).with_context(|| anyhow!("{output_path:?}"))?;
+
+            // only the output which stands for the input may be replaced by it
+            let original = match size {
+                None if args.output_template.is_none() => Some(input_path),
+                _ => None,
+            };
+
+            write_rendition(args, &mut report.outputs, &output_path, extensions, &data, original)
+                .with_context(|| anyhow!("write {output_path:?}"))
+        })?;
+
+        print_resized_message(args, report, &output_path)?;
+    }
+
+    Ok(Outcome::Resized {
+        width,
+        height,
+    })
+}
+
+// the options which only ImageMagick applies are rejected instead of being ignored, including the
+// ones given by --rule and the format sections
+pub fn check_options(args: &CLIArgs) -> anyhow::Result<()> {
+    let options = [
+        ("--remain-profile", args.remains_profile()),
+        ("--keep-metadata", !args.keep_metadata.is_empty() && !args.strip),
+        ("--filter", args.filter != ResizeFilter::Lanczos),
+        ("--annotate", args.annotate.is_some()),
+        ("--ppi", args.ppi.is_some()),
+        ("--chroma-quartered", args.chroma_quartered),
+        ("--jpeg-restart-interval", args.restart_interval.is_some()),
+        ("--jpeg-arithmetic", args.jpeg_arithmetic),
+        // the encoders of the image crate write neither progressive JPEG nor interlaced PNG images
+        ("--progressive", args.progressive),
+        (
+            "--interlace line or plane",
+            matches!(args.interlace, Some(Interlace::Line | Interlace::Plane)),
+        ),
+        ("--png-color-type", args.png_color_type.is_some()),
+        ("--dither", args.dither.is_some()),
+        ("--seed", args.seed.is_some()),
+        ("--brightness", args.brightness.is_some()),
+        ("--contrast", args.contrast.is_some()),
+        ("--saturation", args.saturation.is_some()),
+        ("--auto-level", args.auto_level.is_some()),
+        ("--gamma", args.gamma.is_some()),
+        ("--auto-orient", args.auto_orient),
+        ("--rotate", args.rotate.is_some()),
+        ("--flip", args.flip.is_some()),
+        ("--trim-margins", args.trim_margins.is_some()),
+        ("--convert", args.convert.is_some()),
+        ("--target-size", args.target_size.is_some()),
+        ("--quality-ladder", !args.quality_ladder.is_empty()),
+        ("--dpr", !args.dpr.is_empty()),
+        ("--tile", args.tile.is_some()),
+        ("--magick-memory-limit", args.memory_limit.is_some()),
+        // without the magick feature, these modes are not available at all
+        ("image-resizer pack", cfg!(feature = "magick") && args.pack),
+        ("--stdin", cfg!(feature = "magick") && args.stdin),
+        ("--stdout", cfg!(feature = "magick") && args.stdout),
+    ];
+
+    match options.into_iter().find(|(_, given)| *given) {
+        Some((option, _)) => Err(anyhow!("{option} cannot be used with --backend image.")),
+        None => Ok(()),
+    }
+}
+
+// an attribute of an image for --rule, where EXIF tags are not read
+fn image_attribute(format: &str, width: u32, height: u32, attribute: &str) -> Option<String> {
+    match attribute {
+        "format" => Some(format.to_string()),
+        "width" => Some(width.to_string()),
+        "height" => Some(height.to_string()),
+        _ => None,
+    }
+}
+
+fn resize(args: &CLIArgs, image: DynamicImage) -> anyhow::Result<DynamicImage> {
+    let image = match args.crop {
+        Some(crop_box) => {
+            let size = (image.width() as usize, image.height() as usize);
+
+            let region = processing::cover_region(size, crop_box);
+
+            let (x, y) = processing::crop_offset(size, region, args.gravity, |width, height| {
+                Ok::<_, Infallible>(
+                    image.thumbnail_exact(width as u32, height as u32).to_luma8().into_raw(),
+                )
+            })
+            .unwrap();
+
+            image.crop_imm(x as u32, y as u32, region.0 as u32, region.1 as u32)
+        },
+        None => image,
+    };
+
+    let (width, height) = (image.width(), image.height());
+
+    let (max_width, max_height) = args.max_size();
+
+    let (output_width, output_height) = match processing::compute_output_size(
+        args.only_shrink,
+        width,
+        height,
+        max_width,
+        max_height,
+    ) {
+        Some(size) => size,
+        None => return Ok(image),
+    };
+
+    // resized with the Lanczos filter of fast_image_resize, which is the one of ImageMagick as well
+    let mut resized = DynamicImage::new(output_width, output_height, image.color());
+
+    Resizer::new().resize(&image, &mut resized, None)?;
+
+    let sigma = match args.sharpening() {
+        Some(sharpen) => sharpen,
+        // the same strength as ImageMagick's
+        None => processing::auto_sharpen((width, height), (output_width, output_height)),
+    };
+
+    if sigma > 0f64 {
+        Ok(resized.unsharpen(sigma as f32, 0))
+    } else {
+        Ok(resized)
+    }
+}
+
+// the resized image, which fits inside the box, is centered on a canvas of the box
+fn pad(image: DynamicImage, pad_box: CropBox, background: Color) -> DynamicImage {
+    let (width, height) = (u32::from(pad_box.width), u32::from(pad_box.height));
+
+    let mut canvas = RgbaImage::from_pixel(
+        width,
+        height,
+        Rgba([background.red, background.green, background.blue, background.alpha]),
+    );
+
+    imageops::overlay(
+        &mut canvas,
+        &image.to_rgba8(),
+        i64::from(width.saturating_sub(image.width()) / 2),
+        i64::from(height.saturating_sub(image.height()) / 2),
+    );
+
+    DynamicImage::ImageRgba8(canvas)
+}
+
+fn encode(args: &CLIArgs, format: ImageFormat, image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+
+    match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, args.quality))?,
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut data))?,
+        // the WebP encoder of the image crate is lossless only
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut data))?,
+        _ => unreachable!(),
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,212 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    mem,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::path_list;
+
+// the paths of a batch are kept in memory until they take this many bytes by default, and the rest
+// of them are appended to a temporary file, so that a tree of tens of millions of images does not
+// take gigabytes only for the bookkeeping
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct Spill {
+    path:   PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl Spill {
+    fn create() -> io::Result<Spill> {
+        let path = env::temp_dir().join(format!(
+            "image-resizer-{}-{}.queue",
+            process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+
+        Ok(Spill {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct JobQueue {
+    memory:       Vec<PathBuf>,
+    memory_size:  usize,
+    memory_limit: usize,
+    spill:        Option<Spill>,
+    len:          usize,
+}
+
+impl Default for JobQueue {
+    #[inline]
+    fn default() -> Self {
+        JobQueue::with_memory_limit(MEMORY_LIMIT)
+    }
+}
+
+impl JobQueue {
+    #[inline]
+    pub fn with_memory_limit(memory_limit: usize) -> JobQueue {
+        JobQueue {
+            memory: Vec::new(),
+            memory_size: 0,
+            memory_limit,
+            spill: None,
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn push(&mut self, path: PathBuf) -> io::Result<()> {
+        if self.spill.is_none() {
+            self.memory_size += path.as_os_str().len() + mem::size_of::<PathBuf>();
+
+            if self.memory_size <= self.memory_limit {
+                self.memory.push(path);
+                self.len += 1;
+
+                return Ok(());
+            }
+
+            self.spill = Some(Spill::create()?);
+        }
+
+        path_list::write_path0(&mut self.spill.as_mut().unwrap().writer, path)?;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    // the paths in the order they were pushed, the spilled ones being read again from the
+    // temporary file every time
+    pub fn iter(&mut self) -> io::Result<impl Iterator<Item = io::Result<PathBuf>> + '_> {
+        let reader = match self.spill.as_mut() {
+            Some(spill) => {
+                spill.writer.flush()?;
+
+                Some(BufReader::new(File::open(&spill.path)?))
+            },
+            None => None,
+        };
+
+        let spilled = reader
+            .into_iter()
+            .flat_map(|reader| reader.split(0).map(|p| p.map(|p| path_list::bytes_to_path(&p))));
+
+        Ok(self.memory.iter().cloned().map(Ok).chain(spilled))
+    }
+
+    // the sprite sheets are packed from all of the images at once
+    #[cfg(feature = "magick")]
+    #[inline]
+    pub fn into_vec(mut self) -> io::Result<Vec<PathBuf>> {
+        self.iter()?.collect()
+    }
+}
+
+impl From<Vec<PathBuf>> for JobQueue {
+    #[inline]
+    fn from(paths: Vec<PathBuf>) -> Self {
+        let memory_size =
+            paths.iter().map(|p| p.as_os_str().len() + mem::size_of::<PathBuf>()).sum();
+
+        JobQueue {
+            len: paths.len(),
+            memory: paths,
+            memory_size,
+            memory_limit: MEMORY_LIMIT,
+            spill: None,
+        }
+    }
+}
+
+impl Drop for JobQueue {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(spill) = self.spill.take() {
+            drop(spill.writer);
+
+            let _ = fs::remove_file(spill.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(count: usize) -> Vec<PathBuf> {
+        (0..count).map(|i| PathBuf::from(format!("images/{i:03}.jpg"))).collect()
+    }
+
+    #[test]
+    fn in_memory() {
+        let mut queue = JobQueue::default();
+
+        for path in paths(10) {
+            queue.push(path).unwrap();
+        }
+
+        assert!(queue.spill.is_none());
+        assert_eq!(10, queue.len());
+        assert_eq!(paths(10), queue.iter().unwrap().collect::<io::Result<Vec<_>>>().unwrap());
+    }
+
+    #[test]
+    fn spilled_in_order() {
+        let size = "images/000.jpg".len() + mem::size_of::<PathBuf>();
+
+        // 3 paths fit in memory
+        let mut queue = JobQueue::with_memory_limit(size * 3);
+
+        for path in paths(10) {
+            queue.push(path).unwrap();
+        }
+
+        assert_eq!(3, queue.memory.len());
+        assert_eq!(10, queue.len());
+
+        let spill_path = queue.spill.as_ref().unwrap().path.clone();
+
+        // read again every time, and after more paths are pushed
+        assert_eq!(paths(10), queue.iter().unwrap().collect::<io::Result<Vec<_>>>().unwrap());
+        assert_eq!(paths(10), queue.iter().unwrap().collect::<io::Result<Vec<_>>>().unwrap());
+
+        queue.push(PathBuf::from("images/010.jpg")).unwrap();
+
+        assert_eq!(paths(11), queue.iter().unwrap().collect::<io::Result<Vec<_>>>().unwrap());
+
+        // the temporary file is removed with the queue
+        drop(queue);
+
+        assert!(!spill_path.exists());
+    }
+
+    #[test]
+    fn spilled_at_once() {
+        let mut queue = JobQueue::with_memory_limit(0);
+
+        for path in paths(5) {
+            queue.push(path).unwrap();
+        }
+
+        assert!(queue.memory.is_empty());
+        assert_eq!(paths(5), queue.iter().unwrap().collect::<io::Result<Vec<_>>>().unwrap());
+    }
+}
@@ -2,4 +2,46 @@
 # Image Resizer
 
 Resize or just shrink images and sharpen them appropriately.
+
+## Library
+
+`Resizer` resizes an image in memory into one output, the same way as the `image-resizer` binary resizes an image file, including animated GIF and WebP images, the frames of ICO images, and the SVG, camera RAW and HEIC images which are converted to other formats. So the outputs of this crate can be compared with golden images in tests without running the binary. It needs the default `magick` feature.
+
+The options which write several outputs for an image, such as `--tile`, `--sizes`, `--dpr`, `--quality-ladder` and `--icns`, are not applied, and neither are ICNS and KTX2 images, which the binary recognizes by their file extensions.
+
+```rust,no_run
+# #[cfg(feature = "magick")]
+# {
+let resizer = image_resizer::Resizer::new(["-m", "256", "-q", "85"]).unwrap();
+
+let output = resizer.resize(&std::fs::read("tests/data/input.png").unwrap()).unwrap();
+# }
+```
 */
+
+// the modules are shared with the binary, which imports them from here instead of compiling them
+// again, so they are hidden from the documentation and not a part of the API
+#[cfg(feature = "magick")]
+#[doc(hidden)]
+pub mod animation;
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod console;
+#[doc(hidden)]
+pub mod formats;
+#[cfg(feature = "magick")]
+#[doc(hidden)]
+pub mod icc;
+#[doc(hidden)]
+pub mod processing;
+#[cfg(feature = "magick")]
+mod resizer;
+#[doc(hidden)]
+pub mod rules;
+#[cfg(feature = "dds")]
+#[doc(hidden)]
+pub mod texture;
+
+#[cfg(feature = "magick")]
+pub use resizer::Resizer;
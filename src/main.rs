@@ -1,417 +1,2175 @@
 extern crate core;
 
-mod cli;
-
+mod answers;
+mod cache;
+mod csv;
+mod distributed;
+mod failed_list;
+#[cfg(feature = "magick")]
+mod icns;
+#[cfg(feature = "image-backend")]
+mod image_backend;
+mod job_queue;
+mod notify;
+mod ordered_output;
+#[cfg(feature = "magick")]
+mod pack;
+mod path_list;
+#[cfg(feature = "magick")]
+mod pipe;
+mod placeholder;
+mod preflight;
+mod progress;
+mod progress_bar;
+mod quick_action;
+mod run_report;
+mod shell_integration;
+mod skip_list;
+mod state;
+mod summary;
+
+#[cfg(not(feature = "magick"))]
+use std::convert::Infallible;
 use std::{
-    fs, io,
-    io::Write,
-    path::Path,
-    sync::{Arc, Mutex},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "magick")]
+use animation::Timing;
+use answers::Answers;
 use anyhow::{anyhow, Context};
 use cli::*;
+use console::Line;
+#[cfg(feature = "magick")]
+use formats::Encoding;
+use glob::Pattern;
+#[cfg(feature = "magick")]
+use image_convert::magick_rust::MagickWand;
+#[cfg(feature = "magick")]
+use image_resizer::animation;
+#[cfg(feature = "dds")]
+use image_resizer::texture;
+use image_resizer::{cli, console, formats, processing, rules};
+use job_queue::JobQueue;
+use ordered_output::OrderedOutput;
+use preflight::Preflight;
+use progress::Progress;
+use progress_bar::ProgressBar;
+use run_report::{FileRecord, FileStatus};
 use scanner_rust::{generic_array::typenum::U8, Scanner};
-use str_utils::EqIgnoreAsciiCaseMultiple;
+use skip_list::SkipList;
+#[cfg(feature = "magick")]
+use summary::Timings;
+use summary::{ImageReport, Outcome, SkipReason, Summary};
 use threadpool::ThreadPool;
 use walkdir::WalkDir;
 
+#[cfg(not(any(feature = "magick", feature = "image-backend")))]
+compile_error!("Either the magick feature or the image-backend feature is needed.");
+
+const DISK_FULL_EXIT_CODE: i32 = 3;
+
+#[derive(Debug)]
+struct DiskFull;
+
+impl Display for DiskFull {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("The disk is full.")
+    }
+}
+
+impl Error for DiskFull {}
+
 fn main() -> anyhow::Result<()> {
-    let args = get_args();
+    if let Some(args) = get_shell_integration_args() {
+        if args.quick_action {
+            return quick_action::quick_action(&args);
+        }
+
+        return shell_integration::shell_integration(&args);
+    }
+
+    let args = Arc::new(get_args()?);
+
+    #[cfg(feature = "image-backend")]
+    if args.backend == Backend::Image {
+        image_backend::check_options(&args)?;
+    }
+
+    if args.stdin || args.stdout {
+        #[cfg(feature = "magick")]
+        return pipe::pipe(&args);
+
+        #[cfg(not(feature = "magick"))]
+        return Err(anyhow!("--stdin and --stdout need a build with the magick feature."));
+    }
+
+    let summary: Arc<Mutex<Summary>> = Arc::new(Mutex::new(Summary::new(&args.input_path)));
+
+    let start = Instant::now();
+
+    let result = run(&args, &summary);
+
+    {
+        let summary = summary.lock().unwrap();
+
+        if let Some(timings) = summary.timings {
+            Line::Stderr(format!("Total: {timings}\n")).print()?;
+        }
+
+        if summary.stripped_profiles > 0 {
+            Line::Stderr(format!(
+                "The non-sRGB ICC profiles of {} images have been stripped, so their colors may \
+                 look different.\n",
+                summary.stripped_profiles
+            ))
+            .print()?;
+        }
+
+        for (reason, count) in summary.skip_reasons.iter() {
+            Line::Stderr(format!("{count} images have been skipped because {reason}.\n"))
+                .print()?;
+        }
+
+        if summary.permission_denied > 0 {
+            Line::Stderr(format!(
+                "{} images have not been resized because their outputs are not writable.\n",
+                summary.permission_denied
+            ))
+            .print()?;
+        }
+    }
+
+    // the images resized before a failure are kept in the cache as well
+    if let Err(error) = cache::save(&args) {
+        Line::Stderr(format!("{error:?}\n")).print()?;
+    }
 
+    // the images completed since the state file was last written would be resized again
+    if let Err(error) = state::save(&args) {
+        Line::Stderr(format!("{error:?}\n")).print()?;
+    }
+
+    if args.notify_url.is_some() || args.notify_command.is_some() || args.report.is_some() {
+        let mut summary = summary.lock().unwrap();
+
+        summary.finish(start.elapsed(), result.as_ref().err());
+
+        if let Err(error) = run_report::write(&args, &summary) {
+            Line::Stderr(format!("{error:?}\n")).print()?;
+        }
+
+        if args.notify_url.is_some() || args.notify_command.is_some() {
+            if let Err(error) = notify::notify(&args, &summary) {
+                Line::Stderr(format!("{error:?}\n")).print()?;
+            }
+        }
+    }
+
+    if let Err(error) = result.as_ref() {
+        if error.is::<DiskFull>() {
+            Line::Stderr(format!("Error: {error:?}\n")).print()?;
+            process::exit(DISK_FULL_EXIT_CODE);
+        }
+    }
+
+    result
+}
+
+fn run(args: &Arc<CLIArgs>, summary: &Arc<Mutex<Summary>>) -> anyhow::Result<()> {
     let is_dir =
         args.input_path.metadata().with_context(|| anyhow!("{:?}", args.input_path))?.is_dir();
 
+    if let Some(tile) = args.tile {
+        if args.tile_overlap >= tile {
+            return Err(anyhow!("The tile overlap should be smaller than the tile size."));
+        }
+    }
+
+    #[cfg(feature = "magick")]
+    processing::start_magick(args)?;
+
+    cache::open(args)?;
+
+    let sc: Arc<Mutex<Scanner<io::Stdin, U8>>> = Arc::new(Mutex::new(Scanner::new2(io::stdin())));
+    let overwriting: Arc<Mutex<Answers>> = Arc::new(Mutex::new(Answers::new(args)?));
+
+    #[cfg(not(feature = "magick"))]
+    if args.pack {
+        return Err(anyhow!("Packing images needs a build with the magick feature."));
+    }
+
+    #[cfg(feature = "magick")]
+    if args.pack {
+        if !is_dir {
+            return Err(anyhow!("{:?} is not a directory.", args.input_path));
+        }
+
+        let output_path = match args.output_path.as_deref() {
+            Some(output_path) => output_path,
+            None => return Err(anyhow!("An output path is needed to pack images.")),
+        };
+
+        let image_paths = select_image_paths(args, walk_image_paths(args)?)?;
+
+        summary.lock().unwrap().total = image_paths.len();
+
+        let image_paths = image_paths.into_vec().with_context(|| anyhow!("queue"))?;
+
+        return pack::pack(args, &sc, &overwriting, summary, image_paths, output_path);
+    }
+
     if let Some(output_path) = args.output_path.as_deref() {
         if is_dir {
             match output_path.metadata() {
                 Ok(metadata) => {
                     if !metadata.is_dir() {
-                        return Err(anyhow!("{output_path:?} is not a directory.",));
+                        return Err(anyhow!(
+                            "{:?} is a directory, but the output path {output_path:?} is a file.",
+                            args.input_path
+                        ));
                     }
                 },
+                // nothing is created by a preflight
                 Err(error) if error.kind() == io::ErrorKind::NotFound => {
-                    fs::create_dir_all(output_path)
-                        .with_context(|| anyhow!("{:?}", output_path))?;
+                    if args.preflight.is_none() {
+                        processing::create_dir_all(output_path, args.output_dir_mode)
+                            .with_context(|| anyhow!("{:?}", output_path))?;
+                    }
                 },
                 Err(error) => {
                     return Err(error).with_context(|| anyhow!("{:?}", output_path));
                 },
             }
-        } else if output_path.is_dir() {
-            return Err(anyhow!("{output_path:?} is a directory."));
         }
     }
 
-    let sc: Arc<Mutex<Scanner<io::Stdin, U8>>> = Arc::new(Mutex::new(Scanner::new2(io::stdin())));
-    let overwriting: Arc<Mutex<u8>> = Arc::new(Mutex::new(0));
+    for destination in args.destinations.iter() {
+        processing::create_dir_all(&destination.path, args.output_dir_mode)
+            .with_context(|| anyhow!("{:?}", destination.path))?;
+    }
+
+    let listed =
+        args.retry_failed.is_some() || args.files_from0.is_some() || args.files_from.is_some();
+
+    if (listed || args.state.is_some()) && !is_dir {
+        return Err(anyhow!("{:?} is not a directory.", args.input_path));
+    }
+
+    let completed = state::open(args)?;
+
+    if completed > 0 && !args.quiet {
+        Line::Stderr(format!(
+            "{completed} images have been completed by the interrupted run, and are skipped.\n"
+        ))
+        .print()?;
+    }
+
+    let disk_full: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+
+    // a failed write to the console, e.g. a closed pipe, ends the run instead of panicking the
+    // worker thread, and the first one is returned once the images in progress are done
+    let output_error: Arc<Mutex<Option<io::Error>>> = Arc::new(Mutex::new(None));
 
     if is_dir {
-        let mut image_paths = Vec::new();
+        if let Some(address) = args.worker.as_deref() {
+            return distributed::work(args, &sc, &overwriting, summary, address);
+        }
+
+        let streamed = is_streamed(args);
+
+        let image_paths = match args.retry_failed.as_deref() {
+            Some(retry_failed) => failed_list::read_failed_list(retry_failed)
+                .with_context(|| anyhow!("{retry_failed:?}"))?
+                .into(),
+            None => match args.files_from0.as_deref() {
+                Some(files_from0) => path_list::read_paths0(files_from0)
+                    .with_context(|| anyhow!("{files_from0:?}"))?
+                    .into(),
+                None => match args.files_from.as_deref() {
+                    Some(files_from) => path_list::read_paths(files_from)
+                        .with_context(|| anyhow!("{files_from:?}"))?
+                        .into(),
+                    // the streamed images are counted as they are walked
+                    None if streamed => JobQueue::default(),
+                    None => walk_image_paths(args)?,
+                },
+            },
+        };
+
+        let mut image_paths = select_image_paths(args, image_paths)?;
+
+        if let Some(format) = args.preflight {
+            return Preflight::identify(args, &mut image_paths)?.print(format);
+        }
 
-        for dir_entry in WalkDir::new(args.input_path.as_path()).into_iter().filter_map(|e| e.ok())
         {
-            if !dir_entry.metadata()?.is_file() {
-                continue;
+            let mut summary = summary.lock().unwrap();
+
+            summary.total = image_paths.len();
+
+            // the order has to be kept in memory, even if the queue has been spilled to disk
+            if args.ordered_output {
+                let order = image_paths
+                    .iter()
+                    .and_then(|paths| paths.collect::<io::Result<Vec<PathBuf>>>())
+                    .with_context(|| anyhow!("queue"))?;
+
+                summary.ordered_output = Some(OrderedOutput::new(order));
             }
+        }
 
-            let p = dir_entry.into_path();
+        // the pixels of the images, which the progress is measured in instead of their bytes
+        let mut pixels = None;
 
-            if let Some(extension) = p.extension() {
-                if let Some(extension) = extension.to_str() {
-                    let mut allow_extensions = vec!["jpg", "jpeg", "png"];
+        if args.identify_first {
+            let identified = Preflight::identify(args, &mut image_paths)?;
 
-                    if args.allow_gif {
-                        allow_extensions.push("gif");
-                    }
+            Line::Stderr(identified.overview()).print()?;
 
-                    if extension
-                        .eq_ignore_ascii_case_with_lowercase_multiple(&allow_extensions)
-                        .is_some()
-                    {
-                        image_paths.push(p);
-                    }
-                }
+            if args.priority_glob.is_empty() {
+                image_paths = identified.largest_first(image_paths)?;
             }
+
+            pixels = Some(identified.into_pixels());
         }
 
-        if args.single_thread {
-            for image_path in image_paths {
-                let output_path = match args.output_path.as_ref() {
-                    Some(output_path) => {
-                        let p =
-                            pathdiff::diff_paths(&image_path, args.input_path.as_path()).unwrap();
+        let total_work = match pixels.as_ref() {
+            Some(pixels) => pixels.values().sum(),
+            None if args.progress || args.json_stream || args.status_file.is_some() => {
+                let mut total_bytes = 0;
 
-                        let output_path = output_path.join(p);
+                for p in image_paths.iter().with_context(|| anyhow!("queue"))? {
+                    let p = p.with_context(|| anyhow!("queue"))?;
 
-                        Some(output_path)
-                    },
-                    None => None,
-                };
-
-                resizing(
-                    args.allow_gif,
-                    args.remain_profile,
-                    args.force,
-                    args.side_maximum,
-                    args.only_shrink,
-                    !args.no_sharpen,
-                    args.quality,
-                    args.ppi,
-                    args.chroma_quartered,
+                    total_bytes += p.metadata().map(|m| m.len()).unwrap_or(0);
+                }
+
+                total_bytes
+            },
+            None => 0,
+        };
+
+        let progress: Arc<Mutex<Progress>> =
+            Arc::new(Mutex::new(Progress::new(image_paths.len(), total_work, pixels)));
+
+        // the messages are kept when they are piped or redirected
+        let progress_bar = args.progress_bar && io::stdout().is_terminal();
+
+        if progress_bar {
+            console::set_sink(Box::new(ProgressBar::new(image_paths.len())));
+        }
+
+        let threads = if args.single_thread { 1 } else { num_cpus::get() * 2 };
+
+        let queued: Box<dyn Iterator<Item = anyhow::Result<PathBuf>>> = if streamed {
+            Box::new(stream_image_paths(args, summary, threads)?.into_iter())
+        } else {
+            Box::new(
+                image_paths
+                    .iter()
+                    .with_context(|| anyhow!("queue"))?
+                    .map(|p| p.with_context(|| anyhow!("queue"))),
+            )
+        };
+
+        if let Some(address) = args.coordinator.as_deref() {
+            let image_paths = queued.collect::<anyhow::Result<Vec<PathBuf>>>()?;
+
+            distributed::coordinate(args, summary, image_paths, address)?;
+        } else if args.single_thread {
+            let mut handed_out = 0;
+
+            for image_path in queued {
+                let image_path = image_path?;
+
+                restart_magick_after(args, &mut handed_out, 1, || ())?;
+
+                if let Err(error) = resizing_in_directory(
+                    args,
                     &sc,
                     &overwriting,
-                    image_path.as_path(),
-                    output_path.as_deref(),
-                )?;
+                    &disk_full,
+                    summary,
+                    &progress,
+                    image_path,
+                ) {
+                    output_error.lock().unwrap().get_or_insert(error);
+
+                    break;
+                }
             }
         } else {
-            let cpus = num_cpus::get();
+            let pool = ThreadPool::new(threads);
 
-            let pool = ThreadPool::new(cpus * 2);
+            let mut handed_out = 0;
 
-            for image_path in image_paths {
-                let sc = sc.clone();
-                let overwriting = overwriting.clone();
-                let output_path = match args.output_path.as_ref() {
-                    Some(output_path) => {
-                        let p =
-                            pathdiff::diff_paths(&image_path, args.input_path.as_path()).unwrap();
+            for image_path in queued {
+                let image_path = image_path?;
 
-                        let output_path = output_path.join(p);
+                if output_error.lock().unwrap().is_some() {
+                    break;
+                }
 
-                        Some(output_path)
-                    },
-                    None => None,
-                };
+                // the images are handed to the pool a few at a time, so that the queue is read
+                // from the disk as they are resized instead of being loaded into the pool at once
+                while pool.queued_count() >= threads {
+                    thread::sleep(Duration::from_millis(10));
+                }
+
+                restart_magick_after(args, &mut handed_out, threads, || pool.join())?;
+
+                let args = args.clone();
+                let sc = sc.clone();
+                let overwriting = overwriting.clone();
+                let disk_full = disk_full.clone();
+                let summary = summary.clone();
+                let progress = progress.clone();
+                let output_error = output_error.clone();
 
                 pool.execute(move || {
-                    if let Err(error) = resizing(
-                        args.allow_gif,
-                        args.remain_profile,
-                        args.force,
-                        args.side_maximum,
-                        args.only_shrink,
-                        !args.no_sharpen,
-                        args.quality,
-                        args.ppi,
-                        args.chroma_quartered,
+                    if let Err(error) = resizing_in_directory(
+                        &args,
                         &sc,
                         &overwriting,
-                        image_path.as_path(),
-                        output_path.as_deref(),
+                        &disk_full,
+                        &summary,
+                        &progress,
+                        image_path,
                     ) {
-                        eprintln!("{error:?}");
-                        io::stderr().flush().unwrap();
+                        output_error.lock().unwrap().get_or_insert(error);
                     }
                 });
             }
 
             pool.join();
         }
+
+        if progress_bar {
+            console::set_sink(Box::new(console::Terminal));
+        }
+
+        if let Some(error) = output_error.lock().unwrap().take() {
+            return Err(error.into());
+        }
+
+        let summary = summary.lock().unwrap();
+
+        if !summary.failures.is_empty() {
+            let failed_list = args.failed_list.as_path();
+
+            failed_list::write_failed_list(failed_list, &summary.failures)
+                .with_context(|| anyhow!("{failed_list:?}"))?;
+
+            if disk_full.load(Ordering::Relaxed) {
+                return Err(anyhow::Error::new(DiskFull).context(format!(
+                    "{} images could not be resized. They are listed in {}.",
+                    summary.failures.len(),
+                    console::quoted(failed_list)
+                )));
+            }
+
+            return Err(anyhow!(
+                "{} images could not be resized. They are listed in {}.",
+                summary.failures.len(),
+                console::quoted(failed_list)
+            ));
+        }
+
+        state::finish(args)?;
+    } else if let Some(format) = args.preflight {
+        let mut preflight = Preflight::default();
+
+        preflight.check(args, args.input_path.clone(), single_output_path(args).as_deref());
+
+        preflight.print(format)?;
     } else {
-        resizing(
-            args.allow_gif,
-            args.remain_profile,
-            args.force,
-            args.side_maximum,
-            args.only_shrink,
-            !args.no_sharpen,
-            args.quality,
-            args.ppi,
-            args.chroma_quartered,
+        summary.lock().unwrap().total = 1;
+
+        let mut report = ImageReport::default();
+
+        let start = Instant::now();
+
+        let result = resizing(
+            args,
             &sc,
             &overwriting,
-            args.input_path,
-            args.output_path,
-        )?;
+            args.input_path.as_path(),
+            single_output_path(args).as_deref(),
+            &mut report,
+        );
+
+        report.elapsed = start.elapsed();
+
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                if args.report.is_some() {
+                    let record = FileRecord::new(args, &args.input_path, Err(&error), &report);
+
+                    summary.lock().unwrap().files.push(record);
+                }
+
+                if processing::is_disk_full(&error) {
+                    return Err(error.context(DiskFull));
+                }
+
+                return Err(error);
+            },
+        };
+
+        record_image(args, summary, args.input_path.clone(), Ok(outcome), report)?;
     }
 
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
-fn resizing<IP: AsRef<Path>, OP: AsRef<Path>>(
-    allow_gif: bool,
-    remain_profile: bool,
-    force: bool,
-    side_maximum: u16,
-    only_shrink: bool,
-    sharpen: bool,
-    quality: u8,
-    ppi: Option<f64>,
-    force_to_chroma_quartered: bool,
-    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
-    overwriting: &Arc<Mutex<u8>>,
-    input_path: IP,
-    output_path: Option<OP>,
+// ImageMagick is restarted before the next image is handed out once the threads have been handed
+// --max-jobs-per-worker images each, which are counted by `handed_out`, and `wait` has returned
+// after all of them have been resized
+fn restart_magick_after(
+    args: &CLIArgs,
+    handed_out: &mut usize,
+    threads: usize,
+    wait: impl FnOnce(),
 ) -> anyhow::Result<()> {
-    let input_path = input_path.as_ref();
-    let output_path = output_path.as_ref().map(|p| p.as_ref());
+    let worker_jobs = match args.worker_jobs {
+        Some(worker_jobs) if cfg!(feature = "magick") => worker_jobs as usize,
+        _ => return Ok(()),
+    };
 
-    let input_image_resource = image_convert::ImageResource::from_path(input_path);
+    if *handed_out < worker_jobs * threads {
+        *handed_out += 1;
 
-    let input_identify = image_convert::identify_ping(&input_image_resource)
-        .with_context(|| anyhow!("{input_path:?}"))?;
+        return Ok(());
+    }
 
-    match input_identify.format.as_str() {
-        "JPEG" => {
-            if let Some(output_path) =
-                get_output_path(force, sc, overwriting, input_path, output_path)?
-            {
-                let mut config = image_convert::JPGConfig::new();
+    wait();
 
-                config.remain_profile = remain_profile;
-                config.width = side_maximum;
-                config.height = side_maximum;
-                config.shrink_only = only_shrink;
+    #[cfg(feature = "magick")]
+    processing::restart_magick(args).context("restart ImageMagick")?;
 
-                if !sharpen {
-                    config.sharpen = 0f64;
-                }
+    if !args.quiet {
+        let memory = match progress::resident_memory() {
+            Some(bytes) => format!(" The resident memory is {} MiB.", bytes >> 20),
+            None => String::new(),
+        };
 
-                config.quality = quality;
+        Line::Stderr(format!(
+            "ImageMagick has been restarted after resizing {handed_out} images.{memory}\n"
+        ))
+        .print()?;
+    }
 
-                if let Some(ppi) = ppi {
-                    config.ppi = Some((ppi, ppi));
-                }
+    *handed_out = 1;
 
-                config.force_to_chroma_quartered = force_to_chroma_quartered;
+    Ok(())
+}
 
-                let mut output = image_convert::ImageResource::from_path(output_path);
+fn resizing_in_directory(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    disk_full: &AtomicBool,
+    summary: &Arc<Mutex<Summary>>,
+    progress: &Arc<Mutex<Progress>>,
+    image_path: PathBuf,
+) -> io::Result<()> {
+    // stop processing the rest of the images once the disk is full, but keep them listed so that
+    // they can be retried
+    if disk_full.load(Ordering::Relaxed) {
+        return record_image(
+            args,
+            summary,
+            image_path,
+            Err(anyhow!("not processed because the disk is full")),
+            ImageReport::default(),
+        );
+    }
 
-                image_convert::to_jpg(&mut output, &input_image_resource, &config)
-                    .with_context(|| anyhow!("to_jpg {output_path:?}"))?;
+    let output_path = directory_output_path(args, &image_path);
 
-                print_resized_message(output_path)?;
-            }
-        },
-        "PNG" => {
-            if let Some(output_path) =
-                get_output_path(force, sc, overwriting, input_path, output_path)?
-            {
-                let mut config = image_convert::PNGConfig::new();
+    let tracking =
+        args.progress || args.progress_bar || args.json_stream || args.status_file.is_some();
 
-                config.remain_profile = remain_profile;
-                config.width = side_maximum;
-                config.height = side_maximum;
-                config.shrink_only = only_shrink;
+    let work = if tracking {
+        let mut progress = progress.lock().unwrap();
 
-                if !sharpen {
-                    config.sharpen = 0f64;
-                }
+        progress.start_file(&image_path);
 
-                if let Some(ppi) = ppi {
-                    config.ppi = Some((ppi, ppi));
-                }
+        if args.progress_bar {
+            console::progress(progress.done_files(), progress.current())?;
+        }
 
-                let mut output = image_convert::ImageResource::from_path(output_path);
+        write_status_file(args, &progress, summary)?;
 
-                image_convert::to_png(&mut output, &input_image_resource, &config)
-                    .with_context(|| anyhow!("to_png {output_path:?}"))?;
+        progress
+            .weight(&image_path)
+            .unwrap_or_else(|| image_path.metadata().map(|m| m.len()).unwrap_or(0))
+    } else {
+        0
+    };
 
-                print_resized_message(output_path)?;
-            }
-        },
-        "TIFF" => {
-            if let Some(output_path) =
-                get_output_path(force, sc, overwriting, input_path, output_path)?
-            {
-                let mut config = image_convert::TIFFConfig::new();
+    let mut report = ImageReport::new(args.ordered_output);
 
-                config.remain_profile = remain_profile;
-                config.width = side_maximum;
-                config.height = side_maximum;
-                config.shrink_only = only_shrink;
+    let start = Instant::now();
 
-                if !sharpen {
-                    config.sharpen = 0f64;
-                }
+    let result =
+        resizing(args, sc, overwriting, image_path.as_path(), output_path.as_deref(), &mut report);
 
-                if let Some(ppi) = ppi {
-                    config.ppi = Some((ppi, ppi));
-                }
+    report.elapsed = start.elapsed();
 
-                let mut output = image_convert::ImageResource::from_path(output_path);
+    if let Err(error) = result.as_ref() {
+        if processing::is_disk_full(error) {
+            disk_full.store(true, Ordering::Relaxed);
+        }
 
-                image_convert::to_tiff(&mut output, &input_image_resource, &config)
-                    .with_context(|| anyhow!("to_tiff {output_path:?}"))?;
+        report.print(Line::Stderr(format!("{error:?}\n")))?;
+    }
 
-                print_resized_message(output_path)?;
-            }
-        },
-        "WEBP" => {
-            if let Some(output_path) =
-                get_output_path(force, sc, overwriting, input_path, output_path)?
-            {
-                let mut config = image_convert::WEBPConfig::new();
+    let pixels = result.as_ref().map(Outcome::pixels).unwrap_or(0);
 
-                config.remain_profile = remain_profile;
-                config.width = side_maximum;
-                config.height = side_maximum;
-                config.shrink_only = only_shrink;
+    if result.is_ok() {
+        if let Err(error) = state::complete(args, &image_path) {
+            Line::Stderr(format!("{error:?}\n")).print()?;
+        }
+    }
 
-                if !sharpen {
-                    config.sharpen = 0f64;
-                }
+    let status = FileStatus::of(result.as_ref());
 
-                config.quality = quality;
+    record_image(args, summary, image_path.clone(), result, report)?;
 
-                let mut output = image_convert::ImageResource::from_path(output_path);
+    if tracking {
+        let mut progress = progress.lock().unwrap();
 
-                image_convert::to_webp(&mut output, &input_image_resource, &config)
-                    .with_context(|| anyhow!("to_webp {output_path:?}"))?;
+        progress.record(&image_path, work, pixels);
 
-                print_resized_message(output_path)?;
-            }
-        },
-        "PGM" => {
-            if let Some(output_path) =
-                get_output_path(force, sc, overwriting, input_path, output_path)?
-            {
-                let mut config = image_convert::PGMConfig::new();
+        if args.progress_bar {
+            console::progress(progress.done_files(), progress.current())?;
+        }
 
-                config.remain_profile = remain_profile;
-                config.width = side_maximum;
-                config.height = side_maximum;
-                config.shrink_only = only_shrink;
+        if args.progress {
+            Line::Stderr(format!("{progress}\n")).print()?;
+        }
 
-                if !sharpen {
-                    config.sharpen = 0f64;
-                }
+        if args.json_stream {
+            let mut line = serde_json::to_vec(&progress.event(&image_path, status)).unwrap();
 
-                let mut output = image_convert::ImageResource::from_path(output_path);
+            line.push(b'\n');
 
-                image_convert::to_pgm(&mut output, &input_image_resource, &config)
-                    .with_context(|| anyhow!("to_pgm {output_path:?}"))?;
+            Line::Stdout(line).print()?;
+        }
 
-                print_resized_message(output_path)?;
-            }
-        },
-        "GIF" => {
-            if allow_gif {
-                if let Some(output_path) =
-                    get_output_path(force, sc, overwriting, input_path, output_path)?
-                {
-                    let mut config = image_convert::GIFConfig::new();
+        write_status_file(args, &progress, summary)?;
+    }
 
-                    config.remain_profile = remain_profile;
-                    config.width = side_maximum;
-                    config.height = side_maximum;
-                    config.shrink_only = only_shrink;
+    Ok(())
+}
 
-                    if !sharpen {
-                        config.sharpen = 0f64;
-                    }
+fn record_image(
+    args: &CLIArgs,
+    summary: &Mutex<Summary>,
+    image_path: PathBuf,
+    result: anyhow::Result<Outcome>,
+    mut report: ImageReport,
+) -> io::Result<()> {
+    let record = if args.report.is_some() {
+        Some(FileRecord::new(args, &image_path, result.as_ref(), &report))
+    } else {
+        None
+    };
 
-                    let mut output = image_convert::ImageResource::from_path(output_path);
+    let mut summary = summary.lock().unwrap();
 
-                    image_convert::to_gif(&mut output, &input_image_resource, &config)
-                        .with_context(|| anyhow!("to_gif {output_path:?}"))?;
+    summary.files.extend(record);
 
-                    print_resized_message(output_path)?;
-                }
-            }
-        },
-        _ => (),
+    if let Ok(Outcome::Resized {
+        ..
+    }) = result
+    {
+        if args.timings {
+            report.print(Line::Stderr(format!(
+                "{}: {}\n",
+                console::quoted(&image_path),
+                report.timings
+            )))?;
+
+            summary.record_timings(image_path.clone(), report.timings);
+        }
+
+        if let Some(description) = report.stripped_profile.take() {
+            report.print(Line::Stderr(format!(
+                "{} had an embedded {description:?} ICC profile, which has been stripped without \
+                 converting the colors. Use --keep-metadata icc to keep it.\n",
+                console::quoted(&image_path)
+            )))?;
+
+            summary.record_stripped_profile(image_path.clone(), description);
+        }
+    }
+
+    summary.record(image_path.clone(), result);
+
+    if let Some(ordered_output) = summary.ordered_output.as_mut() {
+        ordered_output.finish(image_path, report.lines)?;
     }
 
     Ok(())
 }
 
-fn get_output_path<'a>(
-    force: bool,
-    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
-    overwriting: &Arc<Mutex<u8>>,
-    input_path: &'a Path,
-    output_path: Option<&'a Path>,
-) -> anyhow::Result<Option<&'a Path>> {
-    match output_path {
+fn write_status_file(
+    args: &CLIArgs,
+    progress: &Progress,
+    summary: &Mutex<Summary>,
+) -> io::Result<()> {
+    if let Some(status_file) = args.status_file.as_deref() {
+        let data = serde_json::to_vec_pretty(&progress.status(&summary.lock().unwrap())).unwrap();
+
+        if let Err(error) = processing::write_atomically(status_file, &data, None) {
+            Line::Stderr(format!("{}: {error}\n", console::quoted(status_file))).print()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn directory_output_path(args: &CLIArgs, image_path: &Path) -> Option<PathBuf> {
+    let output_path = match args.output_path.as_ref() {
         Some(output_path) => {
-            if output_path.exists() {
-                if !force {
-                    let mutex_guard = overwriting.lock().unwrap();
+            let p = pathdiff::diff_paths(image_path, args.input_path.as_path()).unwrap();
 
-                    loop {
-                        print!("{output_path:?} exists, do you want to overwrite it? [Y/N] ",);
-                        io::stdout().flush().with_context(|| anyhow!("stdout"))?;
+            let output_path = output_path.join(p);
 
-                        match sc.lock().unwrap().next_line().with_context(|| anyhow!("stdout"))? {
-                            Some(token) => match token.to_ascii_uppercase().as_str() {
-                                "Y" => {
-                                    break;
-                                },
-                                "N" => {
-                                    return Ok(None);
-                                },
-                                _ => {
-                                    continue;
-                                },
-                            },
-                            None => {
-                                return Ok(None);
-                            },
-                        }
-                    }
+            Some(output_path)
+        },
+        None => None,
+    };
 
-                    drop(mutex_guard);
-                }
-            } else {
-                let dir_path = output_path.parent().unwrap();
+    if args.has_affixes() {
+        return Some(affixed_output_path(args, output_path.as_deref().unwrap_or(image_path)));
+    }
 
-                fs::create_dir_all(dir_path).with_context(|| anyhow!("{dir_path:?}"))?;
-            }
+    output_path
+}
 
-            Ok(Some(output_path))
+// an output path given for a single image is used as it is, without --prefix and --suffix, unless
+// it is a directory, inside which the output keeps the file name of the image as cp does
+fn single_output_path(args: &CLIArgs) -> Option<PathBuf> {
+    match args.output_path.as_ref() {
+        Some(output_path) if is_directory_path(output_path) => {
+            let output_path = output_path.join(args.input_path.file_name().unwrap_or_default());
+
+            if args.has_affixes() {
+                Some(affixed_output_path(args, &output_path))
+            } else {
+                Some(output_path)
+            }
         },
-        None => Ok(Some(input_path)),
+        Some(output_path) => Some(output_path.clone()),
+        None if args.has_affixes() => Some(affixed_output_path(args, &args.input_path)),
+        None => None,
     }
 }
 
+// an existing directory, or a path ending with a separator such as out/, which is created
+#[inline]
+fn is_directory_path(path: &Path) -> bool {
+    path.is_dir() || path.as_os_str().to_string_lossy().ends_with(std::path::is_separator)
+}
+
 #[inline]
-fn print_resized_message<P: AsRef<Path>>(path: P) -> anyhow::Result<()> {
-    println!("{:?} has been resized.", path.as_ref().canonicalize().unwrap());
-    io::stdout().flush()?;
+fn affixed_output_path(args: &CLIArgs, path: &Path) -> PathBuf {
+    processing::affixed_path(
+        path,
+        args.prefix.as_deref().unwrap_or_default(),
+        args.suffix.as_deref().unwrap_or_default(),
+    )
+}
+
+fn walk_image_paths(args: &CLIArgs) -> anyhow::Result<JobQueue> {
+    let mut image_paths = JobQueue::default();
+
+    for p in walk(args) {
+        image_paths.push(p?).with_context(|| anyhow!("queue"))?;
+    }
+
+    Ok(image_paths)
+}
+
+// the images in the input directory, found lazily as it is walked
+fn walk(args: &CLIArgs) -> impl Iterator<Item = anyhow::Result<PathBuf>> + '_ {
+    let walk_dir = WalkDir::new(args.input_path.as_path());
+
+    let walk_dir = match args.max_depth {
+        Some(max_depth) => walk_dir.max_depth(max_depth as usize),
+        None => walk_dir,
+    };
+
+    // walk in a deterministic order, which does not depend on the file system
+    let walk_dir = if args.ordered_output { walk_dir.sort_by_file_name() } else { walk_dir };
+
+    let walk_dir = walk_dir.follow_links(args.follow_symlinks);
+
+    walk_dir.into_iter().filter_map(|e| e.ok()).filter_map(|dir_entry| {
+        if dir_entry.path_is_symlink() {
+            if args.symlinked_files == SymlinkedFiles::Skip {
+                return None;
+            }
+
+            // the entries of the links are not resolved unless they are followed
+            match fs::metadata(dir_entry.path()) {
+                Ok(metadata) if metadata.is_file() => (),
+                _ => return None,
+            }
+        } else {
+            match dir_entry.metadata() {
+                Ok(metadata) if metadata.is_file() => (),
+                Ok(_) => return None,
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+
+        let p = dir_entry.into_path();
+
+        if is_walked_image(args, &p) {
+            Some(Ok(p))
+        } else {
+            None
+        }
+    })
+}
+
+fn is_walked_image(args: &CLIArgs, p: &Path) -> bool {
+    // GIF images are walked even if they are not allowed, so that they are reported
+    let allowed = match p.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if is_image_extension(extension) => args.allows_extension(extension),
+        _ => args.sniff,
+    };
+
+    // the outputs of the last run are next to their images
+    let affixed = args.output_path.is_none()
+        && args.has_affixes()
+        && processing::is_affixed(
+            p,
+            args.prefix.as_deref().unwrap_or_default(),
+            args.suffix.as_deref().unwrap_or_default(),
+        );
+
+    allowed && !affixed
+}
+
+fn is_image_extension(extension: &str) -> bool {
+    extension.eq_ignore_ascii_case("icns")
+        || extension.eq_ignore_ascii_case("svg")
+        || formats::is_raw_extension(extension)
+        || formats::by_extension(extension).is_some()
+}
+
+// KTX2 textures are not known to ImageMagick, so their largest levels are decoded as DDS textures
+#[cfg(feature = "magick")]
+pub fn is_ktx2(p: &Path) -> bool {
+    cfg!(feature = "dds")
+        && p.extension().map(|extension| extension.eq_ignore_ascii_case("ktx2")).unwrap_or(false)
+}
+
+// a file which is walked by --sniff, and is only known to be an image once it has been identified
+pub fn is_sniffed(args: &CLIArgs, p: &Path) -> bool {
+    args.sniff
+        && !p.extension().and_then(|extension| extension.to_str()).is_some_and(is_image_extension)
+}
+
+// the images are resized while the input directory is walked, unless all of them have to be known
+// before the first one is resized
+fn is_streamed(args: &CLIArgs) -> bool {
+    args.retry_failed.is_none()
+        && args.files_from0.is_none()
+        && args.files_from.is_none()
+        && args.coordinator.is_none()
+        && args.preflight.is_none()
+        && !args.ordered_output
+        && !args.identify_first
+        && args.priority_glob.is_empty()
+        && !(args.progress || args.progress_bar || args.json_stream || args.status_file.is_some())
+}
+
+// walks the input directory on another thread, which is blocked once the channel is full, so
+// that the paths waiting to be resized take a constant amount of memory
+fn stream_image_paths(
+    args: &Arc<CLIArgs>,
+    summary: &Arc<Mutex<Summary>>,
+    bound: usize,
+) -> anyhow::Result<Receiver<anyhow::Result<PathBuf>>> {
+    let skip_list = read_skip_list(args)?;
+
+    let (sender, receiver) = mpsc::sync_channel(bound);
+
+    let args = args.clone();
+    let summary = summary.clone();
+
+    thread::spawn(move || {
+        for p in walk(&args) {
+            if let Ok(p) = p.as_ref() {
+                if !is_selected(&args, skip_list.as_ref(), p) {
+                    continue;
+                }
+
+                summary.lock().unwrap().total += 1;
+            }
+
+            // the receiver has been dropped because of an error
+            if sender.send(p).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+fn read_skip_list(args: &CLIArgs) -> anyhow::Result<Option<SkipList>> {
+    match args.skip_list.as_deref() {
+        Some(skip_list) => {
+            Ok(Some(SkipList::read(skip_list).with_context(|| anyhow!("{skip_list:?}"))?))
+        },
+        None => Ok(None),
+    }
+}
+
+// keeps the images inside the subtrees given by --only, and then drops the ones in the skip list
+// and the ones completed by the resumed run
+fn is_selected(args: &CLIArgs, skip_list: Option<&SkipList>, p: &Path) -> bool {
+    if !args.only.is_empty() {
+        let relative_path = p.strip_prefix(&args.input_path).unwrap_or(p);
+
+        if !args.only.iter().any(|only| relative_path.starts_with(only) || p.starts_with(only)) {
+            return false;
+        }
+    }
+
+    if skip_list.is_some_and(|skip_list| skip_list.matches(&args.input_path, p)) {
+        return false;
+    }
+
+    !state::is_completed(args, p)
+}
+
+fn select_image_paths(args: &CLIArgs, mut image_paths: JobQueue) -> anyhow::Result<JobQueue> {
+    let skip_list = read_skip_list(args)?;
+
+    // the globs have been checked by clap
+    let patterns: Vec<Pattern> =
+        args.priority_glob.iter().map(|glob| Pattern::new(glob).unwrap()).collect();
+
+    // one queue per tier of --priority-glob and one for the other images, which are appended in
+    // that order, so that the images of a tier keep the order in which they were walked
+    let mut tiers: Vec<JobQueue> = (0..=patterns.len()).map(|_| JobQueue::default()).collect();
+
+    for p in image_paths.iter().with_context(|| anyhow!("queue"))? {
+        let p = p.with_context(|| anyhow!("queue"))?;
+
+        if !is_selected(args, skip_list.as_ref(), &p) {
+            continue;
+        }
+
+        let file_name = p.file_name().map(Path::new);
+
+        let tier = patterns
+            .iter()
+            .position(|pattern| {
+                skip_list::glob_matches(pattern, &args.input_path, &p)
+                    || file_name.is_some_and(|file_name| {
+                        pattern.matches_path_with(file_name, skip_list::MATCH_OPTIONS)
+                    })
+            })
+            .unwrap_or(patterns.len());
+
+        tiers[tier].push(p).with_context(|| anyhow!("queue"))?;
+    }
+
+    let mut tiers = tiers.into_iter();
+
+    let mut selected = tiers.next().unwrap();
+
+    for mut tier in tiers {
+        for p in tier.iter().with_context(|| anyhow!("queue"))? {
+            selected
+                .push(p.with_context(|| anyhow!("queue"))?)
+                .with_context(|| anyhow!("queue"))?;
+        }
+    }
+
+    Ok(selected)
+}
+
+fn resizing<IP: AsRef<Path>, OP: AsRef<Path>>(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    input_path: IP,
+    output_path: Option<OP>,
+    report: &mut ImageReport,
+) -> anyhow::Result<Outcome> {
+    let input_path = input_path.as_ref();
+    let output_path = output_path.as_ref().map(|p| p.as_ref());
+
+    // a sniffed file, such as an HTML page, is not an image named like one
+    if !is_sniffed(args, input_path) {
+        if let Some(placeholder) =
+            placeholder::detect(input_path).with_context(|| anyhow!("{input_path:?}"))?
+        {
+            return skipping_placeholder(args, input_path, placeholder, report);
+        }
+    }
+
+    let key = match args.cache {
+        Some(_) => Some(cache::Key::new(input_path).with_context(|| anyhow!("{input_path:?}"))?),
+        None => None,
+    };
+
+    if key.as_ref().is_some_and(cache::contains) {
+        return Ok(Outcome::Skipped(SkipReason::Cached));
+    }
+
+    let outcome = if args.destinations.is_empty() {
+        resizing_image(args, sc, overwriting, input_path, None, output_path, report)?
+    } else {
+        resizing_to_destinations(args, sc, overwriting, input_path, report)?
+    };
+
+    if let (
+        Some(key),
+        Outcome::Resized {
+            ..
+        },
+    ) = (key, outcome)
+    {
+        let outputs = report.outputs.iter().map(|(path, _)| path.clone()).collect();
+
+        cache::insert(key, outputs).with_context(|| anyhow!("{input_path:?}"))?;
+    }
+
+    Ok(outcome)
+}
+
+fn skipping_placeholder(
+    args: &CLIArgs,
+    input_path: &Path,
+    placeholder: placeholder::Placeholder,
+    report: &mut ImageReport,
+) -> anyhow::Result<Outcome> {
+    let message = match args.placeholders {
+        PlaceholderAction::Skip => {
+            let message = format!(
+                "{} has been skipped because {placeholder}.\n",
+                console::quoted(input_path)
+            );
+
+            print_skipped_message(args, report, message)?;
+
+            return Ok(Outcome::Skipped(placeholder.skip_reason()));
+        },
+        PlaceholderAction::Delete => {
+            fs::remove_file(input_path).with_context(|| anyhow!("{input_path:?}"))?;
+
+            format!("{} has been deleted because {placeholder}.\n", console::quoted(input_path))
+        },
+        PlaceholderAction::Quarantine => {
+            let quarantine_path = placeholder::quarantine(args, input_path)?;
+
+            format!(
+                "{} has been moved to {} because {placeholder}.\n",
+                console::quoted(input_path),
+                console::quoted(&quarantine_path)
+            )
+        },
+    };
+
+    report.print(Line::Stderr(message))?;
+
+    Ok(Outcome::Skipped(placeholder.skip_reason()))
+}
+
+// an image is decoded once for all of the directories of --out, and then resized with the options
+// of each of them
+fn resizing_to_destinations(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    input_path: &Path,
+    report: &mut ImageReport,
+) -> anyhow::Result<Outcome> {
+    #[cfg(feature = "magick")]
+    let decoded = Timings::measure(&mut report.timings.decode, || decode_once(args, input_path))
+        .with_context(|| anyhow!("{input_path:?}"))?;
+
+    #[cfg(not(feature = "magick"))]
+    let decoded = None;
+
+    let mut outcome = None;
+
+    for (destination, options) in args.destinations.iter().zip(args.destination_args.iter()) {
+        // a single image keeps its file name inside the directory
+        let output_path = if input_path == args.input_path {
+            single_output_path(options)
+        } else {
+            directory_output_path(options, input_path)
+        };
+
+        let result = resizing_image(
+            options,
+            sc,
+            overwriting,
+            input_path,
+            decoded.as_ref(),
+            output_path.as_deref(),
+            report,
+        )
+        .with_context(|| anyhow!("--out {}", destination.name))?;
+
+        // the image counts as resized if it has been resized into any of the directories
+        outcome = match (outcome, result) {
+            (
+                Some(
+                    resized @ Outcome::Resized {
+                        ..
+                    },
+                ),
+                _,
+            )
+            | (
+                Some(_),
+                resized @ Outcome::Resized {
+                    ..
+                },
+            )
+            | (None, resized) => Some(resized),
+            (skipped, _) => skipped,
+        };
+    }
+
+    Ok(outcome.unwrap())
+}
+
+// an image decoded once for all of the directories of --out, which only ImageMagick does
+#[cfg(feature = "magick")]
+type Decoded = MagickWand;
+#[cfg(not(feature = "magick"))]
+type Decoded = Infallible;
+
+// None for the images which are read again for each directory, such as the SVG images, which are
+// rasterized at the size of each of them
+#[cfg(feature = "magick")]
+fn decode_once(args: &CLIArgs, input_path: &Path) -> anyhow::Result<Option<MagickWand>> {
+    #[cfg(feature = "image-backend")]
+    if args.backend == Backend::Image {
+        return Ok(None);
+    }
+
+    let is_icns = input_path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("icns"))
+        .unwrap_or(false);
+
+    if args.icns || is_icns || is_ktx2(input_path) {
+        return Ok(None);
+    }
+
+    let input_image_resource = image_convert::ImageResource::from_path(input_path);
+
+    let ping = match processing::ping(&input_image_resource) {
+        Ok(mw) => image_convert::ImageResource::MagickWand(mw),
+        // skipped by `resizing_image`
+        Err(_) if is_sniffed(args, input_path) => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    let format = image_convert::identify_ping(&ping)?.format;
+
+    if format == "SVG" || formats::RAW_FORMATS.contains(&format.as_str()) {
+        return Ok(None);
+    }
+
+    Ok(processing::decode(input_image_resource)?.into_magick_wand())
+}
+
+fn resizing_image(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    input_path: &Path,
+    decoded: Option<&Decoded>,
+    output_path: Option<&Path>,
+    report: &mut ImageReport,
+) -> anyhow::Result<Outcome> {
+    if args.newer_only {
+        if let Some(output_path) = output_path {
+            if processing::is_newer(output_path, input_path) {
+                return Ok(Outcome::Skipped(SkipReason::UpToDate));
+            }
+        }
+    }
+
+    if !args.chmod_writable {
+        if let Some(path) = processing::read_only_path(output_path.unwrap_or(input_path)) {
+            report.print(Line::Stderr(format!(
+                "{} is read-only, so {} has not been resized. Use --chmod-writable to write it \
+                 anyway.\n",
+                console::quoted(path),
+                console::quoted(input_path)
+            )))?;
+
+            return Ok(Outcome::PermissionDenied);
+        }
+    }
+
+    #[cfg(not(feature = "magick"))]
+    {
+        let _ = decoded;
+
+        image_backend::resizing(args, sc, overwriting, input_path, output_path, report)
+    }
+
+    #[cfg(feature = "magick")]
+    {
+        #[cfg(feature = "image-backend")]
+        if args.backend == Backend::Image {
+            return image_backend::resizing(args, sc, overwriting, input_path, output_path, report);
+        }
+
+        resizing_with_magick(args, sc, overwriting, input_path, decoded, output_path, report)
+    }
+}
+
+#[cfg(feature = "magick")]
+fn resizing_with_magick(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    input_path: &Path,
+    decoded: Option<&MagickWand>,
+    output_path: Option<&Path>,
+    report: &mut ImageReport,
+) -> anyhow::Result<Outcome> {
+    let is_icns = input_path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("icns"))
+        .unwrap_or(false);
+
+    let input_image_resource = match decoded {
+        Some(mw) => image_convert::ImageResource::MagickWand(mw.clone()),
+        None if is_icns => {
+            let data = fs::read(input_path).with_context(|| anyhow!("{input_path:?}"))?;
+
+            let image = icns::largest_image(&data).with_context(|| anyhow!("{input_path:?}"))?;
+
+            image_convert::ImageResource::Data(image.to_vec())
+        },
+        #[cfg(feature = "dds")]
+        None if is_ktx2(input_path) => {
+            let data = fs::read(input_path).with_context(|| anyhow!("{input_path:?}"))?;
+
+            let texture = texture::ktx2_to_dds(&data).with_context(|| anyhow!("{input_path:?}"))?;
+
+            image_convert::ImageResource::Data(texture)
+        },
+        None => image_convert::ImageResource::from_path(input_path),
+    };
+
+    let ping = match processing::ping(&input_image_resource) {
+        Ok(mw) => image_convert::ImageResource::MagickWand(mw),
+        // ImageMagick cannot identify the files which are not images
+        Err(_) if is_sniffed(args, input_path) => {
+            print_skipped_message(
+                args,
+                report,
+                format!(
+                    "{} has been skipped: it could not be identified as an image.\n",
+                    console::quoted(input_path)
+                ),
+            )?;
+
+            return Ok(Outcome::Skipped(SkipReason::UnsupportedFormat));
+        },
+        Err(error) => return Err(error).with_context(|| anyhow!("{input_path:?}")),
+    };
+
+    let input_identify =
+        image_convert::identify_ping(&ping).with_context(|| anyhow!("{input_path:?}"))?;
+
+    if !args.remains_profile() && !args.keeps_icc() {
+        report.stripped_profile = processing::non_srgb_profile(ping.as_magick_wand().unwrap());
+    }
+
+    if args.icns || is_icns {
+        let args = args.for_size(input_identify.resolution.width, input_identify.resolution.height);
+        let args = &*args;
+
+        let icns_path = match output_path {
+            Some(output_path) => Some(output_path.with_extension("icns")),
+            None if is_icns => None,
+            None => Some(input_path.with_extension("icns")),
+        };
+
+        if let Some(output_path) =
+            get_output_path(args, sc, overwriting, input_path, icns_path.as_deref())?
+        {
+            let input_image_resource = Timings::measure(&mut report.timings.decode, || {
+                processing::decode(input_image_resource)
+            })
+            .with_context(|| anyhow!("{input_path:?}"))?;
+
+            let input_image_resource = Timings::measure(&mut report.timings.resize, || {
+                processing::adjust(args, input_image_resource)
+            })
+            .with_context(|| anyhow!("adjust {input_path:?}"))?;
+
+            // the largest square which fits inside the box
+            let max_size = match args.max_size() {
+                (0, side) | (side, 0) => side,
+                (width, height) => width.min(height),
+            };
+
+            let max_size = if args.only_shrink {
+                let side = input_identify.resolution.width.max(input_identify.resolution.height);
+
+                max_size.min(side.try_into().unwrap_or(u16::MAX))
+            } else {
+                max_size
+            };
+
+            // each size is resized while it is encoded
+            let data = Timings::measure(&mut report.timings.encode, || {
+                icns::build(max_size, |size| {
+                    let mut config = image_convert::PNGConfig::new();
+
+                    config.remain_profile = args.remains_profile();
+                    config.width = size;
+                    config.height = size;
+                    config.crop = Some(image_convert::Crop::Center(1f64, 1f64));
+
+                    if let Some(sharpen) = args.sharpening() {
+                        config.sharpen = sharpen;
+                    }
+
+                    let mut output = image_convert::ImageResource::with_capacity(0);
+
+                    image_convert::to_png(&mut output, &input_image_resource, &config)?;
+
+                    Ok(output.into_vec().unwrap())
+                })
+            })
+            .with_context(|| anyhow!("to_icns {output_path:?}"))?;
+
+            Timings::measure(&mut report.timings.encode, || {
+                write_rendition(args, &mut report.outputs, output_path, &["icns"], &data, None)
+            })
+            .with_context(|| anyhow!("write {output_path:?}"))?;
+
+            print_resized_message(args, report, output_path)?;
+
+            return Ok(Outcome::Resized {
+                width:  input_identify.resolution.width,
+                height: input_identify.resolution.height,
+            });
+        }
+
+        return Ok(Outcome::Skipped(SkipReason::OverwriteDeclined));
+    }
+
+    let format = if is_ktx2(input_path) { "KTX2" } else { input_identify.format.as_str() };
+
+    let is_svg = format == "SVG";
+    let is_raw = formats::RAW_FORMATS.contains(&format);
+
+    if !args.allows_format(format) {
+        print_skipped_message(
+            args,
+            report,
+            format!(
+                "{} has been skipped: its format {format} is excluded by --formats or \
+                 --skip-formats.\n",
+                console::quoted(input_path)
+            ),
+        )?;
+
+        return Ok(Outcome::Skipped(SkipReason::FormatExcluded));
+    }
+
+    match formats::by_name(format) {
+        Some(handler) if !handler.is_allowed(args) => {
+            print_skipped_message(
+                args,
+                report,
+                format!(
+                    "{} has been skipped: GIF processing is disabled. Use --allow-gif or \
+                     --extensions gif to resize it.\n",
+                    console::quoted(input_path)
+                ),
+            )?;
+
+            return Ok(Outcome::Skipped(SkipReason::GifNotAllowed));
+        },
+        None if !is_svg && !is_raw => {
+            print_skipped_message(
+                args,
+                report,
+                format!(
+                    "{} has been skipped: its format {format} is not supported.\n",
+                    console::quoted(input_path)
+                ),
+            )?;
+
+            return Ok(Outcome::Skipped(SkipReason::UnsupportedFormat));
+        },
+        _ => (),
+    }
+
+    let (width, height) = (input_identify.resolution.width, input_identify.resolution.height);
+
+    let args = rules::apply(args.for_format(format), |attribute| {
+        processing::image_attribute(
+            ping.as_magick_wand().unwrap(),
+            format,
+            width,
+            height,
+            attribute,
+        )
+    });
+
+    if args.below_minimum == BelowMinimum::Skip && args.is_below_minimum(width, height) {
+        print_skipped_message(
+            &args,
+            report,
+            format!(
+                "{} has been skipped: its long side is shorter than --side-minimum \
+                 ({width}x{height}).\n",
+                console::quoted(input_path)
+            ),
+        )?;
+
+        return Ok(Outcome::Skipped(SkipReason::BelowMinimum));
+    }
+
+    let args = args.for_size(width, height);
+    let args = &*args;
+
+    print_chosen_options(args, report, input_path, format)?;
+
+    let input_image_resource = if is_svg {
+        // rasterized for the largest rendition, so that --dpr renditions stay sharp
+        let dpr = u16::from(args.dpr.iter().copied().max().unwrap_or(1));
+
+        let (max_width, max_height) = args.max_size();
+
+        let mw = Timings::measure(&mut report.timings.decode, || {
+            processing::rasterize_svg(
+                &input_image_resource,
+                args.density,
+                (max_width.saturating_mul(dpr), max_height.saturating_mul(dpr)),
+                args.only_shrink,
+            )
+        })
+        .with_context(|| anyhow!("{input_path:?}"))?;
+
+        image_convert::ImageResource::MagickWand(mw)
+    } else {
+        input_image_resource
+    };
+
+    // HEIC images are transcoded to JPEG next to their outputs if they can only be decoded, as well
+    // as camera RAW images, and SVG images are saved as PNG
+    let converted_path;
+
+    let (format, output_path) = if is_raw || (format == "HEIC" && !processing::can_encode_heic()) {
+        converted_path = output_path.unwrap_or(input_path).with_extension("jpg");
+
+        ("JPEG", Some(converted_path.as_path()))
+    } else if is_svg || (format == "BMP" && args.bmp_to_png) {
+        converted_path = output_path.unwrap_or(input_path).with_extension("png");
+
+        ("PNG", Some(converted_path.as_path()))
+    } else if is_sniffed(args, input_path) {
+        // named after the format which it has been identified as, instead of its own extension
+        let extension = formats::by_name(format).unwrap().extensions[0];
+
+        converted_path = output_path.unwrap_or(input_path).with_extension(extension);
+
+        (format, Some(converted_path.as_path()))
+    } else {
+        (format, output_path)
+    };
+
+    // animated WebP and GIF images are decoded with all of their frames, instead of only the first
+    // one
+    let input_image_resource = if format == "WEBP" || format == "GIF" {
+        let mut mw = Timings::measure(&mut report.timings.decode, || {
+            processing::decode(input_image_resource)
+        })
+        .with_context(|| anyhow!("{input_path:?}"))?
+        .into_magick_wand()
+        .unwrap();
+
+        if processing::frame_count(&mw) > 1 {
+            let (animation_format, extension, animation_path) = match (format, args.convert) {
+                ("WEBP", Some(ConvertFormat::Gif)) => {
+                    ("GIF", "gif", Some(output_path.unwrap_or(input_path).with_extension("gif")))
+                },
+                ("WEBP", None) => ("WEBP", "webp", output_path.map(Path::to_path_buf)),
+                _ => ("GIF", "gif", output_path.map(Path::to_path_buf)),
+            };
+
+            if let Some(output_path) =
+                get_output_path(args, sc, overwriting, input_path, animation_path.as_deref())?
+            {
+                let timing = Timing::of(&mut mw).with_context(|| anyhow!("{input_path:?}"))?;
+
+                let animation = Timings::measure(&mut report.timings.resize, || {
+                    processing::resize_animation(args, mw, animation_format, &timing)
+                })
+                .with_context(|| anyhow!("resize {input_path:?}"))?;
+
+                write_resized(args, report, output_path, &[extension], Some(input_path), || {
+                    let data = animation
+                        .write_images_blob(animation_format)
+                        .with_context(|| anyhow!("to_{extension} {output_path:?}"))?;
+
+                    if args.verify_animation {
+                        timing.verify(&data).with_context(|| anyhow!("verify {output_path:?}"))?;
+                    }
+
+                    Ok(data)
+                })?;
+
+                return Ok(Outcome::Resized {
+                    width:  input_identify.resolution.width,
+                    height: input_identify.resolution.height,
+                });
+            }
+
+            return Ok(Outcome::Skipped(SkipReason::OverwriteDeclined));
+        }
+
+        image_convert::ImageResource::MagickWand(mw)
+    } else {
+        input_image_resource
+    };
+
+    let (input_image_resource, width, height) = if format == "ICO" {
+        let frame = Timings::measure(&mut report.timings.decode, || {
+            processing::select_frame(&input_image_resource, args.ico_frame)
+        })
+        .with_context(|| anyhow!("{input_path:?}"))?;
+
+        let (width, height) = (frame.get_image_width() as u32, frame.get_image_height() as u32);
+
+        (image_convert::ImageResource::MagickWand(frame), width, height)
+    } else if is_svg {
+        let mw = input_image_resource.as_magick_wand().unwrap();
+
+        let (width, height) = (mw.get_image_width() as u32, mw.get_image_height() as u32);
+
+        (input_image_resource, width, height)
+    } else {
+        (input_image_resource, input_identify.resolution.width, input_identify.resolution.height)
+    };
+
+    let resized = Outcome::Resized {
+        width,
+        height,
+    };
+
+    let handler = formats::by_name(format).unwrap();
+
+    let (max_width, max_height) = args.max_size();
+
+    let (output_width, output_height) =
+        processing::compute_output_size(args.only_shrink, width, height, max_width, max_height)
+            .unwrap_or((width, height));
+
+    if let Some(tile) =
+        args.tile.filter(|tile| output_width > u32::from(*tile) || output_height > u32::from(*tile))
+    {
+        let output_path = output_path.unwrap_or(input_path);
+
+        let (resized_image, timings) =
+            decode_adjust_resize(args, input_path, input_image_resource, max_width, max_height)?;
+
+        report.timings.add(&timings);
+
+        let mw = resized_image.as_magick_wand().unwrap();
+
+        let tile = usize::from(tile);
+        let overlap = usize::from(args.tile_overlap);
+
+        for (row, (y, tile_height)) in
+            processing::tile_offsets(mw.get_image_height(), tile, overlap).into_iter().enumerate()
+        {
+            for (column, (x, tile_width)) in
+                processing::tile_offsets(mw.get_image_width(), tile, overlap)
+                    .into_iter()
+                    .enumerate()
+            {
+                let tile_path = processing::tile_path(output_path, row, column);
+
+                if let Some(tile_path) =
+                    get_output_path(args, sc, overwriting, &tile_path, Some(&tile_path))?
+                {
+                    write_resized(args, report, tile_path, handler.extensions, None, || {
+                        let tile_mw = processing::crop(mw, tile_width, tile_height, x, y)
+                            .with_context(|| anyhow!("crop {tile_path:?}"))?;
+
+                        encode(
+                            args,
+                            format,
+                            &image_convert::ImageResource::MagickWand(tile_mw),
+                            tile_path,
+                            Encoding::encoding_only(args.quality),
+                        )
+                    })?;
+                }
+            }
+        }
+
+        return Ok(resized);
+    }
+
+    if !args.quality_ladder.is_empty() && handler.lossy {
+        let output_path = output_path.unwrap_or(input_path);
+
+        // decode, adjust, resize and sharpen once, and then only encode for each quality
+        let (input_image_resource, timings) =
+            decode_adjust_resize(args, input_path, input_image_resource, max_width, max_height)?;
+
+        report.timings.add(&timings);
+
+        for quality in args.quality_ladder.iter().copied() {
+            let ladder_path = processing::ladder_path(&args.ladder_name, output_path, quality);
+
+            if let Some(ladder_path) =
+                get_output_path(args, sc, overwriting, &ladder_path, Some(&ladder_path))?
+            {
+                write_resized(args, report, ladder_path, handler.extensions, None, || {
+                    encode(
+                        args,
+                        format,
+                        &input_image_resource,
+                        ladder_path,
+                        Encoding::encoding_only(quality),
+                    )
+                })?;
+            }
+        }
+
+        return Ok(resized);
+    }
+
+    if let Some(target_size) = args.target_size.filter(|_| matches!(format, "JPEG" | "WEBP")) {
+        if let Some(output_path) = get_output_path(args, sc, overwriting, input_path, output_path)?
+        {
+            // decode, adjust, resize and sharpen once, and then only encode for each quality tried
+            let (input_image_resource, timings) = decode_adjust_resize(
+                args,
+                input_path,
+                input_image_resource,
+                max_width,
+                max_height,
+            )?;
+
+            report.timings.add(&timings);
+
+            // the image has been resized and sharpened, so it is only encoded
+            let encoding = Encoding::encoding_only(args.quality);
+
+            // the size and the quality of an output which is still larger than the target
+            let mut oversized = None;
+
+            write_resized(args, report, output_path, handler.extensions, Some(input_path), || {
+                let (data, quality) = handler
+                    .encode_within(args, &input_image_resource, encoding, target_size)
+                    .with_context(|| {
+                        anyhow!("to_{} {output_path:?}", format.to_ascii_lowercase())
+                    })?;
+
+                if data.len() as u64 > target_size {
+                    oversized = Some((data.len(), quality));
+                }
+
+                Ok(data)
+            })?;
+
+            if let Some((bytes, quality)) = oversized {
+                report.print(Line::Stderr(format!(
+                    "{} is {bytes} bytes even with a quality of {quality}, which is more than \
+                     {target_size} bytes.\n",
+                    console::quoted(output_path)
+                )))?;
+            }
+
+            return Ok(resized);
+        }
+
+        return Ok(Outcome::Skipped(SkipReason::OverwriteDeclined));
+    }
+
+    if !args.dpr.is_empty() {
+        let output_path = output_path.unwrap_or(input_path);
+
+        // decode once, and then adjust and resize from the full resolution for each ratio
+        let mw = Timings::measure(&mut report.timings.decode, || {
+            processing::decode(input_image_resource)
+        })
+        .with_context(|| anyhow!("{input_path:?}"))?
+        .into_magick_wand()
+        .unwrap();
+
+        for dpr in args.dpr.iter().copied() {
+            let dpr_path = processing::dpr_path(output_path, dpr);
+
+            if let Some(dpr_path) =
+                get_output_path(args, sc, overwriting, &dpr_path, Some(&dpr_path))?
+            {
+                let (resized_image, timings) = decode_adjust_resize(
+                    args,
+                    input_path,
+                    image_convert::ImageResource::MagickWand(mw.clone()),
+                    max_width.saturating_mul(u16::from(dpr)),
+                    max_height.saturating_mul(u16::from(dpr)),
+                )?;
+
+                report.timings.add(&timings);
+
+                write_resized(args, report, dpr_path, handler.extensions, None, || {
+                    encode(
+                        args,
+                        format,
+                        &resized_image,
+                        dpr_path,
+                        Encoding::encoding_only(args.quality),
+                    )
+                })?;
+            }
+        }
+
+        return Ok(resized);
+    }
+
+    if !args.sizes.is_empty() {
+        let output_path = output_path.unwrap_or(input_path);
+
+        // decode once, and then adjust and resize from the full resolution for each size
+        let mw = Timings::measure(&mut report.timings.decode, || {
+            processing::decode(input_image_resource)
+        })
+        .with_context(|| anyhow!("{input_path:?}"))?
+        .into_magick_wand()
+        .unwrap();
+
+        for size in args.sizes.iter().copied() {
+            let size_path = processing::size_path(&args.size_name, output_path, size);
+
+            if let Some(size_path) =
+                get_output_path(args, sc, overwriting, &size_path, Some(&size_path))?
+            {
+                let (resized_image, timings) = decode_adjust_resize(
+                    args,
+                    input_path,
+                    image_convert::ImageResource::MagickWand(mw.clone()),
+                    size,
+                    size,
+                )?;
+
+                report.timings.add(&timings);
+
+                write_resized(args, report, size_path, handler.extensions, None, || {
+                    encode(
+                        args,
+                        format,
+                        &resized_image,
+                        size_path,
+                        Encoding::encoding_only(args.quality),
+                    )
+                })?;
+            }
+        }
+
+        return Ok(resized);
+    }
+
+    if let Some(template) = args.output_template.as_deref() {
+        // the final size is only known once the image has been resized, so it is resized before
+        // asking whether to overwrite the output
+        let (resized_image, timings) =
+            decode_adjust_resize(args, input_path, input_image_resource, max_width, max_height)?;
+
+        report.timings.add(&timings);
+
+        let mw = resized_image.as_magick_wand().unwrap();
+
+        let template_path = processing::template_path(
+            template,
+            output_path.unwrap_or(input_path),
+            mw.get_image_width(),
+            mw.get_image_height(),
+        );
+
+        if let Some(output_path) =
+            get_output_path(args, sc, overwriting, &template_path, Some(&template_path))?
+        {
+            write_resized(args, report, output_path, handler.extensions, None, || {
+                encode(
+                    args,
+                    format,
+                    &resized_image,
+                    output_path,
+                    Encoding::encoding_only(args.quality),
+                )
+            })?;
+
+            return Ok(resized);
+        }
+
+        return Ok(Outcome::Skipped(SkipReason::OverwriteDeclined));
+    }
+
+    if let Some(output_path) = get_output_path(args, sc, overwriting, input_path, output_path)? {
+        if args.timings {
+            // decode, resize and encode as separate steps, so that each of them can be measured
+            let (input_image_resource, timings) = decode_adjust_resize(
+                args,
+                input_path,
+                input_image_resource,
+                max_width,
+                max_height,
+            )?;
+
+            report.timings.add(&timings);
+
+            write_resized(args, report, output_path, handler.extensions, Some(input_path), || {
+                encode(
+                    args,
+                    format,
+                    &input_image_resource,
+                    output_path,
+                    Encoding::encoding_only(args.quality),
+                )
+            })?;
+        } else {
+            let input_image_resource = processing::adjust(args, input_image_resource)
+                .with_context(|| anyhow!("adjust {input_path:?}"))?;
+
+            // resized while it is encoded
+            write_resized(args, report, output_path, handler.extensions, Some(input_path), || {
+                encode(
+                    args,
+                    format,
+                    &input_image_resource,
+                    output_path,
+                    Encoding::resizing(args, args.quality),
+                )
+            })?;
+        }
+
+        return Ok(resized);
+    }
+
+    Ok(Outcome::Skipped(SkipReason::OverwriteDeclined))
+}
+
+// the steps before encoding, which the renditions of the modes share, with the time which each of
+// them has taken
+#[cfg(feature = "magick")]
+fn decode_adjust_resize(
+    args: &CLIArgs,
+    input_path: &Path,
+    input_image_resource: image_convert::ImageResource,
+    width: u16,
+    height: u16,
+) -> anyhow::Result<(image_convert::ImageResource, Timings)> {
+    let mut timings = Timings::default();
+
+    let input_image_resource =
+        Timings::measure(&mut timings.decode, || processing::decode(input_image_resource))
+            .with_context(|| anyhow!("{input_path:?}"))?;
+
+    let mw = Timings::measure(&mut timings.resize, || {
+        let input_image_resource = processing::adjust(args, input_image_resource)
+            .with_context(|| anyhow!("adjust {input_path:?}"))?;
+
+        processing::resize_to(args, &input_image_resource, (width, height))
+            .with_context(|| anyhow!("resize {input_path:?}"))
+    })?;
+
+    Ok((image_convert::ImageResource::MagickWand(mw), timings))
+}
+
+#[cfg(feature = "magick")]
+fn encode(
+    args: &CLIArgs,
+    format: &str,
+    input_image_resource: &image_convert::ImageResource,
+    output_path: &Path,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<u8>> {
+    let handler = formats::by_name(format).unwrap();
+
+    let filtered = encoding
+        .filter(args, input_image_resource)
+        .with_context(|| anyhow!("resize {output_path:?}"))?;
+
+    let (input_image_resource, encoding) = match &filtered {
+        Some((filtered, encoding)) => (filtered, *encoding),
+        None => (input_image_resource, encoding),
+    };
+
+    (handler.encode)(args, input_image_resource, encoding)
+        .with_context(|| anyhow!("to_{} {output_path:?}", format.to_ascii_lowercase()))
+}
+
+// the renditions of the modes are encoded and written, which are timed together as encoding, and
+// the outputs which stand for their inputs can be replaced by them with --keep-smaller
+#[cfg(feature = "magick")]
+fn write_resized<F: FnOnce() -> anyhow::Result<Vec<u8>>>(
+    args: &CLIArgs,
+    report: &mut ImageReport,
+    output_path: &Path,
+    extensions: &[&str],
+    original: Option<&Path>,
+    encode: F,
+) -> anyhow::Result<()> {
+    Timings::measure(&mut report.timings.encode, || {
+        let data = encode()?;
+
+        write_rendition(args, &mut report.outputs, output_path, extensions, &data, original)
+            .with_context(|| anyhow!("write {output_path:?}"))
+    })?;
+
+    print_resized_message(args, report, output_path)
+}
+
+// with --link-duplicates, an output which is identical to another output of the same image is
+// hard-linked to it instead of being stored again
+fn write_rendition(
+    args: &CLIArgs,
+    outputs: &mut Vec<(PathBuf, u64)>,
+    output_path: &Path,
+    extensions: &[&str],
+    data: &[u8],
+    original: Option<&Path>,
+) -> anyhow::Result<()> {
+    let kept;
+
+    // with --keep-smaller, the input is copied through instead of an output which has grown, as
+    // long as they are in the same format
+    let data = match original.filter(|_| args.keep_smaller) {
+        Some(original) if fs::metadata(original)?.len() < data.len() as u64 => {
+            let same_format = original
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| extensions.iter().any(|e| extension.eq_ignore_ascii_case(e)))
+                .unwrap_or(false);
+
+            if same_format {
+                kept = fs::read(original)?;
+
+                kept.as_slice()
+            } else {
+                data
+            }
+        },
+        _ => data,
+    };
+
+    if args.chmod_writable {
+        return processing::with_writable(output_path, || {
+            write_rendition_inner(args, outputs, output_path, extensions, data)
+        });
+    }
+
+    write_rendition_inner(args, outputs, output_path, extensions, data)
+}
+
+fn write_rendition_inner(
+    args: &CLIArgs,
+    outputs: &mut Vec<(PathBuf, u64)>,
+    output_path: &Path,
+    extensions: &[&str],
+    data: &[u8],
+) -> anyhow::Result<()> {
+    let hash = processing::content_hash(data);
+
+    if args.link_duplicates {
+        for (path, _) in outputs.iter().filter(|(_, h)| *h == hash) {
+            if fs::read(path)? == data {
+                processing::link_output(path, output_path, extensions)?;
+
+                outputs.push((output_path.to_path_buf(), hash));
+
+                return Ok(());
+            }
+        }
+    }
+
+    processing::write_output(output_path, extensions, data, args.output_mode)?;
+
+    outputs.push((output_path.to_path_buf(), hash));
+
+    Ok(())
+}
+
+fn get_output_path<'a>(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    input_path: &'a Path,
+    output_path: Option<&'a Path>,
+) -> anyhow::Result<Option<&'a Path>> {
+    match output_path {
+        Some(output_path) => {
+            if output_path.exists() {
+                if !args.force {
+                    let mut answers = overwriting.lock().unwrap();
+
+                    let question = format!(
+                        "{} exists, do you want to overwrite it? [Y/N] ",
+                        console::quoted(&output_path)
+                    );
+
+                    // an answer given in advance is printed after the question, which is not asked
+                    match answers.take(output_path) {
+                        Some(answer) => {
+                            if !args.quiet {
+                                Line::Stdout(
+                                    format!("{question}{}\n", if answer { "Y" } else { "N" })
+                                        .into_bytes(),
+                                )
+                                .print()?;
+                            }
+
+                            if !answer {
+                                return Ok(None);
+                            }
+                        },
+                        None => loop {
+                            let answer =
+                                console::prompt(&question, || sc.lock().unwrap().next_line())
+                                    .with_context(|| anyhow!("stdout"))?;
+
+                            match answer.with_context(|| anyhow!("stdout"))? {
+                                Some(token) => match console::parse_answer(&token) {
+                                    Some(true) => {
+                                        break;
+                                    },
+                                    Some(false) => {
+                                        return Ok(None);
+                                    },
+                                    None => {
+                                        continue;
+                                    },
+                                },
+                                None => {
+                                    return Ok(None);
+                                },
+                            }
+                        },
+                    }
+
+                    drop(answers);
+                }
+            } else {
+                let dir_path = output_path.parent().unwrap();
+
+                processing::create_dir_all(dir_path, args.output_dir_mode)
+                    .with_context(|| anyhow!("{dir_path:?}"))?;
+            }
+
+            Ok(Some(output_path))
+        },
+        None => Ok(Some(input_path)),
+    }
+}
+
+// the notices of the skipped images are left out by --quiet, along with the messages of the resized
+// images
+#[inline]
+fn print_skipped_message(
+    args: &CLIArgs,
+    report: &mut ImageReport,
+    message: String,
+) -> anyhow::Result<()> {
+    if !args.quiet {
+        report.print(Line::Stderr(message))?;
+    }
+
+    Ok(())
+}
+
+// the options of an image once the format options, the rules and its size have been applied
+fn print_chosen_options(
+    args: &CLIArgs,
+    report: &mut ImageReport,
+    input_path: &Path,
+    format: &str,
+) -> anyhow::Result<()> {
+    if args.verbose >= 1 {
+        let (width, height) = args.max_size();
+
+        report.print(Line::Stderr(format!(
+            "{}: {format}, the maximum size {width}x{height}, the quality {}{}.\n",
+            console::quoted(input_path),
+            args.quality,
+            match args.sharpening() {
+                Some(sharpen) if sharpen <= 0f64 => ", without sharpening".to_string(),
+                Some(sharpen) => format!(", sharpened by {sharpen}"),
+                None => String::new(),
+            }
+        )))?;
+    }
+
+    if args.verbose >= 2 {
+        report.print(Line::Stderr(format!(
+            "{}: {}\n",
+            console::quoted(input_path),
+            serde_json::to_string(args)?
+        )))?;
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn print_resized_message<P: AsRef<Path>>(
+    args: &CLIArgs,
+    report: &mut ImageReport,
+    path: P,
+) -> anyhow::Result<()> {
+    if (args.quiet || args.json_stream) && !args.print0 {
+        return Ok(());
+    }
+
+    let path = path.as_ref().canonicalize().unwrap();
+
+    let mut data = Vec::new();
+
+    if args.print0 {
+        path_list::write_path0(&mut data, path)?;
+    } else {
+        writeln!(data, "{} has been resized.", console::quoted(&path))?;
+    }
+
+    report.print(Line::Stdout(data))?;
 
     Ok(())
 }
@@ -0,0 +1,59 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{cli::CLIArgs, summary::Summary};
+
+pub fn notify(args: &CLIArgs, summary: &Summary) -> anyhow::Result<()> {
+    let json = serde_json::to_string(summary)?;
+
+    if let Some(notify_url) = args.notify_url.as_deref() {
+        ureq::post(notify_url)
+            .set("Content-Type", "application/json")
+            .send_string(&json)
+            .with_context(|| anyhow!("{notify_url}"))?;
+    }
+
+    if let Some(notify_command) = args.notify_command.as_deref() {
+        let mut child = shell_command(notify_command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| anyhow!("{notify_command}"))?;
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(json.as_bytes())
+            .with_context(|| anyhow!("{notify_command}"))?;
+
+        let status = child.wait().with_context(|| anyhow!("{notify_command}"))?;
+
+        if !status.success() {
+            return Err(anyhow!("{notify_command:?} exited with {status}."));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+
+    shell.arg("/C").arg(command);
+
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+
+    shell.arg("-c").arg(command);
+
+    shell
+}
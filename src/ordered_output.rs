@@ -0,0 +1,36 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::PathBuf,
+};
+
+use crate::console::Line;
+
+#[derive(Debug)]
+pub struct OrderedOutput {
+    order:   VecDeque<PathBuf>,
+    pending: HashMap<PathBuf, Vec<Line>>,
+}
+
+impl OrderedOutput {
+    pub fn new<I: IntoIterator<Item = PathBuf>>(image_paths: I) -> OrderedOutput {
+        OrderedOutput {
+            order: image_paths.into_iter().collect(), pending: HashMap::new()
+        }
+    }
+
+    // the lines of an image are kept until the lines of every image before it have been printed
+    pub fn finish(&mut self, image_path: PathBuf, lines: Vec<Line>) -> io::Result<()> {
+        self.pending.insert(image_path, lines);
+
+        while let Some(lines) = self.order.front().and_then(|p| self.pending.remove(p)) {
+            self.order.pop_front();
+
+            for line in lines {
+                line.print()?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    cmp::Reverse,
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context};
+use image_convert::{
+    magick_rust::{bindings, MagickWand, PixelWand},
+    ImageResource,
+};
+use scanner_rust::{generic_array::typenum::U8, Scanner};
+use serde::Serialize;
+
+use crate::{
+    answers::Answers,
+    cli::CLIArgs,
+    console::{self, Line},
+    formats, get_output_path, path_list, processing,
+    summary::{Outcome, SkipReason, Summary},
+};
+
+#[derive(Debug, Serialize)]
+struct Atlas {
+    sheets:  Vec<Sheet>,
+    sprites: Vec<Sprite>,
+}
+
+#[derive(Debug, Serialize)]
+struct Sheet {
+    file:   String,
+    width:  usize,
+    height: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Sprite {
+    name:   String,
+    sheet:  usize,
+    x:      usize,
+    y:      usize,
+    width:  usize,
+    height: usize,
+}
+
+pub fn pack(
+    args: &CLIArgs,
+    sc: &Arc<Mutex<Scanner<io::Stdin, U8>>>,
+    overwriting: &Arc<Mutex<Answers>>,
+    summary: &Arc<Mutex<Summary>>,
+    image_paths: Vec<PathBuf>,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    let mut cells = Vec::with_capacity(image_paths.len());
+
+    for image_path in image_paths {
+        let disallowed = image_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(formats::by_extension)
+            .map(|handler| !handler.is_allowed(args))
+            .unwrap_or(false);
+
+        if disallowed {
+            if !args.quiet {
+                Line::Stderr(format!(
+                    "{} has been skipped: GIF processing is disabled.\n",
+                    console::quoted(&image_path)
+                ))
+                .print()?;
+            }
+
+            summary
+                .lock()
+                .unwrap()
+                .record(image_path, Ok(Outcome::Skipped(SkipReason::GifNotAllowed)));
+
+            continue;
+        }
+
+        let input_image_resource = processing::adjust(args, ImageResource::from_path(&image_path))
+            .with_context(|| anyhow!("adjust {image_path:?}"))?;
+
+        let mw = processing::resize(args, &input_image_resource)
+            .with_context(|| anyhow!("resize {image_path:?}"))?;
+
+        summary.lock().unwrap().record(
+            image_path.clone(),
+            Ok(Outcome::Resized {
+                width:  mw.get_image_width() as u32,
+                height: mw.get_image_height() as u32,
+            }),
+        );
+
+        cells.push((image_path, mw));
+    }
+
+    // place the cells row by row, from the tallest to the shortest
+    cells.sort_by_key(|(_, mw)| Reverse(mw.get_image_height()));
+
+    let sheet_size = args.sheet_size as usize;
+
+    let mut sheet_sizes: Vec<(usize, usize)> = Vec::new();
+    let mut sprites = Vec::with_capacity(cells.len());
+
+    let (mut sheet, mut x, mut y, mut row_height) = (0, 0, 0, 0);
+
+    for (image_path, mw) in cells.iter() {
+        let (width, height) = (mw.get_image_width(), mw.get_image_height());
+
+        if width > sheet_size || height > sheet_size {
+            return Err(anyhow!("{image_path:?} is larger than the sheet size."));
+        }
+
+        if x + width > sheet_size {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+
+        if y + height > sheet_size {
+            sheet += 1;
+            x = 0;
+            y = 0;
+            row_height = 0;
+        }
+
+        if sheet_sizes.len() <= sheet {
+            sheet_sizes.push((0, 0));
+        }
+
+        let sheet_size = &mut sheet_sizes[sheet];
+
+        sheet_size.0 = sheet_size.0.max(x + width);
+        sheet_size.1 = sheet_size.1.max(y + height);
+
+        let name = pathdiff::diff_paths(image_path, args.input_path.as_path()).unwrap();
+
+        sprites.push(Sprite {
+            name: name
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            sheet,
+            x,
+            y,
+            width,
+            height,
+        });
+
+        x += width;
+        row_height = row_height.max(height);
+    }
+
+    let output_path = output_path.with_extension("");
+
+    let mut sheets = Vec::with_capacity(sheet_sizes.len());
+
+    for (index, (width, height)) in sheet_sizes.into_iter().enumerate() {
+        let mut file_name = output_path.file_name().unwrap().to_os_string();
+
+        file_name.push(format!("-{index}.png"));
+
+        let sheet_path = output_path.with_file_name(&file_name);
+
+        if let Some(sheet_path) = get_output_path(
+            args,
+            sc,
+            overwriting,
+            sheet_path.as_path(),
+            Some(sheet_path.as_path()),
+        )? {
+            let mw = MagickWand::new();
+
+            let mut background = PixelWand::new();
+            background.set_color("none")?;
+
+            mw.new_image(width, height, &background)?;
+
+            for ((_, cell), sprite) in cells.iter().zip(sprites.iter()) {
+                if sprite.sheet == index {
+                    mw.compose_images(
+                        cell,
+                        bindings::CompositeOperator_OverCompositeOp,
+                        false,
+                        sprite.x as isize,
+                        sprite.y as isize,
+                    )?;
+                }
+            }
+
+            let data = mw.write_image_blob("PNG").with_context(|| anyhow!("{sheet_path:?}"))?;
+
+            processing::write_output(sheet_path, &["png"], &data, args.output_mode)
+                .with_context(|| anyhow!("write {sheet_path:?}"))?;
+
+            print_packed_message(args, sheet_path)?;
+        }
+
+        sheets.push(Sheet {
+            file: file_name.to_string_lossy().into_owned(),
+            width,
+            height,
+        });
+    }
+
+    let map_path = output_path.with_extension("json");
+
+    if let Some(map_path) =
+        get_output_path(args, sc, overwriting, map_path.as_path(), Some(map_path.as_path()))?
+    {
+        let data = serde_json::to_vec_pretty(&Atlas {
+            sheets,
+            sprites,
+        })?;
+
+        processing::write_output(map_path, &["json"], &data, args.output_mode)
+            .with_context(|| anyhow!("write {map_path:?}"))?;
+
+        print_packed_message(args, map_path)?;
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn print_packed_message<P: AsRef<Path>>(args: &CLIArgs, path: P) -> anyhow::Result<()> {
+    if args.quiet && !args.print0 {
+        return Ok(());
+    }
+
+    let path = path.as_ref().canonicalize().unwrap();
+
+    let mut data = Vec::new();
+
+    if args.print0 {
+        path_list::write_path0(&mut data, path)?;
+    } else {
+        writeln!(data, "{} has been packed.", console::quoted(&path))?;
+    }
+
+    Line::Stdout(data).print()?;
+
+    Ok(())
+}
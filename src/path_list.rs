@@ -0,0 +1,64 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+pub fn read_paths0<P: AsRef<Path>>(path: P) -> io::Result<Vec<PathBuf>> {
+    let data = read_list(path.as_ref())?;
+
+    Ok(data.split(|b| *b == 0).filter(|p| !p.is_empty()).map(bytes_to_path).collect())
+}
+
+// one path per line, with or without a carriage return, skipping the blank lines
+pub fn read_paths<P: AsRef<Path>>(path: P) -> io::Result<Vec<PathBuf>> {
+    let data = read_list(path.as_ref())?;
+
+    Ok(data
+        .split(|b| *b == b'\n')
+        .map(|p| p.strip_suffix(b"\r").unwrap_or(p))
+        .filter(|p| !p.iter().all(u8::is_ascii_whitespace))
+        .map(bytes_to_path)
+        .collect())
+}
+
+fn read_list(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+
+    if path == Path::new("-") {
+        io::stdin().lock().read_to_end(&mut data)?;
+    } else {
+        File::open(path)?.read_to_end(&mut data)?;
+    }
+
+    Ok(data)
+}
+
+pub fn write_path0<W: Write, P: AsRef<Path>>(writer: &mut W, path: P) -> io::Result<()> {
+    writer.write_all(&path_to_bytes(path.as_ref()))?;
+    writer.write_all(b"\0")
+}
+
+#[cfg(unix)]
+pub fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
@@ -0,0 +1,61 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{cli::CLIArgs, processing};
+
+// a single image is resized in memory, from stdin or the input path into stdout or the output
+// path, so that it can be used in a pipeline without temporary files
+pub fn pipe(args: &CLIArgs) -> anyhow::Result<()> {
+    let output_path = if args.stdout {
+        None
+    } else {
+        let output_path = match args.output_path.as_deref() {
+            Some(output_path) => output_path,
+            None => {
+                return Err(anyhow!(
+                    "An output path or --stdout is needed to resize an image from stdin."
+                ))
+            },
+        };
+
+        // stdin is read to its end, so there is nothing to answer the question with
+        if output_path.exists() && !args.force && !args.yes {
+            return Err(anyhow!("{output_path:?} exists. Use --force or --yes to overwrite it."));
+        }
+
+        Some(output_path)
+    };
+
+    let input = if args.stdin {
+        let mut input = Vec::new();
+
+        io::stdin().lock().read_to_end(&mut input).context("stdin")?;
+
+        input
+    } else {
+        fs::read(&args.input_path).with_context(|| anyhow!("{:?}", args.input_path))?
+    };
+
+    if let Some(seed) = args.seed {
+        processing::set_seed(seed);
+    }
+
+    let (output, handler) = processing::resize_data(args, &input)?;
+
+    match output_path {
+        Some(output_path) => {
+            processing::write_output(output_path, handler.extensions, &output, args.output_mode)
+                .with_context(|| anyhow!("{output_path:?}"))
+        },
+        None => {
+            let mut stdout = io::stdout().lock();
+
+            stdout.write_all(&output).context("stdout")?;
+            stdout.flush().context("stdout")
+        },
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{cli::CLIArgs, processing, summary::SkipReason};
+
+// only the head of a file is read to find the patterns, except for the NUL bytes
+const HEAD_SIZE: u64 = 512;
+
+// a file which is named like an image but cannot be one, found before ImageMagick fails on it with
+// an error of its delegates
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Placeholder {
+    Empty,
+    // such as a file which has been allocated but never downloaded
+    Zeroed,
+    GitLfsPointer,
+    // such as an error page which has been saved instead of the image
+    Html,
+}
+
+impl Placeholder {
+    #[inline]
+    pub fn skip_reason(self) -> SkipReason {
+        match self {
+            Placeholder::Empty => SkipReason::EmptyFile,
+            _ => SkipReason::Placeholder,
+        }
+    }
+}
+
+impl Display for Placeholder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Placeholder::Empty => "it is empty",
+            Placeholder::Zeroed => "it only contains NUL bytes",
+            Placeholder::GitLfsPointer => "it is a Git LFS pointer",
+            Placeholder::Html => "it is an HTML page",
+        })
+    }
+}
+
+pub fn detect(path: &Path) -> io::Result<Option<Placeholder>> {
+    let mut file = File::open(path)?;
+
+    let mut head = Vec::with_capacity(HEAD_SIZE as usize);
+
+    file.by_ref().take(HEAD_SIZE).read_to_end(&mut head)?;
+
+    if head.is_empty() {
+        return Ok(Some(Placeholder::Empty));
+    }
+
+    if head.iter().all(|b| *b == 0) {
+        let mut buffer = [0u8; 8192];
+
+        loop {
+            let c = file.read(&mut buffer)?;
+
+            if c == 0 {
+                return Ok(Some(Placeholder::Zeroed));
+            }
+
+            if buffer[..c].iter().any(|b| *b != 0) {
+                return Ok(None);
+            }
+        }
+    }
+
+    if head.starts_with(b"version https://git-lfs.github.com/spec/") {
+        return Ok(Some(Placeholder::GitLfsPointer));
+    }
+
+    let text = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&head);
+    let text = &text[text.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(0)..];
+
+    let is_html = [b"<!doctype html".as_slice(), b"<html"]
+        .iter()
+        .any(|tag| text.len() >= tag.len() && text[..tag.len()].eq_ignore_ascii_case(tag));
+
+    if is_html {
+        return Ok(Some(Placeholder::Html));
+    }
+
+    Ok(None)
+}
+
+// keep the path relative to the input directory inside the quarantine directory
+pub fn quarantine(args: &CLIArgs, input_path: &Path) -> anyhow::Result<PathBuf> {
+    let quarantine_dir = match args.quarantine_dir.as_deref() {
+        Some(quarantine_dir) => quarantine_dir,
+        None => return Err(anyhow!("A quarantine directory is needed to quarantine files.")),
+    };
+
+    let relative_path = match input_path.strip_prefix(&args.input_path) {
+        Ok(relative_path) if !relative_path.as_os_str().is_empty() => relative_path,
+        _ => Path::new(input_path.file_name().unwrap()),
+    };
+
+    let quarantine_path = quarantine_dir.join(relative_path);
+
+    let parent = quarantine_path.parent().unwrap();
+
+    processing::create_dir_all(parent, args.output_dir_mode)
+        .with_context(|| anyhow!("{parent:?}"))?;
+
+    // the quarantine directory may be on another file system
+    if fs::rename(input_path, &quarantine_path).is_err() {
+        fs::copy(input_path, &quarantine_path).with_context(|| anyhow!("{quarantine_path:?}"))?;
+        fs::remove_file(input_path).with_context(|| anyhow!("{input_path:?}"))?;
+    }
+
+    Ok(quarantine_path)
+}
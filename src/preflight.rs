@@ -0,0 +1,618 @@
+#[cfg(feature = "magick")]
+use std::fs;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use threadpool::ThreadPool;
+
+#[cfg(all(feature = "magick", feature = "image-backend"))]
+use crate::cli::Backend;
+#[cfg(feature = "image-backend")]
+use crate::image_backend;
+#[cfg(feature = "dds")]
+use crate::texture;
+#[cfg(feature = "magick")]
+use crate::{
+    cli::{BelowMinimum, ConvertFormat},
+    formats, is_ktx2, rules,
+};
+use crate::{
+    cli::{CLIArgs, ReportFormat},
+    console::{self, Line},
+    csv, directory_output_path, is_sniffed,
+    job_queue::JobQueue,
+    placeholder, processing,
+    summary::SkipReason,
+};
+
+// what a run would do, found by pinging the images instead of decoding them
+#[derive(Debug, Default)]
+pub struct Preflight {
+    images:          usize,
+    // the pixels of each image which would be resized, which the work of resizing it is measured in
+    pixels:          HashMap<PathBuf, u64>,
+    estimated_bytes: u64,
+    // the images which each output would be written from, once per write
+    outputs:         BTreeMap<PathBuf, Vec<PathBuf>>,
+    skipped:         Vec<(PathBuf, SkipReason)>,
+    errors:          Vec<(PathBuf, String)>,
+}
+
+// what `resizing` would do for an image
+#[derive(Debug, Default)]
+pub struct Plan {
+    // the outputs along with their estimated sizes in bytes
+    pub outputs: Vec<(PathBuf, u64)>,
+    // the pixels of all of the frames of the input
+    pub pixels:  u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    images:          usize,
+    pixels:          u64,
+    outputs:         usize,
+    estimated_bytes: u64,
+    // the outputs which exist, and would be overwritten or asked about without --force
+    existing:        Vec<&'a Path>,
+    collisions:      Vec<Collision<'a>>,
+    skipped:         Vec<Skipped<'a>>,
+    errors:          Vec<Failure<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Collision<'a> {
+    output: &'a Path,
+    inputs: &'a [PathBuf],
+}
+
+#[derive(Debug, Serialize)]
+struct Skipped<'a> {
+    path:   &'a Path,
+    reason: SkipReason,
+}
+
+#[derive(Debug, Serialize)]
+struct Failure<'a> {
+    path:  &'a Path,
+    error: &'a str,
+}
+
+impl Preflight {
+    #[inline]
+    pub fn check(&mut self, args: &CLIArgs, input_path: PathBuf, output_path: Option<&Path>) {
+        let plan = plan(args, &input_path, output_path);
+
+        self.record(input_path, plan);
+    }
+
+    // the images are pinged in parallel, since identifying an image mostly waits for the disk
+    pub fn identify(args: &Arc<CLIArgs>, image_paths: &mut JobQueue) -> anyhow::Result<Preflight> {
+        let pool = ThreadPool::new(num_cpus::get() * 2);
+
+        let preflight = Arc::new(Mutex::new(Preflight::default()));
+
+        for image_path in image_paths.iter().with_context(|| anyhow!("queue"))? {
+            let image_path = image_path.with_context(|| anyhow!("queue"))?;
+
+            while pool.queued_count() >= threads {
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            let args = args.clone();
+            let preflight = preflight.clone();
+
+            pool.execute(move || {
+                let output_path = directory_output_path(&args, &image_path);
+
+                let plan = plan(&args, &image_path, output_path.as_deref());
+
+                preflight.lock().unwrap().record(image_path, plan);
+            });
+        }
+
+        pool.join();
+
+        let mut preflight = Arc::try_unwrap(preflight).unwrap().into_inner().unwrap();
+
+        // in the order of the paths rather than the order in which the threads finished
+        preflight.skipped.sort();
+        preflight.errors.sort();
+
+        for inputs in preflight.outputs.values_mut() {
+            inputs.sort();
+        }
+
+        Ok(preflight)
+    }
+
+    fn record(&mut self, input_path: PathBuf, plan: anyhow::Result<Result<Plan, SkipReason>>) {
+        match plan {
+            Ok(Ok(plan)) => {
+                self.images += 1;
+
+                for (output, estimated_bytes) in plan.outputs {
+                    self.estimated_bytes += estimated_bytes;
+
+                    self.outputs.entry(output).or_default().push(input_path.clone());
+                }
+
+                self.pixels.insert(input_path, plan.pixels);
+            },
+            Ok(Err(reason)) => self.skipped.push((input_path, reason)),
+            Err(error) => self.errors.push((input_path, error.root_cause().to_string())),
+        }
+    }
+
+    // a line about what has been found, printed before resizing with --identify-first
+    pub fn overview(&self) -> String {
+        let mut overview = format!(
+            "{} images have been identified: {} would be resized into about {:.1} MB, {} skipped \
+             and {} not identified.\n",
+            self.images + self.skipped.len() + self.errors.len(),
+            self.images,
+            self.estimated_bytes as f64 / 1e6,
+            self.skipped.len(),
+            self.errors.len()
+        );
+
+        let collisions = self.outputs.values().filter(|inputs| inputs.len() > 1).count();
+
+        if collisions > 0 {
+            overview.push_str(&format!(
+                "{collisions} outputs would be written more than once. Use --preflight to list \
+                 them.\n"
+            ));
+        }
+
+        overview
+    }
+
+    // the largest images are started first, so that none of them is left running alone at the end
+    pub fn largest_first(&self, mut image_paths: JobQueue) -> anyhow::Result<JobQueue> {
+        let mut image_paths = image_paths
+            .iter()
+            .and_then(|paths| paths.collect::<Result<Vec<PathBuf>, _>>())
+            .with_context(|| anyhow!("queue"))?;
+
+        image_paths.sort_by_key(|p| Reverse(self.pixels.get(p).copied().unwrap_or(0)));
+
+        Ok(image_paths.into())
+    }
+
+    #[inline]
+    pub fn into_pixels(self) -> HashMap<PathBuf, u64> {
+        self.pixels
+    }
+
+    fn report(&self) -> Report<'_> {
+        Report {
+            images:          self.images,
+            pixels:          self.pixels.values().sum(),
+            outputs:         self.outputs.len(),
+            estimated_bytes: self.estimated_bytes,
+            existing:        self
+                .outputs
+                .keys()
+                .filter(|output| output.exists())
+                .map(PathBuf::as_path)
+                .collect(),
+            collisions:      self
+                .outputs
+                .iter()
+                .filter(|(_, inputs)| inputs.len() > 1)
+                .map(|(output, inputs)| Collision {
+                    output,
+                    inputs,
+                })
+                .collect(),
+            skipped:         self
+                .skipped
+                .iter()
+                .map(|(path, reason)| Skipped {
+                    path,
+                    reason: *reason,
+                })
+                .collect(),
+            errors:          self
+                .errors
+                .iter()
+                .map(|(path, error)| Failure {
+                    path,
+                    error,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn print(&self, format: ReportFormat) -> anyhow::Result<()> {
+        let report = self.report();
+
+        let data = match format {
+            ReportFormat::Json => {
+                let mut data = serde_json::to_vec_pretty(&report)?;
+
+                data.push(b'\n');
+
+                data
+            },
+            ReportFormat::Text => {
+                let mut text = format!(
+                    "{} images would be resized into {} outputs of about {:.1} MB.\n",
+                    report.images,
+                    report.outputs,
+                    report.estimated_bytes as f64 / 1e6
+                );
+
+                if !report.existing.is_empty() {
+                    writeln!(text, "\n{} outputs exist already:", report.existing.len())?;
+
+                    for output in report.existing.iter() {
+                        writeln!(text, "  {}", console::quoted(output))?;
+                    }
+                }
+
+                if !report.collisions.is_empty() {
+                    writeln!(
+                        text,
+                        "\n{} outputs would be written more than once:",
+                        report.collisions.len()
+                    )?;
+
+                    for collision in report.collisions.iter() {
+                        writeln!(text, "  {}", console::quoted(collision.output))?;
+
+                        for input in collision.inputs {
+                            writeln!(text, "    from {}", console::quoted(input))?;
+                        }
+                    }
+                }
+
+                if !report.skipped.is_empty() {
+                    writeln!(text, "\n{} images would be skipped:", report.skipped.len())?;
+
+                    for skipped in report.skipped.iter() {
+                        writeln!(text, "  {}: {}", console::quoted(skipped.path), skipped.reason)?;
+                    }
+                }
+
+                if !report.errors.is_empty() {
+                    writeln!(text, "\n{} images could not be identified:", report.errors.len())?;
+
+                    for failure in report.errors.iter() {
+                        writeln!(text, "  {}: {}", console::quoted(failure.path), failure.error)?;
+                    }
+                }
+
+                text.into_bytes()
+            },
+            ReportFormat::Csv => {
+                let mut text = String::new();
+
+                csv::write_row(&mut text, &["category", "path", "detail"])?;
+
+                for output in report.existing.iter() {
+                    csv::write_row(&mut text, &["existing", &output.to_string_lossy(), ""])?;
+                }
+
+                // a row per input of each output which would be written more than once
+                for collision in report.collisions.iter() {
+                    let output = collision.output.to_string_lossy();
+
+                    for input in collision.inputs {
+                        csv::write_row(&mut text, &[
+                            "collision",
+                            &output,
+                            &input.to_string_lossy(),
+                        ])?;
+                    }
+                }
+
+                for skipped in report.skipped.iter() {
+                    csv::write_row(&mut text, &[
+                        "skipped",
+                        &skipped.path.to_string_lossy(),
+                        &skipped.reason.to_string(),
+                    ])?;
+                }
+
+                for failure in report.errors.iter() {
+                    csv::write_row(&mut text, &[
+                        "error",
+                        &failure.path.to_string_lossy(),
+                        failure.error,
+                    ])?;
+                }
+
+                text.into_bytes()
+            },
+        };
+
+        Line::Stdout(data).print()?;
+
+        Ok(())
+    }
+}
+
+// the outputs which `resizing` would write for an image, or why it would be skipped
+fn plan(
+    args: &CLIArgs,
+    input_path: &Path,
+    output_path: Option<&Path>,
+) -> anyhow::Result<Result<Plan, SkipReason>> {
+    if !is_sniffed(args, input_path) {
+        if let Some(placeholder) =
+            placeholder::detect(input_path).with_context(|| anyhow!("{input_path:?}"))?
+        {
+            return Ok(Err(placeholder.skip_reason()));
+        }
+    }
+
+    if let Some(output_path) = output_path.filter(|_| args.newer_only) {
+        if processing::is_newer(output_path, input_path) {
+            return Ok(Err(SkipReason::UpToDate));
+        }
+    }
+
+    #[cfg(not(feature = "magick"))]
+    {
+        image_backend::plan(args, input_path, output_path)
+    }
+
+    #[cfg(feature = "magick")]
+    {
+        #[cfg(feature = "image-backend")]
+        if args.backend == Backend::Image {
+            return image_backend::plan(args, input_path, output_path);
+        }
+
+        plan_with_magick(args, input_path, output_path)
+    }
+}
+
+#[cfg(feature = "magick")]
+fn plan_with_magick(
+    args: &CLIArgs,
+    input_path: &Path,
+    output_path: Option<&Path>,
+) -> anyhow::Result<Result<Plan, SkipReason>> {
+    let input_bytes = fs::metadata(input_path).with_context(|| anyhow!("{input_path:?}"))?.len();
+
+    let is_icns = input_path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("icns"))
+        .unwrap_or(false);
+
+    // the icons are not pinged, so their pixels are unknown
+    if args.icns || is_icns {
+        let output_path = match output_path {
+            Some(output_path) => output_path.with_extension("icns"),
+            None if is_icns => input_path.to_path_buf(),
+            None => input_path.with_extension("icns"),
+        };
+
+        return Ok(Ok(Plan {
+            outputs: vec![(output_path, input_bytes)], pixels: 0
+        }));
+    }
+
+    #[cfg(feature = "dds")]
+    let input_image_resource = if is_ktx2(input_path) {
+        let data = fs::read(input_path).with_context(|| anyhow!("{input_path:?}"))?;
+
+        image_convert::ImageResource::Data(
+            texture::ktx2_to_dds(&data).with_context(|| anyhow!("{input_path:?}"))?,
+        )
+    } else {
+        image_convert::ImageResource::from_path(input_path)
+    };
+    #[cfg(not(feature = "dds"))]
+    let input_image_resource = image_convert::ImageResource::from_path(input_path);
+
+    let ping = match processing::ping(&input_image_resource) {
+        Ok(mw) => image_convert::ImageResource::MagickWand(mw),
+        Err(_) if is_sniffed(args, input_path) => return Ok(Err(SkipReason::UnsupportedFormat)),
+        Err(error) => return Err(error).with_context(|| anyhow!("{input_path:?}")),
+    };
+
+    let frames = processing::frame_count(ping.as_magick_wand().unwrap());
+
+    let identify =
+        image_convert::identify_ping(&ping).with_context(|| anyhow!("{input_path:?}"))?;
+
+    let format = if is_ktx2(input_path) { "KTX2" } else { identify.format.as_str() };
+
+    let is_svg = format == "SVG";
+    let is_raw = formats::RAW_FORMATS.contains(&format);
+
+    if !args.allows_format(format) {
+        return Ok(Err(SkipReason::FormatExcluded));
+    }
+
+    match formats::by_name(format) {
+        Some(handler) if !handler.is_allowed(args) => return Ok(Err(SkipReason::GifNotAllowed)),
+        None if !is_svg && !is_raw => return Ok(Err(SkipReason::UnsupportedFormat)),
+        _ => (),
+    }
+
+    let (width, height) = (identify.resolution.width, identify.resolution.height);
+
+    let args = rules::apply(args.for_format(format), |attribute| {
+        processing::image_attribute(
+            ping.as_magick_wand().unwrap(),
+            format,
+            width,
+            height,
+            attribute,
+        )
+    });
+
+    if args.below_minimum == BelowMinimum::Skip && args.is_below_minimum(width, height) {
+        return Ok(Err(SkipReason::BelowMinimum));
+    }
+
+    let args = &*args.for_size(width, height);
+
+    let output_path = output_path.unwrap_or(input_path);
+
+    let (format, output_path) = if is_raw || (format == "HEIC" && !processing::can_encode_heic()) {
+        ("JPEG", output_path.with_extension("jpg"))
+    } else if is_svg || (format == "BMP" && args.bmp_to_png) {
+        ("PNG", output_path.with_extension("png"))
+    } else if is_sniffed(args, input_path) {
+        (format, output_path.with_extension(formats::by_name(format).unwrap().extensions[0]))
+    } else {
+        (format, output_path.to_path_buf())
+    };
+
+    let pixels = u64::from(width) * u64::from(height) * frames as u64;
+
+    let estimate = |size: (usize, usize)| estimate_bytes(input_bytes, (width, height), size);
+
+    let fit = |(max_width, max_height): (u16, u16)| {
+        let (width, height) =
+            processing::compute_output_size(args.only_shrink, width, height, max_width, max_height)
+                .unwrap_or((width, height));
+
+        (width as usize, height as usize)
+    };
+
+    let outputs = |outputs: Vec<(PathBuf, u64)>| {
+        Ok(Ok(Plan {
+            outputs,
+            pixels,
+        }))
+    };
+
+    let (output_width, output_height) = fit(args.max_size());
+
+    if (format == "WEBP" || format == "GIF") && frames > 1 {
+        let estimated_bytes = estimate((output_width, output_height));
+
+        if format == "WEBP" && args.convert == Some(ConvertFormat::Gif) {
+            return outputs(vec![(output_path.with_extension("gif"), estimated_bytes)]);
+        }
+
+        return outputs(vec![(output_path, estimated_bytes)]);
+    }
+
+    if let Some(tile) = args
+        .tile
+        .filter(|tile| output_width > usize::from(*tile) || output_height > usize::from(*tile))
+    {
+        let (tile, overlap) = (usize::from(tile), usize::from(args.tile_overlap));
+
+        let mut tiles = Vec::new();
+
+        for (row, (_, tile_height)) in
+            processing::tile_offsets(output_height, tile, overlap).into_iter().enumerate()
+        {
+            for (column, (_, tile_width)) in
+                processing::tile_offsets(output_width, tile, overlap).into_iter().enumerate()
+            {
+                tiles.push((
+                    processing::tile_path(&output_path, row, column),
+                    estimate((tile_width, tile_height)),
+                ));
+            }
+        }
+
+        return outputs(tiles);
+    }
+
+    if !args.quality_ladder.is_empty() && formats::by_name(format).unwrap().lossy {
+        let estimated_bytes = estimate((output_width, output_height));
+
+        return outputs(
+            args.quality_ladder
+                .iter()
+                .map(|quality| {
+                    (
+                        processing::ladder_path(&args.ladder_name, &output_path, *quality),
+                        estimated_bytes,
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    if !args.sizes.is_empty() {
+        return outputs(
+            args.sizes
+                .iter()
+                .map(|size| {
+                    (
+                        processing::size_path(&args.size_name, &output_path, *size),
+                        estimate(fit((*size, *size))),
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    if !args.dpr.is_empty() {
+        let (max_width, max_height) = args.max_size();
+
+        return outputs(
+            args.dpr
+                .iter()
+                .map(|dpr| {
+                    let dpr_size = (
+                        max_width.saturating_mul(u16::from(*dpr)),
+                        max_height.saturating_mul(u16::from(*dpr)),
+                    );
+
+                    (processing::dpr_path(&output_path, *dpr), estimate(fit(dpr_size)))
+                })
+                .collect(),
+        );
+    }
+
+    let size = predicted_size(args, width, height);
+
+    if let Some(template) = args.output_template.as_deref() {
+        return outputs(vec![(
+            processing::template_path(template, &output_path, size.0, size.1),
+            estimate(size),
+        )]);
+    }
+
+    outputs(vec![(output_path, estimate(size))])
+}
+
+// the size of an output is estimated by scaling the size of its input by the pixels, regardless of
+// the quality and the format
+pub fn estimate_bytes(input_bytes: u64, (width, height): (u32, u32), size: (usize, usize)) -> u64 {
+    let pixels = u64::from(width) * u64::from(height);
+
+    if pixels == 0 {
+        return input_bytes;
+    }
+
+    (input_bytes as f64 * (size.0 * size.1) as f64 / pixels as f64) as u64
+}
+
+// the size of the output of an image for --output-template, which does not take the trimmed
+// margins into account
+pub fn predicted_size(args: &CLIArgs, width: u32, height: u32) -> (usize, usize) {
+    if let Some(size_box) = args.crop.or(args.pad) {
+        return (usize::from(size_box.width), usize::from(size_box.height));
+    }
+
+    let (max_width, max_height) = args.max_size();
+
+    let (width, height) =
+        processing::compute_output_size(args.only_shrink, width, height, max_width, max_height)
+            .unwrap_or((width, height));
+
+    (width as usize, height as usize)
+}
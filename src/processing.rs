@@ -0,0 +1,1830 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::OsString,
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "magick")]
+use std::{
+    ffi::{c_void, CString},
+    slice,
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Context};
+#[cfg(feature = "magick")]
+use image_convert::{
+    magick_rust::{self, bindings, DrawingWand, MagickWand, PixelWand},
+    ImageResource, MagickError,
+};
+use str_utils::EqIgnoreAsciiCaseMultiple;
+
+use crate::cli::{CropBox, Gravity};
+#[cfg(feature = "magick")]
+use crate::{
+    animation::Timing,
+    cli::{
+        AnnotateGravity, AutoLevel, BelowMinimum, CLIArgs, Color, ConvertFormat, DensityUnit,
+        Dither, Flip, GifPalette, Interlace, Margins, PngColorType, ResizeFilter,
+    },
+    formats::{self, Encoding, FormatHandler},
+    icc, rules,
+};
+
+// resize an image in memory into a single output, the same way as the binary resizes an image file
+// which is not resized into several outputs, and encode it in its own format, or in the one which
+// the binary converts it to
+#[cfg(feature = "magick")]
+pub fn resize_data(
+    args: &CLIArgs,
+    input: &[u8],
+) -> anyhow::Result<(Vec<u8>, &'static FormatHandler)> {
+    let input = ImageResource::Data(input.to_vec());
+
+    let ping = ImageResource::MagickWand(ping(&input).context("ping")?);
+
+    let identify = image_convert::identify_ping(&ping).context("identify")?;
+
+    let format = identify.format.as_str();
+
+    let is_svg = format == "SVG";
+    let is_raw = formats::RAW_FORMATS.contains(&format);
+
+    if !args.allows_format(format) {
+        return Err(anyhow!("The format {format} is excluded by --formats or --skip-formats."));
+    }
+
+    match formats::by_name(format) {
+        Some(handler) if !handler.is_allowed(args) => {
+            return Err(anyhow!(
+                "GIF processing is disabled. Use --allow-gif or --extensions gif to resize it."
+            ));
+        },
+        None if !is_svg && !is_raw => {
+            return Err(anyhow!("The format {format:?} is not supported."));
+        },
+        _ => (),
+    }
+
+    let (width, height) = (identify.resolution.width, identify.resolution.height);
+
+    let args = rules::apply(args.for_format(format), |attribute| {
+        image_attribute(ping.as_magick_wand().unwrap(), format, width, height, attribute)
+    });
+
+    if args.below_minimum == BelowMinimum::Skip && args.is_below_minimum(width, height) {
+        return Err(anyhow!("The long side is shorter than --side-minimum ({width}x{height})."));
+    }
+
+    let args = &*args.for_size(width, height);
+
+    let input = if is_svg {
+        let mw = rasterize_svg(&input, args.density, args.max_size(), args.only_shrink)
+            .context("rasterize")?;
+
+        ImageResource::MagickWand(mw)
+    } else {
+        input
+    };
+
+    // camera RAW images are saved as JPEG, as well as the HEIC images which can only be decoded,
+    // and SVG images as PNG
+    let format = if is_raw || (format == "HEIC" && !can_encode_heic()) {
+        "JPEG"
+    } else if is_svg || (format == "BMP" && args.bmp_to_png) {
+        "PNG"
+    } else {
+        format
+    };
+
+    // animated WebP and GIF images are resized frame by frame
+    let input = if format == "WEBP" || format == "GIF" {
+        let mut mw = decode(input).context("decode")?.into_magick_wand().unwrap();
+
+        if frame_count(&mw) > 1 {
+            let format = match (format, args.convert) {
+                ("WEBP", Some(ConvertFormat::Gif)) => "GIF",
+                _ => format,
+            };
+
+            let timing = Timing::of(&mut mw).context("timing")?;
+
+            let animation = resize_animation(args, mw, format, &timing).context("resize")?;
+
+            let output = animation
+                .write_images_blob(format)
+                .with_context(|| anyhow!("to_{}", format.to_ascii_lowercase()))?;
+
+            if args.verify_animation {
+                timing.verify(&output).context("verify")?;
+            }
+
+            return Ok((output, formats::by_name(format).unwrap()));
+        }
+
+        ImageResource::MagickWand(mw)
+    } else {
+        input
+    };
+
+    // the largest frame of an ICO image, or the one chosen by --ico-frame
+    let input = if format == "ICO" {
+        ImageResource::MagickWand(select_frame(&input, args.ico_frame).context("decode")?)
+    } else {
+        input
+    };
+
+    let handler = formats::by_name(format).unwrap();
+
+    let input = adjust(args, input).context("adjust")?;
+
+    if let Some(target_size) = args.target_size.filter(|_| matches!(format, "JPEG" | "WEBP")) {
+        // resized and sharpened once, and then only encoded for each quality tried
+        let input = ImageResource::MagickWand(resize(args, &input).context("resize")?);
+
+        let (output, _) = handler
+            .encode_within(args, &input, Encoding::encoding_only(args.quality), target_size)
+            .with_context(|| anyhow!("to_{}", format.to_ascii_lowercase()))?;
+
+        return Ok((output, handler));
+    }
+
+    let encoding = Encoding::resizing(args, args.quality);
+
+    let filtered = encoding.filter(args, &input).context("resize")?;
+
+    let (input, encoding) = match filtered {
+        Some((filtered, encoding)) => (filtered, encoding),
+        None => (input, encoding),
+    };
+
+    let output = (handler.encode)(args, &input, encoding)
+        .with_context(|| anyhow!("to_{}", format.to_ascii_lowercase()))?;
+
+    Ok((output, handler))
+}
+
+// an attribute of an image for --rule, where EXIF tags are read from the pinged image
+#[cfg(feature = "magick")]
+pub fn image_attribute(
+    mw: &MagickWand,
+    format: &str,
+    width: u32,
+    height: u32,
+    attribute: &str,
+) -> Option<String> {
+    match attribute {
+        "format" => Some(format.to_string()),
+        "width" => Some(width.to_string()),
+        "height" => Some(height.to_string()),
+        _ => mw.get_image_property(&rules::exif_property(attribute)?).ok(),
+    }
+}
+
+#[cfg(feature = "magick")]
+pub fn ping(input: &ImageResource) -> Result<MagickWand, MagickError> {
+    match input {
+        ImageResource::Path(p) => {
+            let mw = MagickWand::new();
+
+            mw.ping_image(p.as_str())?;
+
+            Ok(mw)
+        },
+        ImageResource::Data(b) => {
+            let mw = MagickWand::new();
+
+            mw.ping_image_blob(b)?;
+
+            Ok(mw)
+        },
+        ImageResource::MagickWand(mw) => Ok(mw.clone()),
+    }
+}
+
+// returns the description of the embedded ICC profile if it is not an sRGB one, since stripping it
+// without converting the colors makes them look different
+#[cfg(feature = "magick")]
+pub fn non_srgb_profile(mw: &MagickWand) -> Option<String> {
+    let data = get_profile(mw, "icc")?;
+
+    if icc::is_srgb(&data) {
+        None
+    } else {
+        Some(icc::description(&data).unwrap_or_else(|| String::from("unnamed")))
+    }
+}
+
+// the resized image gets its metadata and the text of --annotate before it is encoded
+#[cfg(feature = "magick")]
+pub fn finish(
+    args: &CLIArgs,
+    input: &ImageResource,
+    output: &MagickWand,
+) -> Result<(), MagickError> {
+    apply_metadata(args, input, output)?;
+
+    match args.annotate.as_deref() {
+        Some(text) => annotate(args, output, text),
+        None => Ok(()),
+    }
+}
+
+// image-convert strips all of the profiles unless --remain-profile is given, so the ICC profile of
+// the input is put back into its output for --keep-metadata icc, whatever the output format is,
+// and the profiles kept by --remain-profile are removed for --strip-exif and --strip-xmp
+#[cfg(feature = "magick")]
+fn apply_metadata(
+    args: &CLIArgs,
+    input: &ImageResource,
+    output: &MagickWand,
+) -> Result<(), MagickError> {
+    if args.strip_exif {
+        remove_profile(output, "exif");
+    }
+
+    if args.strip_xmp {
+        remove_profile(output, "xmp");
+    }
+
+    if args.remains_profile() || !args.keeps_icc() {
+        return Ok(());
+    }
+
+    let icc = match input {
+        ImageResource::MagickWand(mw) => get_profile(mw, "icc"),
+        _ => get_profile(&ping(input)?, "icc"),
+    };
+
+    match icc {
+        Some(icc) => set_profile(output, "icc", &icc),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "magick")]
+fn annotate(args: &CLIArgs, mw: &MagickWand, text: &str) -> Result<(), MagickError> {
+    let side = mw.get_image_width().min(mw.get_image_height());
+
+    let size = args.annotate_size.to_pixels(side).max(1) as f64;
+
+    let gravity = match args.annotate_gravity {
+        AnnotateGravity::NorthWest => bindings::GravityType_NorthWestGravity,
+        AnnotateGravity::North => bindings::GravityType_NorthGravity,
+        AnnotateGravity::NorthEast => bindings::GravityType_NorthEastGravity,
+        AnnotateGravity::West => bindings::GravityType_WestGravity,
+        AnnotateGravity::Center => bindings::GravityType_CenterGravity,
+        AnnotateGravity::East => bindings::GravityType_EastGravity,
+        AnnotateGravity::SouthWest => bindings::GravityType_SouthWestGravity,
+        AnnotateGravity::South => bindings::GravityType_SouthGravity,
+        AnnotateGravity::SouthEast => bindings::GravityType_SouthEastGravity,
+    };
+
+    let mut color = PixelWand::new();
+    color.set_color(&args.annotate_color.to_hex())?;
+
+    let mut dw = DrawingWand::new();
+
+    if let Some(font) = args.annotate_font.as_deref() {
+        dw.set_font(font)?;
+    }
+
+    dw.set_font_size(size);
+    dw.set_fill_color(&color);
+    dw.set_gravity(gravity);
+
+    // kept off the edges by half of the size of the text, and the offsets are towards the inside
+    // whatever the gravity is, so the text stays centered along an edge
+    let margin = size / 2f64;
+
+    let (x, y) = match args.annotate_gravity {
+        AnnotateGravity::Center => (0f64, 0f64),
+        AnnotateGravity::North | AnnotateGravity::South => (0f64, margin),
+        AnnotateGravity::West | AnnotateGravity::East => (margin, 0f64),
+        _ => (margin, margin),
+    };
+
+    let text = CString::new(text).map_err(|_| "the text contains a NUL character")?;
+
+    let result =
+        unsafe { bindings::MagickAnnotateImage(mw.wand, dw.wand, x, y, 0f64, text.as_ptr()) };
+
+    if result != bindings::MagickBooleanType_MagickTrue {
+        return Err("failed to annotate the image".into());
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "magick")]
+pub fn decode(input: ImageResource) -> Result<ImageResource, MagickError> {
+    if let ImageResource::MagickWand(_) = input {
+        return Ok(input);
+    }
+
+    let mut mw = None;
+
+    image_convert::identify_read(&mut mw, &input)?;
+
+    Ok(ImageResource::MagickWand(mw.unwrap()))
+}
+
+// SVG images are rasterized at the given density, or at the one which makes them fit inside
+// `max_size`, so that they are not enlarged as bitmaps afterwards
+#[cfg(feature = "magick")]
+pub fn rasterize_svg(
+    input: &ImageResource,
+    density: Option<f64>,
+    (max_width, max_height): (u16, u16),
+    only_shrink: bool,
+) -> Result<MagickWand, MagickError> {
+    let density = match density {
+        Some(density) => density,
+        None => {
+            let ping = ping(input)?;
+
+            // ImageMagick renders SVG images at 96 DPI by default
+            let base_density = match ping.get_image_resolution()? {
+                (x, _) if x > 0f64 => x,
+                _ => 96f64,
+            };
+
+            let (width, height) =
+                (ping.get_image_width().max(1) as u32, ping.get_image_height().max(1) as u32);
+
+            match compute_output_size(only_shrink, width, height, max_width, max_height) {
+                Some((output_width, _)) => {
+                    base_density * f64::from(output_width) / f64::from(width)
+                },
+                None => base_density,
+            }
+        },
+    };
+
+    let mw = MagickWand::new();
+
+    let mut background = PixelWand::new();
+    background.set_color("none")?;
+
+    mw.set_background_color(&background)?;
+    mw.set_resolution(density, density)?;
+
+    match input {
+        ImageResource::Path(p) => mw.read_image(p.as_str())?,
+        ImageResource::Data(b) => mw.read_image_blob(b)?,
+        ImageResource::MagickWand(mw) => return Ok(mw.clone()),
+    }
+
+    Ok(mw)
+}
+
+// the pseudo-random number generator of ImageMagick, which is seeded with the time otherwise
+#[cfg(feature = "magick")]
+pub fn set_seed(seed: u32) {
+    // ImageMagick is initialized along with the first wand, which would seed the generator again
+    let _ = MagickWand::new();
+
+    unsafe { bindings::MagickSetSeed(seed.into()) }
+}
+
+// the settings of ImageMagick for a run, which are lost whenever it is restarted
+#[cfg(feature = "magick")]
+pub fn start_magick(args: &CLIArgs) -> Result<(), MagickError> {
+    magick_rust::magick_wand_genesis();
+
+    if let Some(seed) = args.seed {
+        set_seed(seed);
+    }
+
+    match args.memory_limit {
+        Some(memory_limit) => limit_memory(memory_limit),
+        None => Ok(()),
+    }
+}
+
+// tear ImageMagick down and start it again, which releases the memory that it keeps between
+// images, such as the registry, the caches of its coders and the fragments of its pixel caches,
+// so no wand may be alive meanwhile
+#[cfg(feature = "magick")]
+pub fn restart_magick(args: &CLIArgs) -> Result<(), MagickError> {
+    magick_rust::magick_wand_terminus();
+
+    start_magick(args)
+}
+
+// the pixel caches beyond the limit are kept on the disk instead of the heap or memory maps
+#[cfg(feature = "magick")]
+fn limit_memory(mebibytes: u32) -> Result<(), MagickError> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        let bytes = u64::from(mebibytes) << 20;
+
+        MagickWand::set_resource_limit(magick_rust::ResourceType::Memory, bytes)?;
+        MagickWand::set_resource_limit(magick_rust::ResourceType::Map, bytes)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = mebibytes;
+
+        Err(MagickError("--magick-memory-limit is only supported on Linux and macOS"))
+    }
+}
+
+#[cfg(feature = "magick")]
+pub fn adjust(args: &CLIArgs, input: ImageResource) -> Result<ImageResource, MagickError> {
+    let has_adjustments = args.auto_orient
+        || args.rotate.is_some()
+        || args.flip.is_some()
+        || args.trim_margins.is_some()
+        || args.crop.is_some()
+        || args.pad.is_some()
+        || args.auto_level.is_some()
+        || args.brightness.is_some()
+        || args.contrast.is_some()
+        || args.saturation.is_some()
+        || args.gamma.is_some();
+
+    if !has_adjustments {
+        return Ok(input);
+    }
+
+    let mut mw = None;
+
+    image_convert::identify_read(&mut mw, &input)?;
+
+    let mut mw = mw.unwrap();
+
+    // upright first, so that the margins and the crop are given as the image is viewed
+    if args.auto_orient && mw.requires_orientation() && !mw.auto_orient() {
+        return Err("failed to orient the image".into());
+    }
+
+    if let Some(rotation) = args.rotate {
+        // right angles leave no corners to be filled
+        mw.rotate_image(&PixelWand::new(), rotation.degrees())?;
+    }
+
+    match args.flip {
+        Some(Flip::Horizontal) => mw.flop_image()?,
+        Some(Flip::Vertical) => mw.flip_image()?,
+        None => (),
+    }
+
+    if let Some(margins) = args.trim_margins {
+        mw = trim_margins(&mw, margins)?;
+    }
+
+    if let Some(crop) = args.crop {
+        mw = crop_to_cover(&mw, crop, args.gravity)?;
+    }
+
+    if let Some(auto_level) = args.auto_level {
+        match auto_level {
+            AutoLevel::Channels => {
+                for channel in [
+                    bindings::ChannelType_RedChannel,
+                    bindings::ChannelType_GreenChannel,
+                    bindings::ChannelType_BlueChannel,
+                ] {
+                    let channel_mask = mw.set_image_channel_mask(channel);
+
+                    mw.auto_level()?;
+
+                    mw.set_image_channel_mask(channel_mask);
+                }
+            },
+            AutoLevel::Luminance => {
+                let colorspace = mw.get_image_colorspace();
+
+                // the first channel of the Lab colorspace is the lightness
+                mw.transform_image_colorspace(bindings::ColorspaceType_LabColorspace)?;
+
+                let channel_mask = mw.set_image_channel_mask(bindings::ChannelType_RedChannel);
+
+                mw.auto_level()?;
+
+                mw.set_image_channel_mask(channel_mask);
+
+                mw.transform_image_colorspace(colorspace)?;
+            },
+        }
+    }
+
+    if args.brightness.is_some() || args.contrast.is_some() {
+        mw.brightness_contrast_image(
+            f64::from(args.brightness.unwrap_or(0)),
+            f64::from(args.contrast.unwrap_or(0)),
+        )?;
+    }
+
+    if let Some(saturation) = args.saturation {
+        mw.modulate_image(100f64, 100f64 + f64::from(saturation), 100f64)?;
+    }
+
+    // applied to the sRGB values, in which the images are also resized; a resize in linear light
+    // would have to convert the image back to sRGB before the gamma is applied
+    if let Some(gamma) = args.gamma {
+        mw.level_image(0f64, gamma, 1f64)?;
+    }
+
+    // padded after the colors are adjusted, so that the padding keeps the background color
+    if let Some(pad_box) = args.pad {
+        mw = pad_to_contain(&mw, pad_box, args.only_shrink, args.background)?;
+    }
+
+    Ok(ImageResource::MagickWand(mw))
+}
+
+#[cfg(feature = "magick")]
+fn trim_margins(mw: &MagickWand, margins: Margins) -> Result<MagickWand, MagickError> {
+    let (width, height) = (mw.get_image_width(), mw.get_image_height());
+
+    let top = margins.top.to_pixels(height);
+    let right = margins.right.to_pixels(width);
+    let bottom = margins.bottom.to_pixels(height);
+    let left = margins.left.to_pixels(width);
+
+    if left + right >= width || top + bottom >= height {
+        return Err("the margins are larger than the image".into());
+    }
+
+    crop(mw, width - left - right, height - top - bottom, left, top)
+}
+
+#[cfg(feature = "magick")]
+#[inline]
+pub fn resize(args: &CLIArgs, input: &ImageResource) -> Result<MagickWand, MagickError> {
+    resize_to(args, input, args.max_size())
+}
+
+#[cfg(feature = "magick")]
+pub fn resize_to(
+    args: &CLIArgs,
+    input: &ImageResource,
+    (width, height): (u16, u16),
+) -> Result<MagickWand, MagickError> {
+    let mut config = image_convert::PNGConfig::new();
+
+    config.remain_profile = args.remains_profile();
+    config.width = width;
+    config.height = height;
+    config.shrink_only = args.only_shrink;
+
+    if let Some(sharpen) = args.sharpening() {
+        config.sharpen = sharpen;
+    }
+
+    let filtered =
+        resize_with_filter(args, input, (width, height), config.sharpen)?.map(|(mw, sharpen)| {
+            config.width = saturate(mw.get_image_width());
+            config.height = saturate(mw.get_image_height());
+            config.sharpen = sharpen;
+
+            ImageResource::MagickWand(mw)
+        });
+
+    let mut output = ImageResource::MagickWand(MagickWand::new());
+
+    image_convert::to_png(&mut output, filtered.as_ref().unwrap_or(input), &config)?;
+
+    let mw = output.into_magick_wand().unwrap();
+
+    finish(args, input, &mw)?;
+
+    Ok(mw)
+}
+
+// image-convert always resizes with the Lanczos filter, so the other filters resize the image to
+// its output size here, and image-convert is given that size, so that it only sharpens the image
+// by the returned sigma, which is negative for the strength chosen by the scale
+#[cfg(feature = "magick")]
+pub fn resize_with_filter(
+    args: &CLIArgs,
+    input: &ImageResource,
+    (width, height): (u16, u16),
+    sharpen: f64,
+) -> Result<Option<(MagickWand, f64)>, MagickError> {
+    let filter = match args.filter {
+        ResizeFilter::Lanczos => return Ok(None),
+        ResizeFilter::Mitchell => bindings::FilterType_MitchellFilter,
+        ResizeFilter::Catrom => bindings::FilterType_CatromFilter,
+        ResizeFilter::Nearest => bindings::FilterType_PointFilter,
+        ResizeFilter::Box => bindings::FilterType_BoxFilter,
+    };
+
+    let mut mw = None;
+
+    image_convert::identify_read(&mut mw, input)?;
+
+    let mw = mw.unwrap();
+
+    let size = (mw.get_image_width() as u32, mw.get_image_height() as u32);
+
+    let output_size = match compute_output_size(args.only_shrink, size.0, size.1, width, height) {
+        Some(output_size) => output_size,
+        None => return Ok(None),
+    };
+
+    mw.resize_image(output_size.0 as usize, output_size.1 as usize, filter);
+
+    // the pixels of the nearest neighbor are kept crisp
+    let sharpen = if sharpen >= 0f64 {
+        sharpen
+    } else if args.filter == ResizeFilter::Nearest {
+        0f64
+    } else {
+        auto_sharpen(size, output_size)
+    };
+
+    Ok(Some((mw, sharpen)))
+}
+
+// the same size as image-convert's, which fits inside the box while keeping the aspect ratio, or
+// None if the image keeps its size; the sides of the image may be longer than the ones of the box,
+// and no side is rounded down to 0
+pub fn compute_output_size(
+    only_shrink: bool,
+    width: u32,
+    height: u32,
+    max_width: u16,
+    max_height: u16,
+) -> Option<(u32, u32)> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let limit = |max: u16, side: u32| {
+        let max = u32::from(max);
+
+        if max == 0 || (only_shrink && max > side) {
+            side
+        } else {
+            max
+        }
+    };
+
+    let (mut output_width, mut output_height) =
+        (limit(max_width, width), limit(max_height, height));
+
+    if output_width == width && output_height == height {
+        return None;
+    }
+
+    let ratio = f64::from(width) / f64::from(height);
+
+    if f64::from(width) / f64::from(output_width) >= f64::from(height) / f64::from(output_height) {
+        output_height = ((f64::from(output_width) / ratio).round() as u32).max(1);
+    } else {
+        output_width = ((f64::from(output_height) * ratio).round() as u32).max(1);
+    }
+
+    Some((output_width, output_height))
+}
+
+// the same strength as image-convert's, which grows with the output size and the scale
+pub fn auto_sharpen((width, height): (u32, u32), (output_width, output_height): (u32, u32)) -> f64 {
+    let (original_pixels, output_pixels) =
+        (f64::from(width) * f64::from(height), f64::from(output_width) * f64::from(output_height));
+
+    let (m, n) = if original_pixels >= output_pixels {
+        (original_pixels, output_pixels)
+    } else {
+        (output_pixels, original_pixels)
+    };
+
+    ((output_pixels / 5_000_000f64).sqrt() * ((m - n) / m)).min(3f64)
+}
+
+// the sizes given to image-convert are u16, so a longer side is clamped instead of being wrapped
+#[inline]
+pub fn saturate(side: impl TryInto<u16>) -> u16 {
+    side.try_into().unwrap_or(u16::MAX)
+}
+
+pub fn tile_offsets(length: usize, tile: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let mut offsets = Vec::new();
+
+    let mut offset = 0;
+
+    loop {
+        let size = tile.min(length - offset);
+
+        offsets.push((offset, size));
+
+        if offset + size >= length {
+            break;
+        }
+
+        offset += tile - overlap;
+    }
+
+    offsets
+}
+
+pub fn tile_path(path: &Path, row: usize, column: usize) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+
+    file_name.push(format!("_{row}_{column}"));
+
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
+pub fn ladder_path(template: &str, path: &Path, quality: u8) -> PathBuf {
+    let file_name = template
+        .replace("{stem}", &path.file_stem().unwrap_or_default().to_string_lossy())
+        .replace("{quality}", &quality.to_string())
+        .replace("{ext}", &path.extension().unwrap_or_default().to_string_lossy());
+
+    path.with_file_name(file_name)
+}
+
+pub fn size_path(template: &str, path: &Path, size: u16) -> PathBuf {
+    let file_name = template
+        .replace("{stem}", &path.file_stem().unwrap_or_default().to_string_lossy())
+        .replace("{size}", &size.to_string())
+        .replace("{ext}", &path.extension().unwrap_or_default().to_string_lossy());
+
+    path.with_file_name(file_name)
+}
+
+pub fn template_path(template: &str, path: &Path, width: usize, height: usize) -> PathBuf {
+    let file_name = template
+        .replace("{stem}", &path.file_stem().unwrap_or_default().to_string_lossy())
+        .replace("{width}", &width.to_string())
+        .replace("{height}", &height.to_string())
+        .replace("{ext}", &path.extension().unwrap_or_default().to_string_lossy());
+
+    path.with_file_name(file_name)
+}
+
+pub fn affixed_path(path: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let mut file_name = OsString::from(prefix);
+
+    file_name.push(path.file_stem().unwrap_or_default());
+    file_name.push(suffix);
+
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
+// whether a file looks like an output of --prefix and --suffix
+pub fn is_affixed(path: &Path, prefix: &str, suffix: &str) -> bool {
+    match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) => {
+            stem.len() > prefix.len() + suffix.len()
+                && stem.starts_with(prefix)
+                && stem.ends_with(suffix)
+        },
+        None => false,
+    }
+}
+
+pub fn dpr_path(path: &Path, dpr: u8) -> PathBuf {
+    if dpr == 1 {
+        return path.to_path_buf();
+    }
+
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+
+    file_name.push(format!("@{dpr}x"));
+
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
+// JPEG and PGM cannot carry an alpha channel, and dropping it would turn the transparent pixels
+// black, so they are composited over --background, which is taken as opaque
+#[cfg(feature = "magick")]
+pub fn flatten(mw: &MagickWand, background: Color) -> Result<(), MagickError> {
+    if !mw.get_image_alpha_channel() {
+        return Ok(());
+    }
+
+    let background = Color {
+        alpha: 255,
+        ..background
+    };
+
+    let mut color = PixelWand::new();
+    color.set_color(&background.to_hex())?;
+
+    mw.set_image_background_color(&color)?;
+    mw.set_image_alpha_channel(bindings::AlphaChannelOption_RemoveAlphaChannel)
+}
+
+// the image is cropped to the aspect ratio of the box here, and resized to the box afterwards
+#[cfg(feature = "magick")]
+fn crop_to_cover(
+    mw: &MagickWand,
+    crop_box: CropBox,
+    gravity: Gravity,
+) -> Result<MagickWand, MagickError> {
+    let size = (mw.get_image_width(), mw.get_image_height());
+
+    let region = cover_region(size, crop_box);
+
+    let (x, y) = crop_offset(size, region, gravity, |width, height| {
+        let small = mw.clone();
+
+        small.sample_image(width, height)?;
+
+        small
+            .export_image_pixels(0, 0, width, height, "I")
+            .ok_or(MagickError("failed to export pixels"))
+    })?;
+
+    crop(mw, region.0, region.1, x, y)
+}
+
+// the image is padded to the aspect ratio of the box here, and resized to the box afterwards
+#[cfg(feature = "magick")]
+fn pad_to_contain(
+    mw: &MagickWand,
+    pad_box: CropBox,
+    only_shrink: bool,
+    background: Color,
+) -> Result<MagickWand, MagickError> {
+    let (width, height) = (mw.get_image_width(), mw.get_image_height());
+
+    let (canvas_width, canvas_height) = contain_region((width, height), pad_box, only_shrink);
+
+    let mw = mw.clone();
+
+    let mut color = PixelWand::new();
+    color.set_color(&background.to_hex())?;
+
+    mw.set_image_background_color(&color)?;
+
+    // a negative offset moves the image into the canvas
+    mw.extend_image(
+        canvas_width,
+        canvas_height,
+        -(((canvas_width - width) / 2) as isize),
+        -(((canvas_height - height) / 2) as isize),
+    )?;
+
+    Ok(mw)
+}
+
+// the smallest canvas with the aspect ratio of the box which contains an image, or the box itself
+// for an image which fits inside it and is not enlarged
+#[cfg(feature = "magick")]
+fn contain_region(
+    (width, height): (usize, usize),
+    pad_box: CropBox,
+    only_shrink: bool,
+) -> (usize, usize) {
+    let (pad_width, pad_height) = (usize::from(pad_box.width), usize::from(pad_box.height));
+
+    if only_shrink && width <= pad_width && height <= pad_height {
+        return (pad_width, pad_height);
+    }
+
+    if width * pad_height > height * pad_width {
+        (width, ((width * pad_height + pad_width / 2) / pad_width).max(height))
+    } else {
+        (((height * pad_width + pad_height / 2) / pad_height).max(width), height)
+    }
+}
+
+// the largest region of an image with the aspect ratio of the box
+pub fn cover_region((width, height): (usize, usize), crop_box: CropBox) -> (usize, usize) {
+    let (crop_width, crop_height) = (usize::from(crop_box.width), usize::from(crop_box.height));
+
+    if width * crop_height > height * crop_width {
+        (((height * crop_width + crop_height / 2) / crop_height).max(1), height)
+    } else {
+        (width, ((width * crop_height + crop_width / 2) / crop_width).max(1))
+    }
+}
+
+// the side of the copy of an image whose details are measured for the attention gravity
+const ATTENTION_SIDE: usize = 256;
+
+// `luma` gives the lightness of the image sampled to a size, which is only needed by the
+// attention gravity
+pub fn crop_offset<E, F: FnOnce(usize, usize) -> Result<Vec<u8>, E>>(
+    (width, height): (usize, usize),
+    (region_width, region_height): (usize, usize),
+    gravity: Gravity,
+    luma: F,
+) -> Result<(usize, usize), E> {
+    let center = ((width - region_width) / 2, (height - region_height) / 2);
+
+    match gravity {
+        Gravity::Center => Ok(center),
+        Gravity::Top => Ok((center.0, 0)),
+        Gravity::Attention => {
+            let horizontal = region_width < width;
+
+            if !horizontal && region_height == height {
+                return Ok((0, 0));
+            }
+
+            let scale = (ATTENTION_SIDE as f64 / width.max(height) as f64).min(1f64);
+
+            let (small_width, small_height) = (
+                ((width as f64 * scale).round() as usize).max(1),
+                ((height as f64 * scale).round() as usize).max(1),
+            );
+
+            let luma = luma(small_width, small_height)?;
+
+            // the differences between neighboring pixels, summed along the side which is not cropped
+            let mut energy = vec![0u64; if horizontal { small_width } else { small_height }];
+
+            for y in 0..small_height {
+                for x in 0..small_width {
+                    let l = i32::from(luma[y * small_width + x]);
+
+                    let dx = if x + 1 < small_width {
+                        (i32::from(luma[y * small_width + x + 1]) - l).unsigned_abs()
+                    } else {
+                        0
+                    };
+
+                    let dy = if y + 1 < small_height {
+                        (i32::from(luma[(y + 1) * small_width + x]) - l).unsigned_abs()
+                    } else {
+                        0
+                    };
+
+                    energy[if horizontal { x } else { y }] += u64::from(dx + dy);
+                }
+            }
+
+            let (side, region_side) =
+                if horizontal { (width, region_width) } else { (height, region_height) };
+
+            let window = ((region_side * energy.len() + side / 2) / side).clamp(1, energy.len());
+
+            let start = best_window(&energy, window);
+
+            let offset = ((start * side + energy.len() / 2) / energy.len()).min(side - region_side);
+
+            if horizontal {
+                Ok((offset, center.1))
+            } else {
+                Ok((center.0, offset))
+            }
+        },
+    }
+}
+
+// the start of the window with the most energy, preferring the center when nothing stands out
+fn best_window(energy: &[u64], window: usize) -> usize {
+    let mut sums = Vec::with_capacity(energy.len() - window + 1);
+
+    let mut sum: u64 = energy[..window].iter().sum();
+
+    sums.push(sum);
+
+    for i in window..energy.len() {
+        sum = sum + energy[i] - energy[i - window];
+
+        sums.push(sum);
+    }
+
+    let mut best = sums.len() / 2;
+
+    for (start, sum) in sums.iter().enumerate() {
+        if *sum > sums[best] {
+            best = start;
+        }
+    }
+
+    best
+}
+
+#[cfg(feature = "magick")]
+pub fn crop(
+    mw: &MagickWand,
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+) -> Result<MagickWand, MagickError> {
+    let mw = mw.clone();
+
+    mw.crop_image(width, height, x as isize, y as isize)?;
+    mw.reset_image_page("0x0+0+0")?;
+
+    Ok(mw)
+}
+
+#[cfg(feature = "magick")]
+pub fn select_frame(input: &ImageResource, frame: Option<usize>) -> anyhow::Result<MagickWand> {
+    let mut mw = None;
+
+    image_convert::identify_read(&mut mw, input)?;
+
+    let mut mw = mw.unwrap();
+
+    let mut sizes = Vec::new();
+
+    mw.set_first_iterator();
+
+    loop {
+        sizes.push(mw.get_image_width() * mw.get_image_height());
+
+        if !mw.next_image() {
+            break;
+        }
+    }
+
+    let index = match frame {
+        Some(frame) => {
+            if frame >= sizes.len() {
+                return Err(anyhow!("There are only {} frames.", sizes.len()));
+            }
+
+            frame
+        },
+        None => (0..sizes.len()).rev().max_by_key(|&i| sizes[i]).unwrap(),
+    };
+
+    mw.set_iterator_index(index as isize)?;
+
+    let frame_wand = unsafe { bindings::MagickGetImage(mw.wand) };
+
+    if frame_wand.is_null() {
+        return Err(anyhow!("failed to get frame {index}"));
+    }
+
+    Ok(MagickWand::new_from_wand(frame_wand))
+}
+
+#[cfg(feature = "magick")]
+#[inline]
+pub fn frame_count(mw: &MagickWand) -> usize {
+    unsafe { bindings::MagickGetNumberImages(mw.wand) }
+}
+
+// the frames are coalesced into full images first, so that each of them can be adjusted and resized
+// on its own, and then they get the timing of the original frames
+#[cfg(feature = "magick")]
+pub fn resize_animation(
+    args: &CLIArgs,
+    mut mw: MagickWand,
+    format: &str,
+    timing: &Timing,
+) -> anyhow::Result<MagickWand> {
+    mw.coalesce()?;
+
+    let mut animation = MagickWand::new();
+
+    for index in 0..frame_count(&mw) {
+        mw.set_iterator_index(index as isize)?;
+
+        let frame = unsafe { bindings::MagickGetImage(mw.wand) };
+
+        if frame.is_null() {
+            return Err(anyhow!("failed to get frame {index}"));
+        }
+
+        let frame = adjust(args, ImageResource::MagickWand(MagickWand::new_from_wand(frame)))?;
+
+        let mut frame = resize(args, &frame)?;
+
+        frame.reset_image_page("0x0+0+0")?;
+
+        timing.apply(index, &mut frame)?;
+
+        match format {
+            "WEBP" => frame.set_image_compression_quality(args.quality as usize)?,
+            "GIF" => quantize_gif(args, &frame)?,
+            _ => unreachable!(),
+        }
+
+        let result = unsafe { bindings::MagickAddImage(animation.wand, frame.wand) };
+
+        if result != bindings::MagickBooleanType_MagickTrue {
+            return Err(anyhow!("failed to add frame {index}"));
+        }
+    }
+
+    // the frames are put together here instead of by image-convert, so they are not interlaced
+    // unless it is asked for
+    if format == "GIF" {
+        set_interlace(&mut animation, args.interlace_scheme())?;
+    }
+
+    Ok(animation)
+}
+
+#[cfg(feature = "magick")]
+pub fn quantize_gif(args: &CLIArgs, mw: &MagickWand) -> Result<(), MagickError> {
+    match args.gif_palette {
+        GifPalette::Reuse => match args.dither {
+            Some(dither) => quantize(mw, 256, dither),
+            None => Ok(()),
+        },
+        // the palette is chosen in a perceptual colorspace, so that its colors are spread evenly
+        // over the steps of gradients, and the pixels are converted back to sRGB afterwards
+        GifPalette::Regenerate => quantize_in(
+            mw,
+            256,
+            bindings::ColorspaceType_LabColorspace,
+            args.dither.unwrap_or(Dither::FloydSteinberg),
+        ),
+    }
+}
+
+#[cfg(feature = "magick")]
+#[inline]
+pub fn quantize(mw: &MagickWand, colors: usize, dither: Dither) -> Result<(), MagickError> {
+    quantize_in(mw, colors, mw.get_image_colorspace(), dither)
+}
+
+#[cfg(feature = "magick")]
+fn quantize_in(
+    mw: &MagickWand,
+    colors: usize,
+    colorspace: bindings::ColorspaceType,
+    dither: Dither,
+) -> Result<(), MagickError> {
+    let dither_method = match dither {
+        Dither::None => bindings::DitherMethod_NoDitherMethod,
+        Dither::FloydSteinberg => bindings::DitherMethod_FloydSteinbergDitherMethod,
+        Dither::Ordered => {
+            // posterize every channel to 6 levels (216 colors) with an 8x8 Bayer matrix, so that
+            // the image fits in the palette without being dithered again
+            let threshold_map = CString::new("o8x8,6").unwrap();
+
+            let result =
+                unsafe { bindings::MagickOrderedDitherImage(mw.wand, threshold_map.as_ptr()) };
+
+            if result != bindings::MagickBooleanType_MagickTrue {
+                return Err("failed to do ordered dithering".into());
+            }
+
+            bindings::DitherMethod_NoDitherMethod
+        },
+    };
+
+    mw.quantize_image(colors, colorspace, 0, dither_method, bindings::MagickBooleanType_MagickFalse)
+}
+
+#[cfg(feature = "magick")]
+pub fn set_density(
+    mw: &mut MagickWand,
+    density: f64,
+    unit: DensityUnit,
+    format: &str,
+) -> Result<(), MagickError> {
+    let density = density.max(0f64);
+
+    let units = match unit {
+        DensityUnit::Ppi => bindings::ResolutionType_PixelsPerInchResolution,
+        DensityUnit::Ppcm => bindings::ResolutionType_PixelsPerCentimeterResolution,
+    };
+
+    mw.set_image_resolution(density, density)?;
+    mw.set_image_units(units)?;
+
+    // WebP has no density field of its own, so it is written into an EXIF profile, unless the
+    // image keeps an EXIF profile of its own
+    if format == "WEBP" && get_profile(mw, "exif").is_none() {
+        set_profile(mw, "exif", &exif_density(density, unit))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "magick")]
+fn get_profile(mw: &MagickWand, name: &str) -> Option<Vec<u8>> {
+    let name = CString::new(name).unwrap();
+
+    let mut length = 0;
+
+    let profile = unsafe { bindings::MagickGetImageProfile(mw.wand, name.as_ptr(), &mut length) };
+
+    if profile.is_null() {
+        return None;
+    }
+
+    let data = unsafe { slice::from_raw_parts(profile, length) }.to_vec();
+
+    unsafe { bindings::MagickRelinquishMemory(profile as *mut c_void) };
+
+    Some(data)
+}
+
+#[cfg(feature = "magick")]
+fn remove_profile(mw: &MagickWand, name: &str) {
+    let name = CString::new(name).unwrap();
+
+    let mut length = 0;
+
+    // the removed profile is returned, or null if there was none
+    let profile =
+        unsafe { bindings::MagickRemoveImageProfile(mw.wand, name.as_ptr(), &mut length) };
+
+    if !profile.is_null() {
+        unsafe { bindings::MagickRelinquishMemory(profile as *mut c_void) };
+    }
+}
+
+#[cfg(feature = "magick")]
+fn set_profile(mw: &MagickWand, name: &str, data: &[u8]) -> Result<(), MagickError> {
+    let name = CString::new(name).unwrap();
+
+    let result = unsafe {
+        bindings::MagickSetImageProfile(
+            mw.wand,
+            name.as_ptr(),
+            data.as_ptr() as *const c_void,
+            data.len(),
+        )
+    };
+
+    if result != bindings::MagickBooleanType_MagickTrue {
+        return Err("failed to set the profile".into());
+    }
+
+    Ok(())
+}
+
+// a little-endian TIFF structure with only the XResolution, YResolution and ResolutionUnit tags
+#[cfg(feature = "magick")]
+fn exif_density(density: f64, unit: DensityUnit) -> Vec<u8> {
+    const DATA_OFFSET: u32 = 8 + 2 + 3 * 12 + 4;
+
+    let numerator = (density * 100f64).round().min(f64::from(u32::MAX)) as u32;
+
+    let resolution_unit: u16 = match unit {
+        DensityUnit::Ppi => 2,
+        DensityUnit::Ppcm => 3,
+    };
+
+    let mut exif = Vec::with_capacity(DATA_OFFSET as usize + 16);
+
+    exif.extend_from_slice(b"II");
+    exif.extend_from_slice(&42u16.to_le_bytes());
+    exif.extend_from_slice(&8u32.to_le_bytes());
+
+    exif.extend_from_slice(&3u16.to_le_bytes());
+
+    // the tag, the type (5 for RATIONAL and 3 for SHORT), the count, and the value or its offset
+    for (tag, offset) in [(0x011Au16, DATA_OFFSET), (0x011B, DATA_OFFSET + 8)] {
+        exif.extend_from_slice(&tag.to_le_bytes());
+        exif.extend_from_slice(&5u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    exif.extend_from_slice(&0x0128u16.to_le_bytes());
+    exif.extend_from_slice(&3u16.to_le_bytes());
+    exif.extend_from_slice(&1u32.to_le_bytes());
+    exif.extend_from_slice(&resolution_unit.to_le_bytes());
+    exif.extend_from_slice(&[0, 0]);
+
+    // no next IFD
+    exif.extend_from_slice(&0u32.to_le_bytes());
+
+    for _ in 0..2 {
+        exif.extend_from_slice(&numerator.to_le_bytes());
+        exif.extend_from_slice(&100u32.to_le_bytes());
+    }
+
+    exif
+}
+
+#[cfg(feature = "magick")]
+pub fn encode_png(
+    mw: &mut MagickWand,
+    color_type: PngColorType,
+    dither: Option<Dither>,
+) -> Result<Vec<u8>, MagickError> {
+    // the values of the color type field in the IHDR chunk
+    let color_type = match color_type {
+        PngColorType::Auto => match unsafe { bindings::MagickIdentifyImageType(mw.wand) } {
+            bindings::ImageType_BilevelType | bindings::ImageType_GrayscaleType => "0",
+            bindings::ImageType_GrayscaleAlphaType => "4",
+            bindings::ImageType_PaletteType
+            | bindings::ImageType_PaletteAlphaType
+            | bindings::ImageType_PaletteBilevelAlphaType => "3",
+            bindings::ImageType_TrueColorType => "2",
+            _ => "6",
+        },
+        PngColorType::Rgb => "2",
+        PngColorType::Rgba => "6",
+        PngColorType::Gray => "0",
+        PngColorType::Palette => {
+            quantize(mw, 256, dither.unwrap_or(Dither::None))?;
+
+            "3"
+        },
+    };
+
+    mw.set_option("png:color-type", color_type)?;
+
+    mw.write_image_blob("PNG")
+}
+
+// image-convert interlaces the JPEG, PNG and GIF images it encodes by line, which is replaced by
+// --interlace
+#[cfg(feature = "magick")]
+#[inline]
+pub fn set_interlace(mw: &mut MagickWand, interlace: Option<Interlace>) -> Result<(), MagickError> {
+    match interlace {
+        Some(Interlace::None) => mw.set_interlace_scheme(bindings::InterlaceType_NoInterlace),
+        Some(Interlace::Line) => mw.set_interlace_scheme(bindings::InterlaceType_LineInterlace),
+        Some(Interlace::Plane) => mw.set_interlace_scheme(bindings::InterlaceType_PlaneInterlace),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "magick")]
+pub fn encode_jpeg(
+    mw: &mut MagickWand,
+    restart_interval: Option<u16>,
+    arithmetic: bool,
+) -> Result<Vec<u8>, MagickError> {
+    if let Some(restart_interval) = restart_interval {
+        mw.set_option("jpeg:restart-interval", &restart_interval.to_string())?;
+    }
+
+    // ignored by ImageMagick if its JPEG library is built without arithmetic coding
+    if arithmetic {
+        mw.set_option("jpeg:arithmetic-coding", "true")?;
+    }
+
+    mw.write_image_blob("JPEG")
+}
+
+#[cfg(feature = "magick")]
+pub fn encode_heic(mw: &mut MagickWand, quality: u8) -> Result<Vec<u8>, MagickError> {
+    mw.set_image_compression_quality(usize::from(quality))?;
+
+    mw.write_image_blob("HEIC")
+}
+
+#[cfg(feature = "magick")]
+pub fn encode_jxl(
+    mw: &mut MagickWand,
+    quality: u8,
+    lossless: bool,
+) -> Result<Vec<u8>, MagickError> {
+    // the JXL coder of ImageMagick encodes losslessly at the quality of 100
+    let quality = if lossless { 100 } else { quality };
+
+    mw.set_image_compression_quality(usize::from(quality))?;
+
+    mw.write_image_blob("JXL")
+}
+
+// some builds of libheif can only decode, so try encoding a pixel once to find out
+#[cfg(feature = "magick")]
+pub fn can_encode_heic() -> bool {
+    static CAN_ENCODE_HEIC: OnceLock<bool> = OnceLock::new();
+
+    *CAN_ENCODE_HEIC.get_or_init(|| {
+        let mw = MagickWand::new();
+
+        mw.new_image(1, 1, &PixelWand::new()).and_then(|_| mw.write_image_blob("HEIC")).is_ok()
+    })
+}
+
+fn check_extension(output_path: &Path, extensions: &[&str]) -> anyhow::Result<()> {
+    let extension_matched = output_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| {
+            extension.eq_ignore_ascii_case_with_lowercase_multiple(extensions).is_some()
+        })
+        .unwrap_or(false);
+
+    if !extension_matched {
+        return Err(anyhow!("The file extension name is not {}.", extensions.join(" or ")));
+    }
+
+    Ok(())
+}
+
+pub fn write_output(
+    output_path: &Path,
+    extensions: &[&str],
+    data: &[u8],
+    mode: Option<u32>,
+) -> anyhow::Result<()> {
+    check_extension(output_path, extensions)?;
+
+    write_atomically(output_path, data, mode)?;
+
+    Ok(())
+}
+
+pub fn link_output(
+    original_path: &Path,
+    output_path: &Path,
+    extensions: &[&str],
+) -> anyhow::Result<()> {
+    check_extension(output_path, extensions)?;
+
+    let temp_path = temp_path(output_path);
+
+    let result =
+        fs::hard_link(original_path, &temp_path).and_then(|_| fs::rename(&temp_path, output_path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    Ok(result?)
+}
+
+#[inline]
+pub fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    data.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[inline]
+fn temp_path(output_path: &Path) -> PathBuf {
+    output_path.with_file_name(format!(
+        ".{}.image-resizer",
+        output_path.file_name().unwrap().to_string_lossy()
+    ))
+}
+
+// `mode` replaces the permissions of the output, which are kept otherwise
+pub fn write_atomically(output_path: &Path, data: &[u8], mode: Option<u32>) -> io::Result<()> {
+    // write next to the output first, so that a failed write never leaves a truncated file behind
+    let temp_path = temp_path(output_path);
+
+    let result = fs::write(&temp_path, data).and_then(|_| {
+        match mode {
+            Some(mode) => set_mode(&temp_path, mode)?,
+            None => {
+                if let Ok(metadata) = output_path.metadata() {
+                    fs::set_permissions(&temp_path, metadata.permissions())?;
+                }
+            },
+        }
+
+        fs::rename(&temp_path, output_path)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+// the directories which are missing are created with `mode`, which is not masked by the umask
+pub fn create_dir_all(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    let missing: Vec<&Path> = match mode {
+        Some(_) => path
+            .ancestors()
+            .take_while(|path| !path.as_os_str().is_empty() && !path.exists())
+            .collect(),
+        None => Vec::new(),
+    };
+
+    fs::create_dir_all(path)?;
+
+    if let Some(mode) = mode {
+        for path in missing {
+            set_mode(path, mode)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[inline]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+// only the write permission of the owner can be set on the other platforms
+#[cfg(not(unix))]
+#[inline]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = path.metadata()?.permissions();
+
+    permissions.set_readonly(mode & 0o200 == 0);
+
+    fs::set_permissions(path, permissions)
+}
+
+// whether the output has been modified after the input, which is false if either of them cannot be
+// read
+pub fn is_newer(output_path: &Path, input_path: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+
+    match (modified(output_path), modified(input_path)) {
+        (Ok(output), Ok(input)) => output > input,
+        _ => false,
+    }
+}
+
+// the output if it is read-only, otherwise the directory which it would be written in if that one is
+pub fn read_only_path(output_path: &Path) -> Option<&Path> {
+    if let Ok(metadata) = output_path.metadata() {
+        if metadata.permissions().readonly() {
+            return Some(output_path);
+        }
+    }
+
+    // the nearest existing ancestor, since missing directories are created when writing
+    let directory = output_path
+        .ancestors()
+        .skip(1)
+        .map(|path| if path.as_os_str().is_empty() { Path::new(".") } else { path })
+        .find(|path| path.is_dir())?;
+
+    match directory.metadata() {
+        Ok(metadata) if metadata.permissions().readonly() => Some(directory),
+        _ => None,
+    }
+}
+
+// make the output and its directory writable while `f` runs, then restore their permissions
+pub fn with_writable<T>(
+    output_path: &Path,
+    f: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let directory = match output_path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+
+    let mut restorations = Vec::with_capacity(2);
+
+    for path in [directory, output_path] {
+        if let Ok(metadata) = path.metadata() {
+            let permissions = metadata.permissions();
+
+            if permissions.readonly() {
+                fs::set_permissions(path, writable(&permissions))
+                    .with_context(|| anyhow!("{path:?}"))?;
+
+                restorations.push((path, permissions));
+            }
+        }
+    }
+
+    let result = f();
+
+    // the output is restored before its directory
+    for (path, permissions) in restorations.into_iter().rev() {
+        if path.exists() {
+            fs::set_permissions(path, permissions).with_context(|| anyhow!("{path:?}"))?;
+        }
+    }
+
+    result
+}
+
+#[cfg(unix)]
+#[inline]
+fn writable(permissions: &fs::Permissions) -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+
+    // only for the owner
+    fs::Permissions::from_mode(permissions.mode() | 0o200)
+}
+
+#[cfg(not(unix))]
+#[inline]
+fn writable(permissions: &fs::Permissions) -> fs::Permissions {
+    let mut permissions = permissions.clone();
+
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+
+    permissions
+}
+
+pub fn is_disk_full(error: &anyhow::Error) -> bool {
+    #[cfg(windows)]
+    const DISK_FULL_CODES: [i32; 2] = [39, 112]; // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+    #[cfg(not(windows))]
+    const DISK_FULL_CODES: [i32; 1] = [28]; // ENOSPC
+
+    error.chain().any(|cause| match cause.downcast_ref::<io::Error>() {
+        Some(error) => {
+            error.raw_os_error().map(|code| DISK_FULL_CODES.contains(&code)) == Some(true)
+        },
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_output_size_side_maximum() {
+        assert_eq!(Some((32, 24)), compute_output_size(false, 64, 48, 32, 32));
+        assert_eq!(Some((24, 32)), compute_output_size(false, 48, 64, 32, 32));
+
+        // rounded to the nearest pixel
+        assert_eq!(Some((100, 67)), compute_output_size(false, 300, 200, 100, 100));
+    }
+
+    #[test]
+    fn compute_output_size_independent_sides() {
+        // --max-width alone, and --max-height alone
+        assert_eq!(Some((1920, 1440)), compute_output_size(false, 4000, 3000, 1920, 0));
+        assert_eq!(Some((800, 600)), compute_output_size(false, 4000, 3000, 0, 600));
+
+        // a box whose height is the tighter side
+        assert_eq!(Some((800, 600)), compute_output_size(false, 4000, 3000, 1920, 600));
+
+        // a box whose width is the tighter side
+        assert_eq!(Some((600, 450)), compute_output_size(false, 4000, 3000, 600, 1920));
+    }
+
+    #[test]
+    fn compute_output_size_unchanged() {
+        assert_eq!(None, compute_output_size(false, 64, 48, 0, 0));
+        assert_eq!(None, compute_output_size(false, 64, 48, 64, 0));
+        assert_eq!(None, compute_output_size(true, 64, 48, 100, 100));
+        assert_eq!(None, compute_output_size(false, 0, 48, 32, 32));
+        assert_eq!(None, compute_output_size(false, 64, 0, 32, 32));
+    }
+
+    #[test]
+    fn compute_output_size_only_shrink() {
+        // enlarged without --only-shrink
+        assert_eq!(Some((200, 100)), compute_output_size(false, 100, 50, 200, 200));
+
+        // only the side which is larger than the box is shrunk
+        assert_eq!(Some((80, 40)), compute_output_size(true, 100, 50, 80, 200));
+        assert_eq!(Some((50, 25)), compute_output_size(true, 100, 50, 200, 25));
+    }
+
+    #[test]
+    fn compute_output_size_extreme_aspect_ratios() {
+        // the sides are not wrapped to u16
+        assert_eq!(Some((1024, 15)), compute_output_size(false, 70000, 1000, 1024, 1024));
+        assert_eq!(Some((35000, 500)), compute_output_size(false, 70000, 1000, 0, 500));
+
+        // a side is not rounded down to 0
+        assert_eq!(Some((1024, 1)), compute_output_size(false, 100000, 10, 1024, 1024));
+        assert_eq!(Some((1, 1024)), compute_output_size(false, 10, 100000, 1024, 1024));
+    }
+
+    fn crop_box(width: u16, height: u16) -> CropBox {
+        CropBox {
+            width,
+            height,
+        }
+    }
+
+    // the lightness of an image sampled to a size, which is dark except for a column or a row of
+    // stripes at `detail`, counted in the pixels of the full image
+    fn luma_with_detail(
+        (full_width, full_height): (usize, usize),
+        detail: usize,
+        horizontal: bool,
+    ) -> impl FnOnce(usize, usize) -> Result<Vec<u8>, ()> {
+        move |width, height| {
+            let mut luma = vec![0u8; width * height];
+
+            for y in 0..height {
+                for x in 0..width {
+                    let (position, side, full_side) =
+                        if horizontal { (x, width, full_width) } else { (y, height, full_height) };
+
+                    if position == detail * side / full_side && (x + y) % 2 == 0 {
+                        luma[y * width + x] = 255;
+                    }
+                }
+            }
+
+            Ok(luma)
+        }
+    }
+
+    #[test]
+    fn cover_region_aspect_ratios() {
+        assert_eq!((3000, 3000), cover_region((4000, 3000), crop_box(1, 1)));
+        assert_eq!((3000, 3000), cover_region((3000, 4000), crop_box(1, 1)));
+        assert_eq!((4000, 3000), cover_region((4000, 3000), crop_box(400, 300)));
+
+        // rounded to the nearest pixel
+        assert_eq!((1000, 667), cover_region((1000, 1000), crop_box(3, 2)));
+        assert_eq!((667, 1000), cover_region((1000, 1000), crop_box(2, 3)));
+
+        // a side is not rounded down to 0
+        assert_eq!((1, 1), cover_region((10000, 1), crop_box(1, 1)));
+        assert_eq!((1, 1), cover_region((1, 10000), crop_box(16, 9)));
+    }
+
+    #[test]
+    fn crop_offset_center_and_top() {
+        let no_luma = |_, _| -> Result<Vec<u8>, ()> { unreachable!() };
+
+        assert_eq!(Ok((500, 0)), crop_offset((4000, 3000), (3000, 3000), Gravity::Center, no_luma));
+        assert_eq!(Ok((0, 500)), crop_offset((3000, 4000), (3000, 3000), Gravity::Center, no_luma));
+
+        assert_eq!(Ok((500, 0)), crop_offset((4000, 3000), (3000, 3000), Gravity::Top, no_luma));
+        assert_eq!(Ok((0, 0)), crop_offset((3000, 4000), (3000, 3000), Gravity::Top, no_luma));
+
+        // an odd margin is rounded down
+        assert_eq!(Ok((0, 1)), crop_offset((5, 8), (5, 5), Gravity::Center, no_luma));
+    }
+
+    #[test]
+    fn crop_offset_attention_without_cropping() {
+        let no_luma = |_, _| -> Result<Vec<u8>, ()> { unreachable!() };
+
+        assert_eq!(Ok((0, 0)), crop_offset((400, 300), (400, 300), Gravity::Attention, no_luma));
+    }
+
+    #[test]
+    fn crop_offset_attention_follows_details() {
+        let size = (4000, 1000);
+        let region = (1000, 1000);
+
+        for detail in [100, 2000, 3900] {
+            let (x, y) =
+                crop_offset(size, region, Gravity::Attention, luma_with_detail(size, detail, true))
+                    .unwrap();
+
+            assert_eq!(0, y);
+            assert!(x <= detail && detail < x + region.0, "{detail} is outside of {x}");
+            assert!(x + region.0 <= size.0);
+        }
+
+        let size = (800, 3200);
+        let region = (800, 600);
+
+        for detail in [0, 1600, 3199] {
+            let (x, y) = crop_offset(
+                size,
+                region,
+                Gravity::Attention,
+                luma_with_detail(size, detail, false),
+            )
+            .unwrap();
+
+            assert_eq!(0, x);
+            assert!(y <= detail && detail < y + region.1, "{detail} is outside of {y}");
+            assert!(y + region.1 <= size.1);
+        }
+    }
+
+    #[test]
+    fn crop_offset_attention_flat_image() {
+        // the center is preferred when nothing stands out
+        let flat =
+            |width: usize, height: usize| -> Result<Vec<u8>, ()> { Ok(vec![128; width * height]) };
+
+        assert_eq!(Ok((150, 0)), crop_offset((400, 100), (100, 100), Gravity::Attention, flat));
+        assert_eq!(Ok((0, 150)), crop_offset((100, 400), (100, 100), Gravity::Attention, flat));
+    }
+
+    #[test]
+    fn crop_offset_attention_small_and_extreme_images() {
+        let flat =
+            |width: usize, height: usize| -> Result<Vec<u8>, ()> { Ok(vec![0; width * height]) };
+
+        // smaller than the sampled side, so the image is not scaled
+        assert_eq!(Ok((1, 0)), crop_offset((3, 1), (1, 1), Gravity::Attention, flat));
+
+        // so wide that its height is sampled to 1 pixel, and the window is 1 sampled pixel wide
+        assert_eq!(Ok((50000, 0)), crop_offset((100000, 10), (100, 10), Gravity::Attention, flat));
+    }
+
+    #[test]
+    fn best_window_sums() {
+        assert_eq!(3, best_window(&[0, 0, 0, 9, 9, 0], 2));
+        assert_eq!(0, best_window(&[5, 0, 0, 0, 0], 1));
+
+        // the center wins a tie, and so does the first of the others
+        assert_eq!(1, best_window(&[0, 0, 0], 2));
+        assert_eq!(2, best_window(&[1, 0, 1, 0, 1], 1));
+        assert_eq!(0, best_window(&[2, 0, 1, 0, 2], 1));
+
+        // a window as long as the energy
+        assert_eq!(0, best_window(&[1, 2, 3], 3));
+    }
+}
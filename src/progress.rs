@@ -0,0 +1,232 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{run_report::FileStatus, summary::Summary};
+
+const WINDOW_SIZE: usize = 64;
+
+// the work of an image is measured by its bytes, or by its pixels once the images have been
+// identified with --identify-first
+#[derive(Debug)]
+pub struct Progress {
+    total_files: usize,
+    total_work:  u64,
+    done_files:  usize,
+    done_work:   u64,
+    weights:     Option<HashMap<PathBuf, u64>>,
+    start:       Instant,
+    window:      VecDeque<(Instant, u64, u64)>,
+    current:     Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status<'a> {
+    total:                 usize,
+    done:                  usize,
+    resized:               usize,
+    skipped:               usize,
+    failed:                usize,
+    permission_denied:     usize,
+    current:               &'a [PathBuf],
+    files_per_second:      f64,
+    megapixels_per_second: f64,
+    eta_seconds:           Option<f64>,
+    elapsed_seconds:       f64,
+    resident_memory_bytes: Option<u64>,
+}
+
+// a line of --json-stream, which is printed when an image is done
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    path:                  &'a Path,
+    status:                FileStatus,
+    done:                  usize,
+    total:                 usize,
+    files_per_second:      f64,
+    megapixels_per_second: f64,
+    eta_seconds:           Option<f64>,
+}
+
+impl Progress {
+    pub fn new(
+        total_files: usize,
+        total_work: u64,
+        weights: Option<HashMap<PathBuf, u64>>,
+    ) -> Progress {
+        Progress {
+            total_files,
+            total_work,
+            done_files: 0,
+            done_work: 0,
+            weights,
+            start: Instant::now(),
+            window: VecDeque::with_capacity(WINDOW_SIZE),
+            current: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn done_files(&self) -> usize {
+        self.done_files
+    }
+
+    #[inline]
+    pub fn current(&self) -> &[PathBuf] {
+        &self.current
+    }
+
+    #[inline]
+    pub fn start_file(&mut self, path: &Path) {
+        self.current.push(path.to_path_buf());
+    }
+
+    // the pixels of an image if the images have been identified, where the ones which would be
+    // skipped or could not be identified take no work
+    #[inline]
+    pub fn weight(&self, path: &Path) -> Option<u64> {
+        self.weights.as_ref().map(|weights| weights.get(path).copied().unwrap_or(0))
+    }
+
+    pub fn record(&mut self, path: &Path, work: u64, pixels: u64) {
+        if let Some(index) = self.current.iter().position(|p| p == path) {
+            self.current.swap_remove(index);
+        }
+
+        self.done_files += 1;
+        self.done_work += work;
+
+        if self.window.len() == WINDOW_SIZE {
+            self.window.pop_front();
+        }
+
+        self.window.push_back((Instant::now(), work, pixels));
+    }
+
+    // the rates are measured over the last `WINDOW_SIZE` images, or since the start if fewer images
+    // have been done
+    fn window_elapsed(&self) -> f64 {
+        let since = if self.window.len() == WINDOW_SIZE {
+            self.window.front().map(|(instant, ..)| *instant).unwrap_or(self.start)
+        } else {
+            self.start
+        };
+
+        since.elapsed().as_secs_f64()
+    }
+
+    pub fn files_per_second(&self) -> f64 {
+        let elapsed = self.window_elapsed();
+
+        if elapsed > 0f64 {
+            self.window.len() as f64 / elapsed
+        } else {
+            0f64
+        }
+    }
+
+    pub fn megapixels_per_second(&self) -> f64 {
+        let elapsed = self.window_elapsed();
+
+        if elapsed > 0f64 {
+            self.window.iter().map(|(_, _, pixels)| *pixels).sum::<u64>() as f64
+                / 1_000_000f64
+                / elapsed
+        } else {
+            0f64
+        }
+    }
+
+    // the remaining time is estimated by work instead of files, so that large images still waiting
+    // in the queue are taken into account
+    pub fn eta(&self) -> Option<Duration> {
+        let elapsed = self.window_elapsed();
+        let work = self.window.iter().map(|(_, work, _)| *work).sum::<u64>();
+
+        if elapsed <= 0f64 || work == 0 {
+            return None;
+        }
+
+        let remaining_work = self.total_work.saturating_sub(self.done_work);
+
+        Some(Duration::from_secs_f64(remaining_work as f64 * elapsed / work as f64))
+    }
+
+    pub fn status<'a>(&'a self, summary: &Summary) -> Status<'a> {
+        Status {
+            total:                 self.total_files,
+            done:                  self.done_files,
+            resized:               summary.resized,
+            skipped:               summary.skipped,
+            failed:                summary.failed,
+            permission_denied:     summary.permission_denied,
+            current:               &self.current,
+            files_per_second:      self.files_per_second(),
+            megapixels_per_second: self.megapixels_per_second(),
+            eta_seconds:           self.eta().map(|eta| eta.as_secs_f64()),
+            elapsed_seconds:       self.start.elapsed().as_secs_f64(),
+            resident_memory_bytes: resident_memory(),
+        }
+    }
+
+    pub fn event<'a>(&self, path: &'a Path, status: FileStatus) -> Event<'a> {
+        Event {
+            path,
+            status,
+            done: self.done_files,
+            total: self.total_files,
+            files_per_second: self.files_per_second(),
+            megapixels_per_second: self.megapixels_per_second(),
+            eta_seconds: self.eta().map(|eta| eta.as_secs_f64()),
+        }
+    }
+}
+
+// the resident set size of this process, which is only known on Linux
+pub fn resident_memory() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+
+    let kib = line["VmRSS:".len()..].trim().trim_end_matches("kB").trim().parse::<u64>().ok()?;
+
+    Some(kib * 1024)
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}/{}] {:.1} files/s, {:.1} MPix/s",
+            self.done_files,
+            self.total_files,
+            self.files_per_second(),
+            self.megapixels_per_second()
+        )?;
+
+        match self.eta() {
+            Some(eta) => {
+                let seconds = eta.as_secs();
+
+                write!(
+                    f,
+                    ", ETA {:02}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )
+            },
+            None => f.write_str(", ETA --:--:--"),
+        }
+    }
+}
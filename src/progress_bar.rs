@@ -0,0 +1,85 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use indicatif::{ProgressBar as Bar, ProgressDrawTarget, ProgressStyle};
+
+use crate::console::{self, Sink};
+
+const TEMPLATE: &str = "{bar:40} {pos}/{len} [{elapsed_precise}, ETA {eta_precise}] {wide_msg}";
+
+// drawn on stdout in place of the messages of the resized images, while the other messages are
+// printed above it
+#[derive(Debug)]
+pub struct ProgressBar {
+    total:   u64,
+    done:    u64,
+    current: String,
+    // cleared before a question is asked, and drawn again after it has been answered
+    bar:     Option<Bar>,
+}
+
+impl ProgressBar {
+    #[inline]
+    pub fn new(total: usize) -> ProgressBar {
+        ProgressBar {
+            total: total as u64, done: 0, current: String::new(), bar: None
+        }
+    }
+
+    fn bar(&mut self) -> &Bar {
+        let (total, done, current) = (self.total, self.done, &self.current);
+
+        self.bar.get_or_insert_with(|| {
+            Bar::with_draw_target(Some(total), ProgressDrawTarget::stdout())
+                .with_style(ProgressStyle::with_template(TEMPLATE).unwrap())
+                .with_position(done)
+                .with_message(current.clone())
+        })
+    }
+}
+
+impl Sink for ProgressBar {
+    #[inline]
+    fn stdout(&mut self, _data: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn stderr(&mut self, data: &[u8]) -> io::Result<()> {
+        self.bar().suspend(|| {
+            let mut stderr = io::stderr().lock();
+
+            stderr.write_all(data)?;
+            stderr.flush()
+        })
+    }
+
+    fn prompt(&mut self, question: &[u8]) -> io::Result<()> {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+        }
+
+        let mut stdout = io::stdout().lock();
+
+        stdout.write_all(question)?;
+        stdout.flush()
+    }
+
+    fn progress(&mut self, done: usize, current: &[PathBuf]) -> io::Result<()> {
+        self.done = done as u64;
+        self.current = match current.first() {
+            Some(path) => console::quoted(path).to_string(),
+            None => String::new(),
+        };
+
+        let (done, current) = (self.done, self.current.clone());
+
+        let bar = self.bar();
+
+        bar.set_position(done);
+        bar.set_message(current);
+
+        Ok(())
+    }
+}
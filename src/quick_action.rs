@@ -0,0 +1,201 @@
+use anyhow::anyhow;
+
+use crate::cli::ShellIntegrationArgs;
+
+#[cfg(target_os = "macos")]
+pub fn quick_action(args: &ShellIntegrationArgs) -> anyhow::Result<()> {
+    use std::{env, fs, path::PathBuf};
+
+    use anyhow::Context;
+
+    use crate::console::{self, Line};
+
+    const INFO_PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>NSServices</key>
+	<array>
+		<dict>
+			<key>NSMenuItem</key>
+			<dict>
+				<key>default</key>
+				<string>{label}</string>
+			</dict>
+			<key>NSMessage</key>
+			<string>runWorkflowAsService</string>
+			<key>NSRequiredContext</key>
+			<dict>
+				<key>NSApplicationIdentifier</key>
+				<string>com.apple.finder</string>
+			</dict>
+			<key>NSSendFileTypes</key>
+			<array>
+				<string>public.image</string>
+				<string>public.folder</string>
+			</array>
+		</dict>
+	</array>
+</dict>
+</plist>
+"#;
+
+    const DOCUMENT_WFLOW: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>AMApplicationBuild</key>
+	<string>521</string>
+	<key>AMApplicationVersion</key>
+	<string>2.10</string>
+	<key>AMDocumentVersion</key>
+	<string>2</string>
+	<key>actions</key>
+	<array>
+		<dict>
+			<key>action</key>
+			<dict>
+				<key>AMAccepts</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Optional</key>
+					<true/>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.string</string>
+					</array>
+				</dict>
+				<key>AMActionVersion</key>
+				<string>2.0.3</string>
+				<key>AMApplication</key>
+				<array>
+					<string>Automator</string>
+				</array>
+				<key>AMProvides</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.string</string>
+					</array>
+				</dict>
+				<key>ActionBundlePath</key>
+				<string>/System/Library/Automator/Run Shell Script.action</string>
+				<key>ActionName</key>
+				<string>Run Shell Script</string>
+				<key>ActionParameters</key>
+				<dict>
+					<key>COMMAND_STRING</key>
+					<string>{command}</string>
+					<key>CheckedForUserDefaultShell</key>
+					<true/>
+					<key>inputMethod</key>
+					<integer>1</integer>
+					<key>shell</key>
+					<string>/bin/sh</string>
+					<key>source</key>
+					<string></string>
+				</dict>
+				<key>BundleIdentifier</key>
+				<string>com.apple.RunShellScript</string>
+				<key>CFBundleVersion</key>
+				<string>2.0.3</string>
+				<key>Class Name</key>
+				<string>RunShellScriptAction</string>
+				<key>InputUUID</key>
+				<string>0E3B4C64-3F2A-4E4B-9B0A-6C1D2E3F4A5B</string>
+				<key>OutputUUID</key>
+				<string>1F4C5D75-4A3B-4F5C-8C1B-7D2E3F4A5B6C</string>
+				<key>UUID</key>
+				<string>2A5D6E86-5B4C-4A6D-9D2C-8E3F4A5B6C7D</string>
+			</dict>
+			<key>isViewVisible</key>
+			<integer>1</integer>
+		</dict>
+	</array>
+	<key>connectors</key>
+	<dict/>
+	<key>workflowMetaData</key>
+	<dict>
+		<key>serviceApplicationBundleID</key>
+		<string>com.apple.finder</string>
+		<key>serviceInputTypeIdentifier</key>
+		<string>com.apple.Automator.fileSystemObject</string>
+		<key>serviceOutputTypeIdentifier</key>
+		<string>com.apple.Automator.nothing</string>
+		<key>workflowTypeIdentifier</key>
+		<string>com.apple.Automator.servicesMenu</string>
+	</dict>
+</dict>
+</plist>
+"#;
+
+    let label = format!("Resize with Image Resizer ({}px)", args.side_maximum);
+
+    let home = env::var_os("HOME").ok_or_else(|| anyhow!("HOME is not set."))?;
+
+    let workflow_path =
+        PathBuf::from(home).join("Library/Services").join(format!("{label}.workflow"));
+
+    if args.uninstall {
+        fs::remove_dir_all(&workflow_path).with_context(|| anyhow!("{workflow_path:?}"))?;
+
+        Line::Stdout(
+            format!("{} has been removed.\n", console::quoted(&workflow_path)).into_bytes(),
+        )
+        .print()?;
+
+        return Ok(());
+    }
+
+    let exe = env::current_exe().with_context(|| anyhow!("current_exe"))?;
+
+    let mut command = format!(
+        "for f in \"$@\"\ndo\n\t{} \"$f\" -m {}",
+        shell_quote(&exe.to_string_lossy()),
+        args.side_maximum
+    );
+
+    for option in args.options.iter() {
+        command.push(' ');
+        command.push_str(&shell_quote(option));
+    }
+
+    command.push_str("\ndone\n");
+
+    let contents_path = workflow_path.join("Contents");
+
+    fs::create_dir_all(&contents_path).with_context(|| anyhow!("{contents_path:?}"))?;
+
+    let info_plist_path = contents_path.join("Info.plist");
+
+    fs::write(&info_plist_path, INFO_PLIST.replace("{label}", &xml_escape(&label)))
+        .with_context(|| anyhow!("{info_plist_path:?}"))?;
+
+    let document_path = contents_path.join("document.wflow");
+
+    fs::write(&document_path, DOCUMENT_WFLOW.replace("{command}", &xml_escape(&command)))
+        .with_context(|| anyhow!("{document_path:?}"))?;
+
+    Line::Stdout(format!("{} has been installed.\n", console::quoted(&workflow_path)).into_bytes())
+        .print()?;
+
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+#[cfg(target_os = "macos")]
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn quick_action(_args: &ShellIntegrationArgs) -> anyhow::Result<()> {
+    Err(anyhow!("The Quick Action is only available on macOS."))
+}
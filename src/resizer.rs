@@ -0,0 +1,60 @@
+use std::{collections::BTreeMap, ffi::OsString};
+
+use clap::{CommandFactory, FromArgMatches};
+
+use crate::{
+    cli::{self, CLIArgs},
+    processing,
+};
+
+// ImageMagick is seeded with the time otherwise
+const DEFAULT_SEED: u32 = 0;
+
+/// Resize images in memory with the options of `image-resizer`, the same way as it resizes an image
+/// file into one output.
+///
+/// The options which write several outputs for an image, such as `--tile`, `--sizes`, `--dpr`,
+/// `--quality-ladder` and `--icns`, are not applied.
+///
+/// Nothing is read from the file system or stdin, and the pseudo-random number generator of
+/// ImageMagick is seeded with `--seed`, or 0 by default, before each image is resized, so the
+/// outputs can be compared with golden images.
+#[derive(Debug)]
+pub struct Resizer {
+    args: CLIArgs,
+}
+
+impl Resizer {
+    /// Parse the options of the command line without the input path, such as `["-m", "256"]`.
+    /// Config files and `--options-json` are not loaded.
+    pub fn new<I, T>(options: I) -> anyhow::Result<Resizer>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone, {
+        let arg_os = [OsString::from("image-resizer"), OsString::from("-")]
+            .into_iter()
+            .chain(options.into_iter().map(Into::into));
+
+        let matches = CLIArgs::command().try_get_matches_from(arg_os)?;
+
+        let mut args = CLIArgs::from_arg_matches(&matches)?;
+
+        if let Some(preset) = args.preset {
+            args = cli::apply_preset(args, &matches, preset, &BTreeMap::new())?;
+        }
+
+        Ok(Resizer {
+            args,
+        })
+    }
+
+    /// Resize an image, and encode it in its own format, or in the one which the binary converts it
+    /// to, such as JPEG for camera RAW images and PNG for SVG images.
+    pub fn resize(&self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        processing::set_seed(self.args.seed.unwrap_or(DEFAULT_SEED));
+
+        let (output, _) = processing::resize_data(&self.args, input)?;
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,482 @@
+use std::{borrow::Cow, cmp::Ordering, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::CLIArgs;
+
+// a rule such as `if exif.camera == "Scanner" and width > 4000 then max=3000, quality=90`, which
+// is kept as it was written so that it can be saved to an options document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rule {
+    source:     String,
+    conditions: Vec<Condition>,
+    actions:    Vec<Action>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    attribute: String,
+    operator:  Operator,
+    value:     String,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Contains,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Action {
+    SideMaximum(u16),
+    MaxWidth(u16),
+    MaxHeight(u16),
+    Quality(u8),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Symbol(String),
+}
+
+impl Rule {
+    pub fn parse(source: &str) -> Result<Rule, String> {
+        let tokens = tokenize(source)?;
+
+        let mut tokens = tokens.into_iter().peekable();
+
+        if tokens.next() != Some(Token::Word("if".into())) {
+            return Err("A rule must start with `if`".into());
+        }
+
+        let mut conditions = Vec::new();
+
+        loop {
+            let attribute = match tokens.next() {
+                Some(Token::Word(word)) if is_attribute(&word) => word,
+                Some(Token::Word(word)) => {
+                    return Err(format!(
+                        "{word:?} is not an attribute. Use format, width, height or exif.<TAG>"
+                    ))
+                },
+                _ => return Err("An attribute is expected after `if` and `and`".into()),
+            };
+
+            let operator = match tokens.next() {
+                Some(Token::Symbol(symbol)) => match symbol.as_str() {
+                    "==" => Operator::Equal,
+                    "!=" => Operator::NotEqual,
+                    "<" => Operator::Less,
+                    "<=" => Operator::LessOrEqual,
+                    ">" => Operator::Greater,
+                    ">=" => Operator::GreaterOrEqual,
+                    _ => return Err(format!("{symbol:?} is not an operator")),
+                },
+                Some(Token::Word(word)) if word == "contains" => Operator::Contains,
+                _ => {
+                    return Err(format!(
+                        "An operator such as ==, !=, <, <=, >, >= or contains is expected after \
+                         {attribute}"
+                    ))
+                },
+            };
+
+            let value = match tokens.next() {
+                Some(Token::Word(value)) | Some(Token::Text(value)) => value,
+                _ => return Err(format!("A value is expected after {attribute}")),
+            };
+
+            conditions.push(Condition {
+                attribute,
+                operator,
+                value,
+            });
+
+            match tokens.next() {
+                Some(Token::Word(word)) if word == "and" => continue,
+                Some(Token::Word(word)) if word == "then" => break,
+                _ => return Err("`and` or `then` is expected after a condition".into()),
+            }
+        }
+
+        let mut actions = Vec::new();
+
+        loop {
+            let key = match tokens.next() {
+                Some(Token::Word(key)) => key,
+                _ => return Err("An option such as max=3000 is expected after `then`".into()),
+            };
+
+            if tokens.next() != Some(Token::Symbol("=".into())) {
+                return Err(format!("`=` is expected after {key}"));
+            }
+
+            let value = match tokens.next() {
+                Some(Token::Word(value)) | Some(Token::Text(value)) => value,
+                _ => return Err(format!("A value is expected after {key}=")),
+            };
+
+            let side = |value: &str| match value.parse::<u16>() {
+                Ok(side) if side > 0 => Ok(side),
+                _ => Err(format!("{key}={value} is not a side such as 3000")),
+            };
+
+            actions.push(match key.as_str() {
+                "max" | "side-maximum" => Action::SideMaximum(side(&value)?),
+                "width" | "max-width" => Action::MaxWidth(side(&value)?),
+                "height" | "max-height" => Action::MaxHeight(side(&value)?),
+                "quality" => match value.parse::<u8>() {
+                    Ok(quality) if quality <= 100 => Action::Quality(quality),
+                    _ => return Err(format!("quality={value} is not a quality from 0 to 100")),
+                },
+                _ => {
+                    return Err(format!(
+                        "{key:?} is not an option of a rule. Use max, width, height or quality"
+                    ))
+                },
+            });
+
+            match tokens.next() {
+                Some(Token::Symbol(symbol)) if symbol == "," => continue,
+                None => break,
+                _ => return Err("`,` or the end of the rule is expected after an option".into()),
+            }
+        }
+
+        Ok(Rule {
+            source: source.trim().to_string(),
+            conditions,
+            actions,
+        })
+    }
+
+    // `attribute` gives the value of an attribute of the image, such as "JPEG" for format, or
+    // None if it does not have one, in which case the condition does not hold
+    fn matches<F: Fn(&str) -> Option<String>>(&self, attribute: &F) -> bool {
+        self.conditions.iter().all(|condition| match attribute(&condition.attribute) {
+            Some(value) => condition.operator.holds(value.trim(), &condition.value),
+            None => false,
+        })
+    }
+}
+
+impl Operator {
+    // numbers are compared as numbers, and other values as text
+    fn holds(self, left: &str, right: &str) -> bool {
+        let ordering = match (left.parse::<f64>(), right.parse::<f64>()) {
+            (Ok(left), Ok(right)) => left.partial_cmp(&right),
+            _ => Some(left.cmp(right)),
+        };
+
+        match self {
+            Operator::Equal => ordering == Some(Ordering::Equal),
+            Operator::NotEqual => ordering != Some(Ordering::Equal),
+            Operator::Less => ordering == Some(Ordering::Less),
+            Operator::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Operator::Greater => ordering == Some(Ordering::Greater),
+            Operator::GreaterOrEqual => {
+                matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+            },
+            Operator::Contains => left.contains(right),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+
+    #[inline]
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Rule::parse(&source)
+    }
+}
+
+impl From<Rule> for String {
+    #[inline]
+    fn from(rule: Rule) -> Self {
+        rule.source
+    }
+}
+
+#[inline]
+fn is_attribute(word: &str) -> bool {
+    matches!(word, "format" | "width" | "height")
+        || word.strip_prefix("exif.").is_some_and(|tag| !tag.is_empty())
+}
+
+// the EXIF property of ImageMagick for an attribute such as exif.camera, where a few friendly
+// names stand for the tags
+pub fn exif_property(attribute: &str) -> Option<String> {
+    let tag = attribute.strip_prefix("exif.")?;
+
+    let tag = match tag.to_ascii_lowercase().as_str() {
+        "camera" | "model" => "Model",
+        "make" => "Make",
+        "software" => "Software",
+        "lens" => "LensModel",
+        _ => tag,
+    };
+
+    Some(format!("exif:{tag}"))
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '"' | '\'' => {
+                let mut text = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(t) => text.push(t),
+                        None => return Err(format!("The text {text:?} is not closed with {c}")),
+                    }
+                }
+
+                tokens.push(Token::Text(text));
+            },
+            '=' | '!' | '<' | '>' | ',' => {
+                let mut symbol = c.to_string();
+
+                if c != ',' && chars.peek() == Some(&'=') {
+                    symbol.push(chars.next().unwrap());
+                }
+
+                tokens.push(Token::Symbol(symbol));
+            },
+            _ => {
+                let mut word = c.to_string();
+
+                while let Some(&w) = chars.peek() {
+                    if w.is_whitespace() || matches!(w, '"' | '\'' | '=' | '!' | '<' | '>' | ',') {
+                        break;
+                    }
+
+                    word.push(w);
+                    chars.next();
+                }
+
+                tokens.push(Token::Word(word));
+            },
+        }
+    }
+
+    Ok(tokens)
+}
+
+// the options for an image after the rules which hold for it, in order, so that a later rule
+// overrides an earlier one
+pub fn apply<'a, F: Fn(&str) -> Option<String>>(
+    args: &'a CLIArgs,
+    attribute: F,
+) -> Cow<'a, CLIArgs> {
+    let mut matched = args.rule.iter().filter(|rule| rule.matches(&attribute)).peekable();
+
+    if matched.peek().is_none() {
+        return Cow::Borrowed(args);
+    }
+
+    let mut ruled = args.clone();
+
+    for rule in matched {
+        for action in rule.actions.iter() {
+            match *action {
+                Action::SideMaximum(side) => {
+                    ruled.side_maximum = Some(side);
+                    ruled.max_width = None;
+                    ruled.max_height = None;
+                },
+                Action::MaxWidth(side) => ruled.max_width = Some(side),
+                Action::MaxHeight(side) => ruled.max_height = Some(side),
+                Action::Quality(quality) => ruled.quality = quality,
+            }
+        }
+    }
+
+    Cow::Owned(ruled)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn image<'a>(attributes: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |attribute| {
+            attributes
+                .iter()
+                .find(|(name, _)| *name == attribute)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    fn holds(source: &str, attributes: &[(&str, &str)]) -> bool {
+        Rule::parse(source).unwrap().matches(&image(attributes))
+    }
+
+    #[test]
+    fn parse() {
+        let rule = Rule::parse(
+            " if exif.camera == \"EOS R5\" and width >= 4000 then max=3000, quality=90 ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            "if exif.camera == \"EOS R5\" and width >= 4000 then max=3000, quality=90",
+            rule.to_string()
+        );
+
+        assert_eq!(
+            vec![
+                Condition {
+                    attribute: "exif.camera".into(),
+                    operator:  Operator::Equal,
+                    value:     "EOS R5".into(),
+                },
+                Condition {
+                    attribute: "width".into(),
+                    operator:  Operator::GreaterOrEqual,
+                    value:     "4000".into(),
+                },
+            ],
+            rule.conditions
+        );
+
+        assert_eq!(vec![Action::SideMaximum(3000), Action::Quality(90)], rule.actions);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (source, error) in [
+            ("width > 4000 then max=3000", "A rule must start with `if`"),
+            (
+                "if size > 4000 then max=3000",
+                "\"size\" is not an attribute. Use format, width, height or exif.<TAG>",
+            ),
+            (
+                "if exif. == 1 then max=3000",
+                "\"exif.\" is not an attribute. Use format, width, height or exif.<TAG>",
+            ),
+            ("if width ! 4000 then max=3000", "\"!\" is not an operator"),
+            (
+                "if width then max=3000",
+                "An operator such as ==, !=, <, <=, >, >= or contains is expected after width",
+            ),
+            ("if width >", "A value is expected after width"),
+            ("if width > 4000 max=3000", "`and` or `then` is expected after a condition"),
+            ("if width > 4000 then", "An option such as max=3000 is expected after `then`"),
+            ("if width > 4000 then max 3000", "`=` is expected after max"),
+            ("if width > 4000 then max=0", "max=0 is not a side such as 3000"),
+            ("if width > 4000 then quality=101", "quality=101 is not a quality from 0 to 100"),
+            (
+                "if width > 4000 then dpi=72",
+                "\"dpi\" is not an option of a rule. Use max, width, height or quality",
+            ),
+            (
+                "if width > 4000 then max=3000 quality=90",
+                "`,` or the end of the rule is expected after an option",
+            ),
+            (
+                "if format == \"JPEG then max=3000",
+                "The text \"JPEG then max=3000\" is not closed with \"",
+            ),
+        ] {
+            assert_eq!(Err(String::from(error)), Rule::parse(source), "{source}");
+        }
+    }
+
+    #[test]
+    fn numbers_are_compared_as_numbers() {
+        let wide = [("width", "10000")];
+
+        assert!(holds("if width > 9000 then max=3000", &wide));
+        assert!(holds("if width >= 10000.0 then max=3000", &wide));
+        assert!(holds("if width == 1e4 then max=3000", &wide));
+        assert!(!holds("if width < 9000 then max=3000", &wide));
+    }
+
+    #[test]
+    fn texts_are_compared_as_texts() {
+        let jpeg = [("format", "JPEG"), ("exif.camera", "  Canon EOS R5 ")];
+
+        assert!(holds("if format == JPEG then max=3000", &jpeg));
+        assert!(holds("if format != PNG then max=3000", &jpeg));
+        assert!(holds("if format < PNG then max=3000", &jpeg));
+        assert!(!holds("if format == jpeg then max=3000", &jpeg));
+
+        // the values of the image are trimmed, and the quoted ones are kept as they are
+        assert!(holds("if exif.camera == 'Canon EOS R5' then max=3000", &jpeg));
+        assert!(holds("if exif.camera contains \"EOS\" then max=3000", &jpeg));
+        assert!(!holds("if exif.camera contains \" EOS  \" then max=3000", &jpeg));
+
+        // a number and a text are compared as texts
+        assert!(holds("if exif.camera > 1000 then max=3000", &jpeg));
+    }
+
+    #[test]
+    fn missing_attributes_do_not_hold() {
+        assert!(!holds("if exif.camera != Scanner then max=3000", &[("format", "JPEG")]));
+        assert!(!holds("if format == JPEG and width > 100 then max=3000", &[("format", "JPEG")]));
+    }
+
+    #[test]
+    fn apply_in_order() {
+        let args = CLIArgs::try_parse_from([
+            "image-resizer",
+            "-",
+            "--max-width",
+            "1920",
+            "--rule",
+            "if format == JPEG then width=2000, quality=80",
+            "--rule",
+            "if width > 4000 then max=3000",
+            "--rule",
+            "if format == PNG then quality=50",
+            "--rule",
+            "if width > 5000 then quality=70",
+        ])
+        .unwrap();
+
+        let large_jpeg = [("format", "JPEG"), ("width", "6000")];
+
+        let ruled = apply(&args, image(&large_jpeg));
+
+        // max resets the width of an earlier rule, and the later quality overrides the earlier one
+        assert_eq!(Some(3000), ruled.side_maximum);
+        assert_eq!(None, ruled.max_width);
+        assert_eq!(70, ruled.quality);
+
+        let small_jpeg = [("format", "JPEG"), ("width", "1000")];
+
+        let ruled = apply(&args, image(&small_jpeg));
+
+        assert_eq!(Some(2000), ruled.max_width);
+        assert_eq!(80, ruled.quality);
+
+        let gif = [("format", "GIF"), ("width", "1000")];
+
+        assert!(matches!(apply(&args, image(&gif)), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+
+#[cfg(all(feature = "magick", feature = "image-backend"))]
+use crate::cli::Backend;
+use crate::{
+    cli::{CLIArgs, ReportFormat},
+    console, csv, processing,
+    summary::{self, ImageReport, Outcome, SkipReason, Summary},
+};
+
+// what has happened to an image, which is kept for --report
+#[derive(Debug, Serialize)]
+pub struct FileRecord {
+    input_path:      PathBuf,
+    status:          FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skip_reason:     Option<SkipReason>,
+    width:           Option<u32>,
+    height:          Option<u32>,
+    bytes:           Option<u64>,
+    outputs:         Vec<OutputRecord>,
+    elapsed_seconds: f64,
+    error:           Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OutputRecord {
+    path:   PathBuf,
+    width:  Option<u32>,
+    height: Option<u32>,
+    bytes:  Option<u64>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Resized,
+    Skipped,
+    PermissionDenied,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    summary: &'a Summary,
+    files:   &'a [FileRecord],
+}
+
+impl FileStatus {
+    pub fn of(result: Result<&Outcome, &anyhow::Error>) -> FileStatus {
+        match result {
+            Ok(Outcome::Resized {
+                ..
+            }) => FileStatus::Resized,
+            Ok(Outcome::Skipped(_)) => FileStatus::Skipped,
+            Ok(Outcome::PermissionDenied) => FileStatus::PermissionDenied,
+            Err(error) if summary::is_permission_denied(error) => FileStatus::PermissionDenied,
+            Err(_) => FileStatus::Failed,
+        }
+    }
+}
+
+impl FileRecord {
+    // the outputs are read back from the disk, since their sizes are only known once written
+    pub fn new(
+        args: &CLIArgs,
+        input_path: &Path,
+        result: Result<&Outcome, &anyhow::Error>,
+        report: &ImageReport,
+    ) -> FileRecord {
+        let status = FileStatus::of(result);
+
+        let (skip_reason, size, error) = match result {
+            Ok(Outcome::Resized {
+                width,
+                height,
+            }) => (None, Some((*width, *height)), None),
+            Ok(Outcome::Skipped(reason)) => (Some(*reason), None, None),
+            Ok(Outcome::PermissionDenied) => (None, None, None),
+            Err(error) => (None, None, Some(format!("{error:#}"))),
+        };
+
+        let outputs = report
+            .outputs
+            .iter()
+            .map(|(path, _)| {
+                let size = dimensions(args, path);
+
+                OutputRecord {
+                    path:   path.clone(),
+                    width:  size.map(|(width, _)| width),
+                    height: size.map(|(_, height)| height),
+                    bytes:  fs::metadata(path).map(|metadata| metadata.len()).ok(),
+                }
+            })
+            .collect();
+
+        FileRecord {
+            input_path: input_path.to_path_buf(),
+            status,
+            skip_reason,
+            width: size.map(|(width, _)| width),
+            height: size.map(|(_, height)| height),
+            bytes: fs::metadata(input_path).map(|metadata| metadata.len()).ok(),
+            outputs,
+            elapsed_seconds: report.elapsed.as_secs_f64(),
+            error,
+        }
+    }
+}
+
+// the size of an output, which is pinged instead of decoded
+fn dimensions(args: &CLIArgs, path: &Path) -> Option<(u32, u32)> {
+    #[cfg(not(feature = "magick"))]
+    {
+        let _ = args;
+
+        image::image_dimensions(path).ok()
+    }
+
+    #[cfg(feature = "magick")]
+    {
+        #[cfg(feature = "image-backend")]
+        if args.backend == Backend::Image {
+            return image::image_dimensions(path).ok();
+        }
+
+        #[cfg(not(feature = "image-backend"))]
+        let _ = args;
+
+        let mw = processing::ping(&image_convert::ImageResource::from_path(path)).ok()?;
+
+        Some((mw.get_image_width() as u32, mw.get_image_height() as u32))
+    }
+}
+
+impl FileStatus {
+    #[inline]
+    fn as_str(self) -> &'static str {
+        match self {
+            FileStatus::Resized => "resized",
+            FileStatus::Skipped => "skipped",
+            FileStatus::PermissionDenied => "permission_denied",
+            FileStatus::Failed => "failed",
+        }
+    }
+}
+
+#[inline]
+fn size_text(width: Option<u32>, height: Option<u32>) -> String {
+    match (width, height) {
+        (Some(width), Some(height)) => format!("{width}x{height}"),
+        _ => String::from("?"),
+    }
+}
+
+#[inline]
+fn bytes_text(bytes: Option<u64>) -> String {
+    match bytes {
+        Some(bytes) => format!("{bytes} bytes"),
+        None => String::from("? bytes"),
+    }
+}
+
+// written once the run has finished, even if it has failed, along with the summary
+pub fn write(args: &CLIArgs, summary: &Summary) -> anyhow::Result<()> {
+    let (format, report_file) = match (args.report, args.report_file.as_deref()) {
+        (Some(format), Some(report_file)) => (format, report_file),
+        _ => return Ok(()),
+    };
+
+    let data = match format {
+        ReportFormat::Json => {
+            let mut data = serde_json::to_vec_pretty(&Report {
+                summary,
+                files: &summary.files,
+            })?;
+
+            data.push(b'\n');
+
+            data
+        },
+        ReportFormat::Text => {
+            let mut text = String::new();
+
+            for file in summary.files.iter() {
+                let input_path = console::quoted(&file.input_path);
+
+                match file.status {
+                    FileStatus::Resized => {
+                        for output in file.outputs.iter() {
+                            writeln!(
+                                text,
+                                "{input_path} -> {}: {} -> {}, {} -> {}, {:.3} s",
+                                console::quoted(&output.path),
+                                size_text(file.width, file.height),
+                                size_text(output.width, output.height),
+                                bytes_text(file.bytes),
+                                bytes_text(output.bytes),
+                                file.elapsed_seconds
+                            )?;
+                        }
+                    },
+                    FileStatus::Skipped => match file.skip_reason {
+                        Some(reason) => writeln!(text, "{input_path}: skipped because {reason}")?,
+                        None => writeln!(text, "{input_path}: skipped")?,
+                    },
+                    FileStatus::PermissionDenied => {
+                        writeln!(text, "{input_path}: the output is not writable")?
+                    },
+                    FileStatus::Failed => writeln!(
+                        text,
+                        "{input_path}: {}",
+                        file.error.as_deref().unwrap_or("failed")
+                    )?,
+                }
+            }
+
+            text.into_bytes()
+        },
+        ReportFormat::Csv => {
+            let mut text = String::new();
+
+            csv::write_row(&mut text, &[
+                "input_path",
+                "status",
+                "skip_reason",
+                "width",
+                "height",
+                "bytes",
+                "output_path",
+                "output_width",
+                "output_height",
+                "output_bytes",
+                "elapsed_seconds",
+                "error",
+            ])?;
+
+            for file in summary.files.iter() {
+                let input_path = file.input_path.to_string_lossy();
+                let skip_reason = csv::optional(file.skip_reason);
+                let width = csv::optional(file.width);
+                let height = csv::optional(file.height);
+                let bytes = csv::optional(file.bytes);
+                let elapsed_seconds = format!("{:.3}", file.elapsed_seconds);
+                let error = file.error.as_deref().unwrap_or("");
+
+                // a row per output, or a row with the empty output columns if there is none
+                let outputs = file.outputs.iter().map(|output| {
+                    [
+                        output.path.to_string_lossy().into_owned(),
+                        csv::optional(output.width),
+                        csv::optional(output.height),
+                        csv::optional(output.bytes),
+                    ]
+                });
+
+                let outputs: Vec<[String; 4]> = if file.outputs.is_empty() {
+                    vec![Default::default()]
+                } else {
+                    outputs.collect()
+                };
+
+                for [output_path, output_width, output_height, output_bytes] in outputs.iter() {
+                    csv::write_row(&mut text, &[
+                        &input_path,
+                        file.status.as_str(),
+                        &skip_reason,
+                        &width,
+                        &height,
+                        &bytes,
+                        output_path,
+                        output_width,
+                        output_height,
+                        output_bytes,
+                        &elapsed_seconds,
+                        error,
+                    ])?;
+                }
+            }
+
+            text.into_bytes()
+        },
+    };
+
+    processing::write_atomically(report_file, &data, None)
+        .with_context(|| anyhow!("{report_file:?}"))?;
+
+    Ok(())
+}
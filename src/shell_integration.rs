@@ -0,0 +1,131 @@
+use anyhow::anyhow;
+
+use crate::cli::ShellIntegrationArgs;
+
+#[cfg(windows)]
+pub fn shell_integration(args: &ShellIntegrationArgs) -> anyhow::Result<()> {
+    use std::{env, process::Command};
+
+    use anyhow::Context;
+
+    use crate::console::Line;
+
+    // files of any image type, and directories
+    const CLASSES: [&str; 2] = ["SystemFileAssociations\\image", "Directory"];
+
+    let key_name = format!("ImageResizer{}", args.side_maximum);
+
+    for class in CLASSES {
+        let key = format!("HKCU\\Software\\Classes\\{class}\\shell\\{key_name}");
+
+        let mut commands = Vec::new();
+
+        if args.uninstall {
+            commands.push(vec!["delete".to_string(), key.clone(), "/f".to_string()]);
+        } else {
+            let exe = env::current_exe().with_context(|| anyhow!("current_exe"))?;
+
+            let mut command_line = format!("\"{}\" \"%1\" -m {}", exe.display(), args.side_maximum);
+
+            for option in args.options.iter() {
+                command_line.push(' ');
+                command_line.push_str(&quote_argument(option));
+            }
+
+            let label = format!("Resize with Image Resizer ({}px)", args.side_maximum);
+
+            commands.push(vec![
+                "add".to_string(),
+                key.clone(),
+                "/ve".to_string(),
+                "/d".to_string(),
+                label,
+                "/f".to_string(),
+            ]);
+            commands.push(vec![
+                "add".to_string(),
+                format!("{key}\\command"),
+                "/ve".to_string(),
+                "/d".to_string(),
+                command_line,
+                "/f".to_string(),
+            ]);
+        }
+
+        for command in commands {
+            let status =
+                Command::new("reg").args(&command).status().with_context(|| anyhow!("reg"))?;
+
+            if !status.success() {
+                return Err(anyhow!("reg {} {key:?} exited with {status}.", command[0]));
+            }
+        }
+    }
+
+    let message = if args.uninstall {
+        format!("The context-menu entries of {key_name} have been removed.\n")
+    } else {
+        format!("The context-menu entries of {key_name} have been installed.\n")
+    };
+
+    Line::Stdout(message.into_bytes()).print()?;
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn shell_integration(_args: &ShellIntegrationArgs) -> anyhow::Result<()> {
+    Err(anyhow!("The shell integration is only available on Windows."))
+}
+
+/// Quote an argument so that the Windows C runtime parses it back as a single argument: a quote is
+/// escaped as `\"`, and the backslashes before it or before the closing quote are doubled.
+#[cfg(any(windows, test))]
+fn quote_argument(argument: &str) -> String {
+    if !argument.is_empty() && !argument.contains([' ', '\t', '\n', '"']) {
+        return argument.to_string();
+    }
+
+    let mut quoted = String::from('"');
+
+    let mut backslashes = 0;
+
+    for c in argument.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+
+                backslashes = 0;
+            },
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+
+                backslashes = 0;
+            },
+        }
+    }
+
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_arguments() {
+        assert_eq!("--quality=90", quote_argument("--quality=90"));
+        assert_eq!("C:\\out", quote_argument("C:\\out"));
+        assert_eq!("\"\"", quote_argument(""));
+        assert_eq!("\"C:\\My Pictures\"", quote_argument("C:\\My Pictures"));
+        assert_eq!("\"C:\\My Pictures\\\\\"", quote_argument("C:\\My Pictures\\"));
+        assert_eq!("\"say \\\"hi\\\"\"", quote_argument("say \"hi\""));
+        assert_eq!("\"a\\\\\\\"b\"", quote_argument("a\\\"b"));
+    }
+}
@@ -0,0 +1,50 @@
+use std::{fs, path::Path};
+
+use anyhow::anyhow;
+use glob::{MatchOptions, Pattern};
+
+pub const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive:              true,
+    // `*` stays in a directory, while `**` crosses directories
+    require_literal_separator:   true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug)]
+pub struct SkipList {
+    patterns: Vec<Pattern>,
+}
+
+impl SkipList {
+    // one path or glob per line, relative to the input directory unless it is absolute, and lines
+    // starting with `#` are comments
+    pub fn read<P: AsRef<Path>>(path: P) -> anyhow::Result<SkipList> {
+        let content = fs::read_to_string(path)?;
+
+        let mut patterns = Vec::new();
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            patterns.push(Pattern::new(line).map_err(|error| anyhow!("{line:?}: {error}"))?);
+        }
+
+        Ok(SkipList {
+            patterns,
+        })
+    }
+
+    pub fn matches(&self, input_path: &Path, image_path: &Path) -> bool {
+        self.patterns.iter().any(|pattern| glob_matches(pattern, input_path, image_path))
+    }
+}
+
+// a pattern matches the path relative to the input directory or the absolute one
+pub fn glob_matches(pattern: &Pattern, input_path: &Path, image_path: &Path) -> bool {
+    let relative_path = image_path.strip_prefix(input_path).unwrap_or(image_path);
+
+    pattern.matches_path_with(relative_path, MATCH_OPTIONS)
+        || pattern.matches_path_with(image_path, MATCH_OPTIONS)
+}
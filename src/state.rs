@@ -0,0 +1,152 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::CLIArgs, processing};
+
+const STATE_VERSION: u32 = 1;
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+// kept in memory during a run, and written into the state file at most once per interval
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    version:    u32,
+    // canonical, so that a run can be resumed from another working directory
+    input_path: PathBuf,
+    // relative to the input directory
+    completed:  BTreeSet<PathBuf>,
+    #[serde(skip)]
+    changed:    bool,
+    #[serde(skip)]
+    saved:      Option<Instant>,
+}
+
+fn relative_path<'a>(args: &CLIArgs, image_path: &'a Path) -> &'a Path {
+    image_path.strip_prefix(&args.input_path).unwrap_or(image_path)
+}
+
+// returns how many images have been completed by the run which is resumed
+pub fn open(args: &CLIArgs) -> anyhow::Result<usize> {
+    let state_path = match args.state.as_deref() {
+        Some(state_path) => state_path,
+        None => return Ok(0),
+    };
+
+    let input_path =
+        args.input_path.canonicalize().with_context(|| anyhow!("{:?}", args.input_path))?;
+
+    // a run which has never been started, or has finished, is resumed from scratch
+    let state = match fs::read(state_path) {
+        Ok(data) if args.resume => {
+            let state: State =
+                serde_json::from_slice(&data).with_context(|| anyhow!("{state_path:?}"))?;
+
+            if state.version != STATE_VERSION {
+                return Err(anyhow!(
+                    "{state_path:?} has been written by another version, and cannot be resumed."
+                ));
+            }
+
+            if state.input_path != input_path {
+                return Err(anyhow!(
+                    "{state_path:?} has been written for {:?}, not {:?}.",
+                    state.input_path,
+                    args.input_path
+                ));
+            }
+
+            state
+        },
+        Ok(_) => State::default(),
+        Err(error) if error.kind() == ErrorKind::NotFound => State::default(),
+        Err(error) => return Err(error).with_context(|| anyhow!("{state_path:?}")),
+    };
+
+    let completed = state.completed.len();
+
+    *STATE.lock().unwrap() = Some(State {
+        version: STATE_VERSION,
+        input_path,
+        ..state
+    });
+
+    Ok(completed)
+}
+
+pub fn is_completed(args: &CLIArgs, image_path: &Path) -> bool {
+    match STATE.lock().unwrap().as_ref() {
+        Some(state) => state.completed.contains(relative_path(args, image_path)),
+        None => false,
+    }
+}
+
+// an image is completed once it has been resized or skipped, so a failed one is tried again
+pub fn complete(args: &CLIArgs, image_path: &Path) -> anyhow::Result<()> {
+    let mut state = STATE.lock().unwrap();
+
+    if let Some(state) = state.as_mut() {
+        state.completed.insert(relative_path(args, image_path).to_path_buf());
+        state.changed = true;
+
+        let due = state.saved.map(|saved| saved.elapsed() >= STATE_SAVE_INTERVAL).unwrap_or(true);
+
+        if due {
+            write_state(args, state)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn save(args: &CLIArgs) -> anyhow::Result<()> {
+    let mut state = STATE.lock().unwrap();
+
+    if let Some(state) = state.as_mut() {
+        if state.changed {
+            write_state(args, state)?;
+        }
+    }
+
+    Ok(())
+}
+
+// the state file is removed once all images have been completed
+pub fn finish(args: &CLIArgs) -> anyhow::Result<()> {
+    if let Some(state_path) = args.state.as_deref() {
+        *STATE.lock().unwrap() = None;
+
+        match fs::remove_file(state_path) {
+            Ok(_) => (),
+            Err(error) if error.kind() == ErrorKind::NotFound => (),
+            Err(error) => return Err(error).with_context(|| anyhow!("{state_path:?}")),
+        }
+    }
+
+    Ok(())
+}
+
+fn write_state(args: &CLIArgs, state: &mut State) -> anyhow::Result<()> {
+    let state_path = args.state.as_deref().unwrap();
+
+    let mut data = serde_json::to_vec_pretty(state)?;
+
+    data.push(b'\n');
+
+    processing::write_atomically(state_path, &data, None)
+        .with_context(|| anyhow!("{state_path:?}"))?;
+
+    state.changed = false;
+    state.saved = Some(Instant::now());
+
+    Ok(())
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+
+use crate::{console::Line, ordered_output::OrderedOutput, run_report::FileRecord};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Completed,
+    Failed,
+}
+
+// why an image has not been resized, without having failed
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    // the format identified by ImageMagick, which may not match the extension, is not supported
+    UnsupportedFormat,
+    GifNotAllowed,
+    // the output exists and overwriting it has been declined
+    OverwriteDeclined,
+    // the long side is smaller than --side-minimum, with --below-minimum skip
+    BelowMinimum,
+    // the identified format is not listed by --formats, or is listed by --skip-formats
+    FormatExcluded,
+    EmptyFile,
+    // the output has been modified after the input, with --newer-only
+    UpToDate,
+    // the content and the options are the same as the ones in --cache
+    Cached,
+    // the file only contains NUL bytes, or is a Git LFS pointer or an HTML page
+    Placeholder,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SkipReason::UnsupportedFormat => "the format is not supported",
+            SkipReason::GifNotAllowed => "GIF processing is disabled",
+            SkipReason::OverwriteDeclined => "the output exists and has not been overwritten",
+            SkipReason::BelowMinimum => "their long sides are shorter than --side-minimum",
+            SkipReason::FormatExcluded => {
+                "their formats are excluded by --formats or --skip-formats"
+            },
+            SkipReason::EmptyFile => "their files are empty",
+            SkipReason::UpToDate => "their outputs are newer than them",
+            SkipReason::Cached => {
+                "their contents and the options have not changed since they were resized"
+            },
+            SkipReason::Placeholder => "their files are placeholders rather than images",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Outcome {
+    Resized { width: u32, height: u32 },
+    Skipped(SkipReason),
+    // the output, or the directory which it would be written in, is not writable
+    PermissionDenied,
+}
+
+impl Outcome {
+    #[inline]
+    pub fn pixels(&self) -> u64 {
+        match self {
+            Outcome::Resized {
+                width,
+                height,
+            } => u64::from(*width) * u64::from(*height),
+            Outcome::Skipped(_) | Outcome::PermissionDenied => 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Timings {
+    pub decode: Duration,
+    pub resize: Duration,
+    pub encode: Duration,
+}
+
+impl Timings {
+    #[inline]
+    pub fn measure<T, F: FnOnce() -> T>(duration: &mut Duration, f: F) -> T {
+        let start = Instant::now();
+
+        let result = f();
+
+        *duration += start.elapsed();
+
+        result
+    }
+
+    #[inline]
+    pub fn add(&mut self, timings: &Timings) {
+        self.decode += timings.decode;
+        self.resize += timings.resize;
+        self.encode += timings.encode;
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "decode {:.1} ms, resize {:.1} ms, encode {:.1} ms",
+            self.decode.as_secs_f64() * 1000f64,
+            self.resize.as_secs_f64() * 1000f64,
+            self.encode.as_secs_f64() * 1000f64
+        )
+    }
+}
+
+impl Serialize for Timings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut timings = serializer.serialize_struct("Timings", 3)?;
+
+        timings.serialize_field("decode_seconds", &self.decode.as_secs_f64())?;
+        timings.serialize_field("resize_seconds", &self.resize.as_secs_f64())?;
+        timings.serialize_field("encode_seconds", &self.encode.as_secs_f64())?;
+
+        timings.end()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImageReport {
+    pub timings:          Timings,
+    // how long resizing the image has taken as a whole
+    pub elapsed:          Duration,
+    pub stripped_profile: Option<String>,
+    // the paths of the outputs along with the hashes of their data
+    pub outputs:          Vec<(PathBuf, u64)>,
+    // whether the lines are kept for --ordered-output instead of being printed at once
+    pub buffered:         bool,
+    pub lines:            Vec<Line>,
+}
+
+impl ImageReport {
+    #[inline]
+    pub fn new(buffered: bool) -> ImageReport {
+        ImageReport {
+            buffered,
+            ..ImageReport::default()
+        }
+    }
+
+    #[inline]
+    pub fn print(&mut self, line: Line) -> io::Result<()> {
+        if self.buffered {
+            self.lines.push(line);
+
+            Ok(())
+        } else {
+            line.print()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub input_path:             PathBuf,
+    pub status:                 Status,
+    pub total:                  usize,
+    pub resized:                usize,
+    pub skipped:                usize,
+    pub skip_reasons:           BTreeMap<SkipReason, usize>,
+    pub failed:                 usize,
+    pub elapsed_seconds:        f64,
+    pub error:                  Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings:                Option<Timings>,
+    pub stripped_profiles:      usize,
+    pub permission_denied:      usize,
+    #[serde(skip)]
+    pub failures:               Vec<(PathBuf, String)>,
+    #[serde(skip)]
+    pub denied_files:           Vec<PathBuf>,
+    #[serde(skip)]
+    pub file_timings:           Vec<(PathBuf, Timings)>,
+    #[serde(skip)]
+    pub stripped_profile_files: Vec<(PathBuf, String)>,
+    #[serde(skip)]
+    pub ordered_output:         Option<OrderedOutput>,
+    #[serde(skip)]
+    pub files:                  Vec<FileRecord>,
+}
+
+impl Summary {
+    pub fn new<P: AsRef<Path>>(input_path: P) -> Summary {
+        Summary {
+            input_path:             input_path.as_ref().to_path_buf(),
+            status:                 Status::Completed,
+            total:                  0,
+            resized:                0,
+            skipped:                0,
+            skip_reasons:           BTreeMap::new(),
+            failed:                 0,
+            elapsed_seconds:        0f64,
+            error:                  None,
+            timings:                None,
+            stripped_profiles:      0,
+            permission_denied:      0,
+            failures:               Vec::new(),
+            denied_files:           Vec::new(),
+            file_timings:           Vec::new(),
+            stripped_profile_files: Vec::new(),
+            ordered_output:         None,
+            files:                  Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, path: PathBuf, result: anyhow::Result<Outcome>) {
+        match result {
+            Ok(Outcome::Resized {
+                ..
+            }) => self.resized += 1,
+            Ok(Outcome::Skipped(reason)) => {
+                self.skipped += 1;
+                *self.skip_reasons.entry(reason).or_insert(0) += 1;
+            },
+            Ok(Outcome::PermissionDenied) => {
+                self.permission_denied += 1;
+                self.denied_files.push(path);
+            },
+            Err(error) if is_permission_denied(&error) => {
+                self.permission_denied += 1;
+                self.denied_files.push(path);
+            },
+            Err(error) => {
+                self.failed += 1;
+                self.failures.push((path, format!("{error:#}")));
+            },
+        }
+    }
+
+    pub fn record_timings(&mut self, path: PathBuf, timings: Timings) {
+        self.timings.get_or_insert_with(Timings::default).add(&timings);
+        self.file_timings.push((path, timings));
+    }
+
+    pub fn record_stripped_profile(&mut self, path: PathBuf, description: String) {
+        self.stripped_profiles += 1;
+        self.stripped_profile_files.push((path, description));
+    }
+
+    pub fn finish(&mut self, elapsed: Duration, error: Option<&anyhow::Error>) {
+        self.elapsed_seconds = elapsed.as_secs_f64();
+
+        match error {
+            Some(error) => {
+                self.status = Status::Failed;
+                self.error = Some(format!("{error:#}"));
+            },
+            None => self.status = Status::Completed,
+        }
+    }
+}
+
+#[inline]
+pub fn is_permission_denied(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| match cause.downcast_ref::<io::Error>() {
+        Some(error) => error.kind() == io::ErrorKind::PermissionDenied,
+        None => false,
+    })
+}
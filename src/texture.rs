@@ -0,0 +1,396 @@
+use anyhow::anyhow;
+use image_convert::magick_rust::MagickWand;
+use intel_tex_2::{bc1, bc3, bc7, RgbaSurface};
+use ktx2::{dfd, Format, Header, Index, LevelIndex, Reader};
+
+use crate::cli::DdsCompression;
+
+// the flags of a DDS header, see
+// https://learn.microsoft.com/en-us/windows/win32/direct3ddds/dds-header
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+const DDSD_LINEARSIZE: u32 = 0x8_0000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+
+// BC7 is only described by the DX10 extension of the header
+const DXGI_FORMAT_BC7_UNORM: u32 = 98;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+// intel_tex_2 links its prebuilt C++ archive statically, without the C++ runtime which it needs
+#[cfg(all(unix, not(target_vendor = "apple")))]
+#[link(name = "stdc++")]
+extern "C" {}
+
+#[cfg(target_vendor = "apple")]
+#[link(name = "c++")]
+extern "C" {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Container {
+    Dds,
+    Ktx2,
+}
+
+// the mip levels of a texture, from the full size down to 1x1
+struct Texture {
+    width:       u32,
+    height:      u32,
+    compression: DdsCompression,
+    levels:      Vec<Vec<u8>>,
+}
+
+// an uncompressed mip level, in RGBA with 8 bits per channel
+struct Level {
+    width:  u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+pub fn encode(
+    mw: &MagickWand,
+    compression: Option<DdsCompression>,
+    container: Container,
+) -> anyhow::Result<Vec<u8>> {
+    let alpha = mw.get_image_alpha_channel();
+
+    let compression =
+        compression.unwrap_or(if alpha { DdsCompression::Bc3 } else { DdsCompression::Bc1 });
+
+    let (width, height) = (mw.get_image_width(), mw.get_image_height());
+
+    let pixels = mw
+        .export_image_pixels(0, 0, width, height, "RGBA")
+        .ok_or_else(|| anyhow!("The pixels of the image cannot be exported."))?;
+
+    let mut level = Level {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    };
+
+    let mut texture = Texture {
+        width: level.width,
+        height: level.height,
+        compression,
+        levels: Vec::new(),
+    };
+
+    loop {
+        texture.levels.push(compress(&level, compression, alpha));
+
+        if level.width == 1 && level.height == 1 {
+            break;
+        }
+
+        level = halve(&level);
+    }
+
+    match container {
+        Container::Dds => Ok(write_dds(&texture)),
+        Container::Ktx2 => write_ktx2(&texture),
+    }
+}
+
+// the largest level of a KTX2 texture, wrapped in a DDS texture which can be decoded by ImageMagick
+pub fn ktx2_to_dds(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let reader = Reader::new(data).map_err(|error| anyhow!("The KTX2 file is broken: {error}."))?;
+
+    let header = reader.header();
+
+    if let Some(scheme) = header.supercompression_scheme {
+        return Err(anyhow!("The KTX2 supercompression {scheme:?} is not supported."));
+    }
+
+    let compression = match header.format {
+        Some(Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB) => DdsCompression::Uncompressed,
+        Some(
+            Format::BC1_RGB_UNORM_BLOCK
+            | Format::BC1_RGB_SRGB_BLOCK
+            | Format::BC1_RGBA_UNORM_BLOCK
+            | Format::BC1_RGBA_SRGB_BLOCK,
+        ) => DdsCompression::Bc1,
+        Some(Format::BC3_UNORM_BLOCK | Format::BC3_SRGB_BLOCK) => DdsCompression::Bc3,
+        Some(Format::BC7_UNORM_BLOCK | Format::BC7_SRGB_BLOCK) => DdsCompression::Bc7,
+        format => return Err(anyhow!("The KTX2 format {format:?} is not supported.")),
+    };
+
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+
+    // only the first layer or face of a texture array or cube map is taken
+    let length = level_length(width, height, compression);
+
+    let level = reader
+        .levels()
+        .next()
+        .and_then(|level| level.data.get(..length))
+        .ok_or_else(|| anyhow!("The KTX2 file is broken."))?;
+
+    Ok(write_dds(&Texture {
+        width,
+        height,
+        compression,
+        levels: vec![level.to_vec()],
+    }))
+}
+
+// the bytes of each 4x4 block, or of each pixel if the texture is uncompressed
+#[inline]
+fn block_length(compression: DdsCompression) -> usize {
+    match compression {
+        DdsCompression::Uncompressed => 4,
+        DdsCompression::Bc1 => 8,
+        DdsCompression::Bc3 | DdsCompression::Bc7 => 16,
+    }
+}
+
+// the size rounded up to whole 4x4 blocks
+#[inline]
+fn pad_to_blocks(size: u32) -> u32 {
+    (size + 3) & !3
+}
+
+fn level_length(width: u32, height: u32, compression: DdsCompression) -> usize {
+    match compression {
+        DdsCompression::Uncompressed => width as usize * height as usize * 4,
+        _ => {
+            let blocks = pad_to_blocks(width) as usize * pad_to_blocks(height) as usize / 16;
+
+            blocks * block_length(compression)
+        },
+    }
+}
+
+fn compress(level: &Level, compression: DdsCompression, alpha: bool) -> Vec<u8> {
+    if compression == DdsCompression::Uncompressed {
+        return level.pixels.clone();
+    }
+
+    // the encoders only take whole blocks, so the last row and column are repeated to fill them
+    let width = pad_to_blocks(level.width);
+    let height = pad_to_blocks(level.height);
+
+    let row_length = level.width as usize * 4;
+
+    let mut padded = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for y in 0..level.height as usize {
+        let row = &level.pixels[y * row_length..(y + 1) * row_length];
+
+        padded.extend_from_slice(row);
+
+        for _ in level.width..width {
+            padded.extend_from_slice(&row[row_length - 4..]);
+        }
+    }
+
+    for _ in level.height..height {
+        padded.extend_from_within(padded.len() - width as usize * 4..);
+    }
+
+    let surface = RgbaSurface {
+        data: &padded,
+        width,
+        height,
+        stride: width * 4,
+    };
+
+    match compression {
+        DdsCompression::Bc1 => bc1::compress_blocks(&surface),
+        DdsCompression::Bc3 => bc3::compress_blocks(&surface),
+        DdsCompression::Bc7 => {
+            let settings =
+                if alpha { bc7::alpha_basic_settings() } else { bc7::opaque_basic_settings() };
+
+            bc7::compress_blocks(&settings, &surface)
+        },
+        DdsCompression::Uncompressed => unreachable!(),
+    }
+}
+
+// the next mip level, whose pixels are the averages of 2x2 pixels
+fn halve(level: &Level) -> Level {
+    let width = (level.width / 2).max(1);
+    let height = (level.height / 2).max(1);
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            for channel in 0..4 {
+                let mut sum = 0u32;
+
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(level.width - 1);
+                    let sy = (y * 2 + dy).min(level.height - 1);
+
+                    sum += u32::from(level.pixels[(sy * level.width + sx) as usize * 4 + channel]);
+                }
+
+                pixels.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+
+    Level {
+        width,
+        height,
+        pixels,
+    }
+}
+
+fn write_dds(texture: &Texture) -> Vec<u8> {
+    let mip_map_count = texture.levels.len() as u32;
+
+    let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+    let mut caps = DDSCAPS_TEXTURE;
+
+    if mip_map_count > 1 {
+        flags |= DDSD_MIPMAPCOUNT;
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+
+    // the pixel format: its flags, FourCC, bit count and the R, G, B and A masks
+    let pixel_format: [u32; 7] = match texture.compression {
+        DdsCompression::Uncompressed => {
+            flags |= DDSD_PITCH;
+
+            [DDPF_RGB | DDPF_ALPHAPIXELS, 0, 32, 0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000]
+        },
+        compression => {
+            flags |= DDSD_LINEARSIZE;
+
+            let four_cc = match compression {
+                DdsCompression::Bc1 => b"DXT1",
+                DdsCompression::Bc3 => b"DXT5",
+                _ => b"DX10",
+            };
+
+            [DDPF_FOURCC, u32::from_le_bytes(*four_cc), 0, 0, 0, 0, 0]
+        },
+    };
+
+    let pitch_or_linear_size = match texture.compression {
+        DdsCompression::Uncompressed => texture.width * 4,
+        _ => texture.levels[0].len() as u32,
+    };
+
+    let mut header =
+        vec![124, flags, texture.height, texture.width, pitch_or_linear_size, 0, mip_map_count];
+
+    header.extend_from_slice(&[0; 11]);
+    header.push(32);
+    header.extend_from_slice(&pixel_format);
+    header.extend_from_slice(&[caps, 0, 0, 0, 0]);
+
+    if texture.compression == DdsCompression::Bc7 {
+        header.extend_from_slice(&[
+            DXGI_FORMAT_BC7_UNORM,
+            D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+            0,
+            1,
+            0,
+        ]);
+    }
+
+    let mut data = b"DDS ".to_vec();
+
+    for value in header {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    for level in texture.levels.iter() {
+        data.extend_from_slice(level);
+    }
+
+    data
+}
+
+fn write_ktx2(texture: &Texture) -> anyhow::Result<Vec<u8>> {
+    // the pixels are sRGB, as they are when they are decoded by ImageMagick
+    let format = match texture.compression {
+        DdsCompression::Uncompressed => Format::R8G8B8A8_SRGB,
+        DdsCompression::Bc1 => Format::BC1_RGB_SRGB_BLOCK,
+        DdsCompression::Bc3 => Format::BC3_SRGB_BLOCK,
+        DdsCompression::Bc7 => Format::BC7_SRGB_BLOCK,
+    };
+
+    let (basic, type_size) = dfd::Basic::from_format(format)
+        .map_err(|error| anyhow!("The KTX2 format {format:?} cannot be described: {error}."))?;
+
+    let dfd = dfd::Block::Basic(basic).to_vec();
+
+    let level_count = texture.levels.len();
+
+    let dfd_byte_offset = Header::LENGTH + level_count * LevelIndex::LENGTH;
+    let dfd_byte_length = 4 + dfd.len();
+
+    let mut data = vec![0; dfd_byte_offset];
+
+    data.extend_from_slice(&(dfd_byte_length as u32).to_le_bytes());
+    data.extend_from_slice(&dfd);
+
+    // the levels are stored from the smallest one, each aligned to a block (a power of two)
+    let alignment = block_length(texture.compression);
+
+    let mut level_index = vec![
+        LevelIndex {
+            byte_offset:              0,
+            byte_length:              0,
+            uncompressed_byte_length: 0,
+        };
+        level_count
+    ];
+
+    for (index, level) in texture.levels.iter().enumerate().rev() {
+        data.resize((data.len() + alignment - 1) & !(alignment - 1), 0);
+
+        level_index[index] = LevelIndex {
+            byte_offset:              data.len() as u64,
+            byte_length:              level.len() as u64,
+            uncompressed_byte_length: level.len() as u64,
+        };
+
+        data.extend_from_slice(level);
+    }
+
+    let header = Header {
+        format: Some(format),
+        type_size,
+        pixel_width: texture.width,
+        pixel_height: texture.height,
+        pixel_depth: 0,
+        layer_count: 0,
+        face_count: 1,
+        level_count: level_count as u32,
+        supercompression_scheme: None,
+        index: Index {
+            dfd_byte_offset: dfd_byte_offset as u32,
+            dfd_byte_length: dfd_byte_length as u32,
+            kvd_byte_offset: 0,
+            kvd_byte_length: 0,
+            sgd_byte_offset: 0,
+            sgd_byte_length: 0,
+        },
+    };
+
+    data[..Header::LENGTH].copy_from_slice(&header.as_bytes());
+
+    for (index, level_index) in level_index.iter().enumerate() {
+        let offset = Header::LENGTH + index * LevelIndex::LENGTH;
+
+        data[offset..offset + LevelIndex::LENGTH].copy_from_slice(&level_index.as_bytes());
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,149 @@
+#![cfg(feature = "magick")]
+
+use std::{env, fs, path::Path};
+
+use image_convert::{magick_rust::MagickWand, ImageResource};
+use image_resizer::Resizer;
+
+// the outputs are compared with the ones in tests/data/golden, which depend on the ImageMagick
+// build, so they are only written when UPDATE_GOLDEN=1 is set
+fn assert_golden(name: &str, output: &[u8]) {
+    let path = Path::new("tests/data/golden").join(name);
+
+    if env::var_os("UPDATE_GOLDEN").is_some_and(|update| update == "1") {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, output).unwrap();
+
+        return;
+    }
+
+    let golden = match fs::read(&path) {
+        Ok(golden) => golden,
+        Err(error) => {
+            panic!(
+                "{path:?} cannot be read ({error}). Run the tests with UPDATE_GOLDEN=1 to write \
+                 it."
+            )
+        },
+    };
+
+    assert!(golden == output, "the output differs from {path:?}");
+}
+
+// the format, and the size of every frame
+fn identify(output: &[u8]) -> (String, Vec<(usize, usize)>) {
+    let format =
+        image_convert::identify_ping(&ImageResource::Data(output.to_vec())).unwrap().format;
+
+    let mw = MagickWand::new();
+
+    mw.read_image_blob(output).unwrap();
+
+    let mut sizes = Vec::new();
+
+    mw.set_first_iterator();
+
+    loop {
+        sizes.push((mw.get_image_width(), mw.get_image_height()));
+
+        if !mw.next_image() {
+            break;
+        }
+    }
+
+    (format, sizes)
+}
+
+// whether the first frame of a JPEG image is progressive, from its SOF marker
+fn is_progressive_jpeg(output: &[u8]) -> bool {
+    let mut i = 2;
+
+    loop {
+        match output[i + 1] {
+            0xC0 | 0xC1 => return false,
+            0xC2 => return true,
+            _ => i += 2 + usize::from(u16::from_be_bytes([output[i + 2], output[i + 3]])),
+        }
+    }
+}
+
+// the interlace method in the IHDR chunk, which is always the first one
+fn is_interlaced_png(output: &[u8]) -> bool {
+    output[28] == 1
+}
+
+fn resize(options: &[&str], input: &str) -> Vec<u8> {
+    let input = fs::read(Path::new("tests/data").join(input)).unwrap();
+
+    Resizer::new(options).unwrap().resize(&input).unwrap()
+}
+
+#[test]
+fn resize_is_deterministic() {
+    let input = fs::read("tests/data/input.png").unwrap();
+
+    let resizer = Resizer::new(["-m", "32", "-q", "85"]).unwrap();
+
+    let first = resizer.resize(&input).unwrap();
+    let second = resizer.resize(&input).unwrap();
+
+    assert!(!first.is_empty());
+    assert_eq!(first, second);
+}
+
+#[test]
+fn png() {
+    let output = resize(&["-m", "32"], "input.png");
+
+    assert_eq!((String::from("PNG"), vec![(32, 24)]), identify(&output));
+
+    assert_golden("png.png", &output);
+}
+
+#[test]
+fn jpeg() {
+    let output = resize(&["-m", "32", "-q", "85"], "input.jpg");
+
+    assert_eq!((String::from("JPEG"), vec![(32, 24)]), identify(&output));
+
+    assert_golden("jpeg.jpg", &output);
+}
+
+#[test]
+fn animated_gif() {
+    let output = resize(&["-m", "16", "--allow-gif"], "animated.gif");
+
+    assert_eq!((String::from("GIF"), vec![(16, 12); 3]), identify(&output));
+
+    assert_golden("animated.gif", &output);
+}
+
+#[test]
+fn ico() {
+    // the largest frame is resized
+    let output = resize(&["-m", "24"], "input.ico");
+
+    assert_eq!((String::from("ICO"), vec![(24, 24)]), identify(&output));
+
+    assert_golden("ico.ico", &output);
+
+    // --ico-frame chooses the 16x16 one
+    let output = resize(&["-m", "8", "--ico-frame", "0"], "input.ico");
+
+    assert_eq!((String::from("ICO"), vec![(8, 8)]), identify(&output));
+
+    assert_golden("ico_frame.ico", &output);
+}
+
+#[test]
+fn interlace() {
+    // image-convert interlaces the still outputs by line
+    assert!(is_progressive_jpeg(&resize(&["-m", "32"], "input.jpg")));
+    assert!(is_interlaced_png(&resize(&["-m", "32"], "input.png")));
+
+    assert!(!is_progressive_jpeg(&resize(&["-m", "32", "--interlace", "none"], "input.jpg")));
+    assert!(!is_interlaced_png(&resize(&["-m", "32", "--interlace", "none"], "input.png")));
+
+    assert!(is_progressive_jpeg(&resize(&["-m", "32", "--interlace", "plane"], "input.jpg")));
+    assert!(is_interlaced_png(&resize(&["-m", "32", "--progressive"], "input.png")));
+}